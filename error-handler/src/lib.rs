@@ -1,24 +1,64 @@
 use std::fmt::Display;
 use std::num::ParseIntError;
 
+use serde::Serialize;
 use tracing::instrument;
 use warp::body::BodyDeserializeError;
 use warp::cors::CorsForbidden;
 use warp::reject::Reject;
+use warp::http::header::{HeaderName, HeaderValue, ETAG, RETRY_AFTER};
+use warp::reply::Response;
 use warp::{http::StatusCode, reply, Rejection, Reply};
 
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+  pub field: String,
+  pub message: String,
+}
+
 #[derive(Debug)]
 pub enum AppError {
   ParseError(ParseIntError),
   MissingParams,
   InvalidRange,
   QuestionNotFound,
+  AnswerNotFound,
+  AccountNotFound,
+  CommentNotFound,
+  TagNotFound,
+  RevisionNotFound,
   InconsistenceId,
   DbError,
   DbQueryError,
   ApiCallErr(String),
   InvalidCredential,
-  InvalidToken
+  InvalidToken,
+  Forbidden,
+  EmailNotVerified,
+  AccountSuspended,
+  OwnPost,
+  PasswordResetRequired,
+  QuestionClosed,
+  QuestionLocked,
+  InsufficientReputation {
+    required: i32,
+  },
+  Validation(Vec<FieldError>),
+  LoginLocked {
+    retry_after: u64,
+  },
+  /// `If-Match` named another version than the stored one
+  PreconditionFailed {
+    current: i32,
+  },
+  RateLimited {
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the limit is fully restored
+    reset: u64,
+    /// Seconds until the next request is allowed
+    retry_after: u64,
+  },
 }
 
 impl Display for AppError {
@@ -28,12 +68,39 @@ impl Display for AppError {
       AppError::MissingParams => write!(f, "Missing required param"),
       AppError::InvalidRange => write!(f, "Invalid range"),
       AppError::QuestionNotFound => write!(f, "Question not found"),
+      AppError::AnswerNotFound => write!(f, "Answer not found"),
+      AppError::AccountNotFound => write!(f, "Account not found"),
+      AppError::CommentNotFound => write!(f, "Comment not found"),
+      AppError::TagNotFound => write!(f, "Tag not found"),
+      AppError::RevisionNotFound => write!(f, "Revision not found"),
       AppError::InconsistenceId => write!(f, "Question ID mismatched"),
       AppError::DbError => write!(f, "DB error"),
       AppError::DbQueryError => write!(f, "DB access failed"),
       AppError::ApiCallErr(reason) => write!(f, "External api call got error {}", reason),
       AppError::InvalidCredential => write!(f, "Invalid login credentials"),
-      AppError::InvalidToken => write!(f, "Unauthorized token")
+      AppError::InvalidToken => write!(f, "Unauthorized token"),
+      AppError::Forbidden => write!(f, "Not allowed"),
+      AppError::EmailNotVerified => write!(f, "Email address is not verified yet"),
+      AppError::AccountSuspended => write!(f, "Account is suspended"),
+      AppError::OwnPost => write!(f, "Cannot vote on your own post"),
+      AppError::PasswordResetRequired => write!(f, "Password must be reset, a link was sent by mail"),
+      AppError::QuestionClosed => write!(f, "Question is closed"),
+      AppError::QuestionLocked => write!(f, "Question is locked"),
+      AppError::InsufficientReputation { required } => write!(f, "Requires {} reputation", required),
+      AppError::Validation(errors) => write!(f, "Invalid fields: {}", errors
+        .iter()
+        .map(|e| format!("{} {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join(", ")),
+      AppError::LoginLocked { retry_after } => {
+        write!(f, "Too many failed logins, retry in {}s", retry_after)
+      }
+      AppError::PreconditionFailed { current } => {
+        write!(f, "Modified meanwhile, current version is {}", current)
+      }
+      AppError::RateLimited { retry_after, .. } => {
+        write!(f, "Too many requests, retry in {}s", retry_after)
+      }
     }
   }
 }
@@ -45,47 +112,108 @@ impl Reject for AppError {}
  * The default error is 406
  */
 #[instrument]
-pub async fn error_hanling(r: Rejection) -> Result<impl Reply, Rejection> {
+pub async fn error_hanling(r: Rejection) -> Result<Response, Rejection> {
   println!("{:?}", r);
 
+  if let Some(e @ AppError::RateLimited { limit, remaining, reset, retry_after }) = r.find() {
+    let mut res = reply::with_status(e.to_string(), StatusCode::TOO_MANY_REQUESTS).into_response();
+    let headers = res.headers_mut();
+    headers.insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+    headers.insert(HeaderName::from_static("ratelimit-limit"), HeaderValue::from(*limit));
+    headers.insert(HeaderName::from_static("ratelimit-remaining"), HeaderValue::from(*remaining));
+    headers.insert(HeaderName::from_static("ratelimit-reset"), HeaderValue::from(*reset));
+    return Ok(res);
+  }
+
+  if let Some(e @ AppError::LoginLocked { retry_after }) = r.find() {
+    let mut res = reply::with_status(e.to_string(), StatusCode::TOO_MANY_REQUESTS).into_response();
+    res.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+    return Ok(res);
+  }
+
+  if let Some(e @ AppError::PreconditionFailed { current }) = r.find() {
+    let mut res = reply::with_status(e.to_string(), StatusCode::PRECONDITION_FAILED).into_response();
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}\"", current)) {
+      res.headers_mut().insert(ETAG, etag);
+    }
+    return Ok(res);
+  }
+
+  if let Some(AppError::Validation(errors)) = r.find() {
+    return Ok(reply::with_status(
+      reply::json(&serde_json::json!({ "errors": errors })),
+      StatusCode::UNPROCESSABLE_ENTITY,
+    ).into_response());
+  }
+
+  if let Some(
+    e @ (AppError::QuestionNotFound
+    | AppError::AnswerNotFound
+    | AppError::AccountNotFound
+    | AppError::CommentNotFound
+    | AppError::TagNotFound
+    | AppError::RevisionNotFound),
+  ) = r.find()
+  {
+    return Ok(reply::with_status(e.to_string(), StatusCode::NOT_FOUND).into_response());
+  }
+
+  if let Some(e @ AppError::InvalidCredential) = r.find() {
+    return Ok(reply::with_status(e.to_string(), StatusCode::UNAUTHORIZED).into_response());
+  }
+
+  if let Some(
+    e @ (AppError::Forbidden
+    | AppError::EmailNotVerified
+    | AppError::AccountSuspended
+    | AppError::OwnPost
+    | AppError::PasswordResetRequired
+    | AppError::QuestionClosed
+    | AppError::QuestionLocked
+    | AppError::InsufficientReputation { .. }),
+  ) = r.find()
+  {
+    return Ok(reply::with_status(e.to_string(), StatusCode::FORBIDDEN).into_response());
+  }
+
   if let Some(AppError::InvalidToken) = r.find() {
     return Ok(reply::with_status(
       AppError::InvalidToken.to_string(),
       StatusCode::UNAUTHORIZED,
-    ));
+    ).into_response());
   }
 
   if let Some(AppError::DbQueryError) = r.find() {
     return Ok(reply::with_status(
       AppError::DbQueryError.to_string(),
       StatusCode::INTERNAL_SERVER_ERROR,
-    ));
+    ).into_response());
   }
 
   if let Some(AppError::ApiCallErr(str)) = r.find() {
     return Ok(reply::with_status(
       str.to_string(),
       StatusCode::INTERNAL_SERVER_ERROR,
-    ));
+    ).into_response());
   }
 
   if let Some(e) = r.find::<BodyDeserializeError>() {
     return Ok(reply::with_status(
       e.to_string(),
       StatusCode::UNPROCESSABLE_ENTITY,
-    ));
+    ).into_response());
   }
   if let Some(e) = r.find::<AppError>() {
     return Ok(reply::with_status(
       e.to_string(),
       StatusCode::RANGE_NOT_SATISFIABLE,
-    ));
+    ).into_response());
   }
   if let Some(e) = r.find::<CorsForbidden>() {
-    return Ok(reply::with_status(e.to_string(), StatusCode::FORBIDDEN));
+    return Ok(reply::with_status(e.to_string(), StatusCode::FORBIDDEN).into_response());
   }
   Ok(reply::with_status(
     "Resource not found".to_string(),
     StatusCode::NOT_ACCEPTABLE,
-  ))
+  ).into_response())
 }
@@ -0,0 +1,6 @@
+-- Add up migration script here
+create table if not exists rate_limit_buckets (
+  key varchar(255) primary key,
+  tokens double precision not null,
+  updated_at timestamptz not null default now()
+);
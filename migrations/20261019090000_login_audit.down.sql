@@ -0,0 +1,4 @@
+-- Add down migration script here
+drop table if exists audit_log;
+drop table if exists login_throttle;
+alter table account drop column role;
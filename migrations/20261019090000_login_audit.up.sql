@@ -0,0 +1,25 @@
+-- Add up migration script here
+alter table account
+  add column role varchar(32) not null default 'user';
+
+-- Failure streaks are keyed by email so unknown emails get locked out exactly like real ones
+create table if not exists login_throttle (
+  email varchar(255) primary key,
+  failures integer not null default 0,
+  last_failure_at timestamptz not null default now(),
+  locked_until timestamptz
+);
+
+create table if not exists audit_log (
+  id serial primary key,
+  action varchar(64) not null,
+  account_id integer references account on delete set null,
+  email varchar(255),
+  ip varchar(64),
+  success boolean not null,
+  detail text,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists audit_log_account_idx on audit_log (account_id, created_at);
+create index if not exists audit_log_ip_idx on audit_log (ip, action, created_at);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop table if exists account_token;
+alter table account drop column email_verified_at;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+alter table account
+  add column email_verified_at timestamptz;
+
+-- Accounts created before verification existed stay usable
+update account set email_verified_at = created_at;
+
+-- Single-use tokens mailed to the account owner, only their sha-256 is stored
+create table if not exists account_token (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  purpose varchar(32) not null,
+  token_hash char(64) unique not null,
+  expires_at timestamptz not null,
+  used_at timestamptz,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists account_token_account_idx on account_token (account_id, purpose);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+drop table if exists recovery_code;
+alter table account
+  drop column totp_secret,
+  drop column totp_pending_secret,
+  drop column totp_enabled_at,
+  drop column totp_last_step;
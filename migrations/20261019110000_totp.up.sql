@@ -0,0 +1,17 @@
+-- Add up migration script here
+alter table account
+  add column totp_secret varchar(64),
+  -- secret of an enrollment waiting for its first code
+  add column totp_pending_secret varchar(64),
+  add column totp_enabled_at timestamptz,
+  -- time step of the last accepted code, a code is never accepted twice
+  add column totp_last_step bigint;
+
+create table if not exists recovery_code (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  code_hash char(64) not null,
+  used_at timestamptz
+);
+
+create index if not exists recovery_code_account_idx on recovery_code (account_id);
@@ -0,0 +1,15 @@
+-- Add up migration script here
+create table if not exists api_key (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  name varchar(100) not null,
+  -- start of the key, shown in listings so users can tell their keys apart
+  prefix varchar(16) not null,
+  key_hash char(64) unique not null,
+  scopes text[] not null,
+  created_at timestamptz not null default now(),
+  last_used_at timestamptz,
+  revoked_at timestamptz
+);
+
+create index if not exists api_key_account_idx on api_key (account_id);
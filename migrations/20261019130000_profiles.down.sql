@@ -0,0 +1,10 @@
+-- Add down migration script here
+drop index if exists questions_account_idx;
+drop index if exists answers_account_idx;
+alter table questions drop constraint if exists questions_account_id_fkey;
+alter table answers drop constraint if exists answers_account_id_fkey;
+alter table account
+  drop column display_name,
+  drop column bio,
+  drop column avatar_url,
+  drop column pending_email;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+alter table account
+  add column display_name varchar(64),
+  add column bio text,
+  add column avatar_url varchar(512),
+  -- new address of a change waiting for its confirmation link
+  add column pending_email varchar(255);
+
+-- account_id was added as serial, every insert drew a made-up id from a sequence
+alter table questions alter column account_id drop default, alter column account_id drop not null;
+alter table answers alter column account_id drop default, alter column account_id drop not null;
+drop sequence if exists questions_account_id_seq;
+drop sequence if exists answers_account_id_seq;
+
+update questions set account_id = null where account_id not in (select id from account);
+update answers set account_id = null where account_id not in (select id from account);
+
+alter table questions
+  add constraint questions_account_id_fkey foreign key (account_id) references account on delete set null;
+alter table answers
+  add constraint answers_account_id_fkey foreign key (account_id) references account on delete set null;
+
+create index if not exists questions_account_idx on questions (account_id);
+create index if not exists answers_account_idx on answers (account_id);
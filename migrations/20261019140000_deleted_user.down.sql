@@ -0,0 +1,5 @@
+-- Add down migration script here
+delete from account where placeholder;
+
+alter table account
+  drop column placeholder;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+-- Placeholder author of the content of erased accounts. Its password is not
+-- a valid hash, nobody can log in with it. It is found by the flag rather than
+-- its email, and the migration fails instead of adopting a real account that
+-- already uses the email.
+alter table account
+  add column placeholder boolean not null default false;
+
+create unique index account_placeholder_idx on account (placeholder) where placeholder;
+
+do $$
+begin
+  if exists (select 1 from account where lower(email) = 'deleted-user@invalid') then
+    raise exception 'deleted-user@invalid is taken by account %, rename it before migrating',
+      (select id from account where lower(email) = 'deleted-user@invalid');
+  end if;
+end $$;
+
+insert into account (email, password, display_name, placeholder)
+values ('deleted-user@invalid', '!', 'deleted user', true);
@@ -0,0 +1,8 @@
+-- Add down migration script here
+alter table audit_log drop column if exists actor_id;
+
+alter table account
+  drop column if exists suspended_at,
+  drop column if exists suspended_reason,
+  drop column if exists password_reset_required,
+  drop column if exists sessions_valid_after;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+alter table account
+  add column suspended_at timestamptz,
+  add column suspended_reason text,
+  -- set by an admin, login is refused until the password is reset
+  add column password_reset_required boolean not null default false,
+  -- login tokens issued before this are refused
+  add column sessions_valid_after timestamptz;
+
+-- admin who acted on the account of the entry, if not the account itself
+alter table audit_log add column actor_id integer references account on delete set null;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+drop trigger if exists votes_score on votes;
+drop function if exists votes_update_score();
+drop table if exists votes;
+alter table questions drop column if exists score;
+alter table answers drop column if exists score;
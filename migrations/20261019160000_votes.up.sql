@@ -0,0 +1,37 @@
+-- Add up migration script here
+create table if not exists votes (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  question_id integer references questions on delete cascade,
+  answer_id integer references answers on delete cascade,
+  value smallint not null check (value in (-1, 1)),
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  -- a vote is on exactly one question or answer
+  check (num_nonnulls(question_id, answer_id) = 1)
+);
+
+create unique index if not exists votes_question_account_idx on votes (question_id, account_id) where question_id is not null;
+create unique index if not exists votes_answer_account_idx on votes (answer_id, account_id) where answer_id is not null;
+create index if not exists votes_account_idx on votes (account_id);
+
+alter table questions add column score integer not null default 0;
+alter table answers add column score integer not null default 0;
+
+-- Scores follow every change of votes, including the ones cascading from deleted accounts
+create or replace function votes_update_score() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') then
+    update questions set score = score - old.value where id = old.question_id;
+    update answers set score = score - old.value where id = old.answer_id;
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') then
+    update questions set score = score + new.value where id = new.question_id;
+    update answers set score = score + new.value where id = new.answer_id;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger votes_score after insert or update or delete on votes
+  for each row execute function votes_update_score();
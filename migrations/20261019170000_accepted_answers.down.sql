@@ -0,0 +1,2 @@
+-- Add down migration script here
+alter table questions drop column if exists accepted_answer_id;
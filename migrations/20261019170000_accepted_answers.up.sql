@@ -0,0 +1,4 @@
+-- Add up migration script here
+alter table questions add column accepted_answer_id integer references answers on delete set null;
+
+create index if not exists questions_accepted_answer_idx on questions (accepted_answer_id);
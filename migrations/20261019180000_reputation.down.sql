@@ -0,0 +1,6 @@
+-- Add down migration script here
+drop table if exists flags;
+drop trigger if exists reputation_events_reputation on reputation_events;
+drop function if exists reputation_events_apply();
+drop table if exists reputation_events;
+alter table account drop column if exists reputation;
@@ -0,0 +1,46 @@
+-- Add up migration script here
+alter table account add column reputation integer not null default 0;
+
+-- Ledger of what earned or cost an account reputation, a row goes away with the vote or accept it stems from
+create table if not exists reputation_events (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  kind text not null,
+  points integer not null,
+  vote_id integer references votes on delete cascade,
+  question_id integer references questions on delete cascade,
+  answer_id integer references answers on delete cascade,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists reputation_events_account_idx on reputation_events (account_id, id);
+create unique index if not exists reputation_events_vote_idx on reputation_events (vote_id) where vote_id is not null;
+create index if not exists reputation_events_question_idx on reputation_events (question_id);
+
+create or replace function reputation_events_apply() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') then
+    update account set reputation = reputation - old.points where id = old.account_id;
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') then
+    update account set reputation = reputation + new.points where id = new.account_id;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger reputation_events_reputation after insert or update or delete on reputation_events
+  for each row execute function reputation_events_apply();
+
+create table if not exists flags (
+  id serial primary key,
+  account_id integer not null references account on delete cascade,
+  question_id integer references questions on delete cascade,
+  answer_id integer references answers on delete cascade,
+  reason text not null,
+  created_at timestamptz not null default now(),
+  check (num_nonnulls(question_id, answer_id) = 1)
+);
+
+create unique index if not exists flags_question_account_idx on flags (question_id, account_id) where question_id is not null;
+create unique index if not exists flags_answer_account_idx on flags (answer_id, account_id) where answer_id is not null;
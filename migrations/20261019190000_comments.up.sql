@@ -0,0 +1,15 @@
+-- Add up migration script here
+create table if not exists comments (
+  id serial primary key,
+  account_id integer references account,
+  question_id integer references questions on delete cascade,
+  answer_id integer references answers on delete cascade,
+  content text not null,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz,
+  check (num_nonnulls(question_id, answer_id) = 1)
+);
+
+create index if not exists comments_question_idx on comments (question_id, id) where question_id is not null;
+create index if not exists comments_answer_idx on comments (answer_id, id) where answer_id is not null;
+create index if not exists comments_account_idx on comments (account_id);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+drop trigger if exists questions_tag_usage on questions;
+drop function if exists questions_tag_usage();
+drop table if exists tag_synonyms;
+drop table if exists tags;
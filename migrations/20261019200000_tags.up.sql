@@ -0,0 +1,53 @@
+-- Add up migration script here
+create table if not exists tags (
+  id serial primary key,
+  slug text not null unique,
+  description text,
+  usage_count integer not null default 0,
+  created_at timestamptz not null default now()
+);
+
+-- Slugs which are rewritten to their tag when a question is tagged
+create table if not exists tag_synonyms (
+  slug text primary key,
+  tag_id integer not null references tags on delete cascade,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists tag_synonyms_tag_idx on tag_synonyms (tag_id);
+
+-- Same rules as types::tag::slugify, duplicates are dropped keeping the first
+update questions set tags = (
+  select coalesce(array_agg(slug order by ord), '{}')
+  from (
+    select slug, min(ord) as ord
+    from unnest(questions.tags) with ordinality as u(raw, ord),
+      lateral (
+        select trim(both '-' from regexp_replace(regexp_replace(regexp_replace(
+          replace(lower(trim(raw)), '#', 'sharp'), '[[:space:]_-]+', '-', 'g'), '[^a-z0-9+.-]', '', 'g'), '-+', '-', 'g')) as slug
+      ) s
+    where slug <> ''
+    group by slug
+  ) d
+)
+where tags is not null;
+
+insert into tags (slug, usage_count)
+  select slug, count(*) from questions, unnest(questions.tags) as slug group by slug
+  on conflict (slug) do nothing;
+
+create or replace function questions_tag_usage() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') and old.tags is not null then
+    update tags set usage_count = usage_count - 1 where slug = any(old.tags);
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') and new.tags is not null then
+    insert into tags (slug) select distinct unnest(new.tags) on conflict (slug) do nothing;
+    update tags set usage_count = usage_count + 1 where slug = any(new.tags);
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger questions_tag_usage after insert or delete or update of tags on questions
+  for each row execute function questions_tag_usage();
@@ -0,0 +1,21 @@
+-- Add up migration script here
+create table if not exists question_revisions (
+  id serial primary key,
+  question_id integer not null references questions on delete cascade,
+  revision integer not null,
+  account_id integer references account,
+  -- create | edit | retag | rollback
+  action text not null,
+  rolled_back_to integer,
+  title text not null,
+  content text not null,
+  tags text[],
+  created_at timestamptz not null default now(),
+  unique (question_id, revision)
+);
+
+create index if not exists question_revisions_account_idx on question_revisions (account_id);
+
+-- what the questions look like now is all that is known of their past
+insert into question_revisions (question_id, revision, account_id, action, title, content, tags)
+  select id, 1, account_id, 'create', title, content, tags from questions;
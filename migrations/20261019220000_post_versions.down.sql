@@ -0,0 +1,6 @@
+-- Add down migration script here
+drop trigger if exists answers_version on answers;
+drop trigger if exists questions_version on questions;
+drop function if exists posts_bump_version();
+alter table answers drop column version;
+alter table questions drop column version;
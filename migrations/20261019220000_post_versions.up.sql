@@ -0,0 +1,18 @@
+-- Add up migration script here
+alter table questions add column version integer not null default 1;
+alter table answers add column version integer not null default 1;
+
+-- Every write bumps the version, except the score kept up to date by votes
+create or replace function posts_bump_version() returns trigger as $$
+begin
+  if to_jsonb(new) - 'score' - 'version' is distinct from to_jsonb(old) - 'score' - 'version' then
+    new.version := old.version + 1;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger questions_version before update on questions
+  for each row execute function posts_bump_version();
+create trigger answers_version before update on answers
+  for each row execute function posts_bump_version();
@@ -0,0 +1,42 @@
+-- Add down migration script here
+-- void rows counted for nothing, they go while the trigger still knows that
+delete from reputation_events where void;
+
+create or replace function reputation_events_apply() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') then
+    update account set reputation = reputation - old.points where id = old.account_id;
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') then
+    update account set reputation = reputation + new.points where id = new.account_id;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+alter table reputation_events drop column void;
+
+create or replace function questions_tag_usage() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') and old.tags is not null then
+    update tags set usage_count = usage_count - 1 where slug = any(old.tags);
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') and new.tags is not null then
+    insert into tags (slug) select distinct unnest(new.tags) on conflict (slug) do nothing;
+    update tags set usage_count = usage_count + 1 where slug = any(new.tags);
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+drop trigger questions_tag_usage on questions;
+create trigger questions_tag_usage after insert or delete or update of tags on questions
+  for each row execute function questions_tag_usage();
+
+-- the usage of deleted questions was not counted
+update tags set usage_count = (
+  select count(*) from questions where tags.slug = any(questions.tags)
+);
+
+alter table answers drop column deleted_by, drop column deleted_at;
+alter table questions drop column deleted_by, drop column deleted_at;
@@ -0,0 +1,45 @@
+-- Add up migration script here
+alter table questions
+  add column deleted_at timestamptz,
+  add column deleted_by integer references account on delete set null;
+alter table answers
+  add column deleted_at timestamptz,
+  add column deleted_by integer references account on delete set null;
+
+-- for the purge job
+create index if not exists questions_deleted_at_idx on questions (deleted_at) where deleted_at is not null;
+create index if not exists answers_deleted_at_idx on answers (deleted_at) where deleted_at is not null;
+
+-- deleted questions no longer count towards the usage of their tags
+create or replace function questions_tag_usage() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') and old.tags is not null and old.deleted_at is null then
+    update tags set usage_count = usage_count - 1 where slug = any(old.tags);
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') and new.tags is not null and new.deleted_at is null then
+    insert into tags (slug) select distinct unnest(new.tags) on conflict (slug) do nothing;
+    update tags set usage_count = usage_count + 1 where slug = any(new.tags);
+  end if;
+  return null;
+end;
+$$ language plpgsql;
+
+drop trigger questions_tag_usage on questions;
+create trigger questions_tag_usage after insert or delete or update of tags, deleted_at on questions
+  for each row execute function questions_tag_usage();
+
+-- Reputation earned on a post is void while it is deleted, so that the purge
+-- cascading to its votes and ledger rows later changes nothing
+alter table reputation_events add column void boolean not null default false;
+
+create or replace function reputation_events_apply() returns trigger as $$
+begin
+  if tg_op in ('UPDATE', 'DELETE') and not old.void then
+    update account set reputation = reputation - old.points where id = old.account_id;
+  end if;
+  if tg_op in ('INSERT', 'UPDATE') and not new.void then
+    update account set reputation = reputation + new.points where id = new.account_id;
+  end if;
+  return null;
+end;
+$$ language plpgsql;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop index if exists questions_title_trgm_idx;
+alter table questions drop column duplicate_of;
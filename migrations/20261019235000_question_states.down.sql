@@ -0,0 +1,3 @@
+-- Add down migration script here
+drop table if exists moderation_log;
+alter table questions drop column close_reason, drop column state;
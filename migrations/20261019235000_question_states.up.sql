@@ -0,0 +1,21 @@
+-- Add up migration script here
+alter table questions
+  add column state text not null default 'open' check (state in ('open', 'closed', 'locked', 'protected')),
+  add column close_reason text check ((state = 'closed') = (close_reason is not null));
+
+update questions set state = 'closed', close_reason = 'duplicate' where duplicate_of is not null;
+
+create index if not exists questions_state_idx on questions (state);
+
+create table if not exists moderation_log
+(
+  id serial primary key,
+  question_id integer not null references questions on delete cascade,
+  actor_id integer references account on delete set null,
+  from_state text not null,
+  to_state text not null,
+  reason text,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists moderation_log_question_idx on moderation_log (question_id);
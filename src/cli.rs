@@ -0,0 +1,123 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use crate::{
+  export,
+  reputation::ReputationConfig,
+  retention::RetentionConfig,
+  routes::privacy::privacy_entry,
+  store::Store,
+  types::audit,
+};
+
+const USAGE: &str = "Usage:
+  helloworld                             start the server
+  helloworld export <account id> [file]  write the data export, JSON when file ends with .json
+  helloworld erase <account id>          erase the account, its content goes to the deleted user
+  helloworld recompute-reputation        rebuild the reputation ledger from the votes and accepted answers
+  helloworld purge-deleted               remove the posts deleted longer ago than the retention period";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+  Export { id: i32, file: PathBuf },
+  Erase { id: i32 },
+  RecomputeReputation,
+  PurgeDeleted,
+}
+
+/// `None` when no command is given and the server should start
+pub fn parse(args: &[String]) -> Result<Option<Command>, String> {
+  let id = |arg: Option<&String>| -> Result<i32, String> {
+    arg
+      .ok_or_else(|| USAGE.to_string())?
+      .parse()
+      .map_err(|_| format!("Invalid account id\n{}", USAGE))
+  };
+
+  match args.first().map(String::as_str) {
+    None => Ok(None),
+    Some("export") if args.len() <= 3 => {
+      let id = id(args.get(1))?;
+      let file = args
+        .get(2)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("account-{}-export.zip", id)));
+      Ok(Some(Command::Export { id, file }))
+    }
+    Some("erase") if args.len() == 2 => Ok(Some(Command::Erase { id: id(args.get(1))? })),
+    Some("recompute-reputation") if args.len() == 1 => Ok(Some(Command::RecomputeReputation)),
+    Some("purge-deleted") if args.len() == 1 => Ok(Some(Command::PurgeDeleted)),
+    Some(_) => Err(USAGE.to_string()),
+  }
+}
+
+pub async fn run(
+  command: Command,
+  db_url: &str,
+  reputation: &ReputationConfig,
+  retention: &RetentionConfig,
+) -> Result<(), Box<dyn Error>> {
+  let store = Store::new(db_url).await;
+
+  match command {
+    Command::Export { id, file } => {
+      let data = store.export_account(id).await?.ok_or("Account not found")?;
+      let bytes = if file.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_vec_pretty(&data)?
+      } else {
+        export::to_zip(&data)?
+      };
+      fs::write(&file, bytes)?;
+      store
+        .add_audit(privacy_entry(audit::DATA_EXPORT, Some(id), "from the command line".to_string()))
+        .await?;
+      println!("Exported account {} to {}", id, file.display());
+    }
+    Command::Erase { id } => {
+      store.erase_account(id).await?.ok_or("Account not found")?;
+      let detail = format!("account {} erased from the command line", id);
+      store.add_audit(privacy_entry(audit::ERASURE, None, detail)).await?;
+      println!("Erased account {}", id);
+    }
+    Command::RecomputeReputation => {
+      let drifted = store.recompute_reputation(reputation).await?;
+      println!("Recomputed reputation, {} accounts had drifted", drifted);
+    }
+    Command::PurgeDeleted => {
+      let (questions, answers) = store.purge_deleted(retention.deleted_post_days).await?;
+      println!("Purged {} questions and {} answers", questions, answers);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod cli_tests {
+  use std::path::PathBuf;
+
+  use super::{parse, Command};
+
+  fn args(s: &str) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(parse(&[]), Ok(None));
+    assert_eq!(
+      parse(&args("export 7")),
+      Ok(Some(Command::Export { id: 7, file: PathBuf::from("account-7-export.zip") }))
+    );
+    assert_eq!(
+      parse(&args("export 7 out.json")),
+      Ok(Some(Command::Export { id: 7, file: PathBuf::from("out.json") }))
+    );
+    assert_eq!(parse(&args("erase 7")), Ok(Some(Command::Erase { id: 7 })));
+    assert_eq!(parse(&args("recompute-reputation")), Ok(Some(Command::RecomputeReputation)));
+    assert!(parse(&args("recompute-reputation 7")).is_err());
+    assert_eq!(parse(&args("purge-deleted")), Ok(Some(Command::PurgeDeleted)));
+    assert!(parse(&args("erase")).is_err());
+    assert!(parse(&args("erase x")).is_err());
+    assert!(parse(&args("erase 7 8")).is_err());
+    assert!(parse(&args("serve")).is_err());
+  }
+}
@@ -0,0 +1,22 @@
+use serde::Deserialize;
+
+/// When `add_q` reports earlier questions as likely duplicates of the new one
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DuplicateConfig {
+  /// pg_trgm similarity of the titles, between 0 and 1
+  pub min_title_similarity: f32,
+  /// Added to the score for every tag the questions share
+  pub tag_weight: f32,
+  pub max_candidates: i64,
+}
+
+impl Default for DuplicateConfig {
+  fn default() -> Self {
+    DuplicateConfig {
+      min_title_similarity: 0.4,
+      tag_weight: 0.1,
+      max_candidates: 5,
+    }
+  }
+}
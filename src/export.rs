@@ -0,0 +1,93 @@
+use std::io::{Cursor, Write};
+
+use serde::Serialize;
+use zip::{result::ZipResult, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::types::export::AccountExport;
+
+/// Packs the export as a ZIP of JSON files, one per kind of record
+pub fn to_zip(export: &AccountExport) -> ZipResult<Vec<u8>> {
+  let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+  let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+  add_json(&mut zip, options, "account.json", &export.account)?;
+  add_json(&mut zip, options, "questions.json", &export.questions)?;
+  add_json(&mut zip, options, "answers.json", &export.answers)?;
+  add_json(&mut zip, options, "revisions.json", &export.revisions)?;
+  add_json(&mut zip, options, "comments.json", &export.comments)?;
+  add_json(&mut zip, options, "votes.json", &export.votes)?;
+  add_json(&mut zip, options, "reputation.json", &export.reputation)?;
+  add_json(&mut zip, options, "flags.json", &export.flags)?;
+  add_json(&mut zip, options, "api_keys.json", &export.api_keys)?;
+  add_json(&mut zip, options, "audit_log.json", &export.audit_log)?;
+  add_json(&mut zip, options, "export.json", &serde_json::json!({ "exported_at": export.exported_at }))?;
+
+  Ok(zip.finish()?.into_inner())
+}
+
+fn add_json<T: Serialize>(
+  zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+  options: SimpleFileOptions,
+  name: &str,
+  value: &T,
+) -> ZipResult<()> {
+  zip.start_file(name, options)?;
+  serde_json::to_writer_pretty(&mut *zip, value).map_err(std::io::Error::from)?;
+  zip.write_all(b"\n")?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod export_tests {
+  use std::io::{Cursor, Read};
+
+  use chrono::Utc;
+
+  use super::to_zip;
+  use crate::types::{
+    account::{Profile, Role},
+    export::AccountExport,
+  };
+
+  #[test]
+  fn test_to_zip() {
+    let export = AccountExport {
+      exported_at: Utc::now(),
+      account: Profile {
+        id: 7,
+        email: "foo@example.com".to_string(),
+        pending_email: None,
+        email_verified: true,
+        display_name: Some("Foo".to_string()),
+        bio: None,
+        avatar_url: None,
+        role: Role::User,
+        totp_enabled: false,
+        reputation: 1,
+        created_at: Utc::now().naive_utc(),
+      },
+      questions: vec![],
+      answers: vec![],
+      revisions: vec![],
+      comments: vec![],
+      votes: vec![],
+      reputation: vec![],
+      flags: vec![],
+      api_keys: vec![],
+      audit_log: vec![],
+    };
+
+    let mut zip = zip::ZipArchive::new(Cursor::new(to_zip(&export).unwrap())).unwrap();
+    assert_eq!(zip.len(), 11);
+
+    let mut account = String::new();
+    zip.by_name("account.json").unwrap().read_to_string(&mut account).unwrap();
+    let account: serde_json::Value = serde_json::from_str(&account).unwrap();
+    assert_eq!(account["email"], "foo@example.com");
+    assert_eq!(account["role"], "user");
+
+    let mut questions = String::new();
+    zip.by_name("questions.json").unwrap().read_to_string(&mut questions).unwrap();
+    assert_eq!(questions.trim(), "[]");
+  }
+}
@@ -0,0 +1,397 @@
+use std::{
+  borrow::Cow,
+  collections::BTreeMap,
+  io,
+  sync::atomic::{AtomicBool, Ordering},
+  time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use tracing::{error, info};
+use tracing_appender::{
+  non_blocking::WorkerGuard,
+  rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+  fmt::{self, format::FmtSpan, MakeWriter},
+  layer::{Layered, SubscriberExt},
+  reload,
+  util::SubscriberInitExt,
+  EnvFilter, Layer, Registry,
+};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+  #[default]
+  Pretty,
+  Json,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+  Minutely,
+  Hourly,
+  #[default]
+  Daily,
+  Never,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FileConfig {
+  pub directory: String,
+  pub prefix: String,
+  pub rotation: LogRotation,
+  /// Number of rotated files to keep, all of them are kept when unset
+  pub max_files: Option<usize>,
+}
+
+impl Default for FileConfig {
+  fn default() -> Self {
+    FileConfig {
+      directory: "logs".to_string(),
+      prefix: format!("{}.log", env!("CARGO_PKG_NAME")),
+      rotation: LogRotation::default(),
+      max_files: None,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RedactConfig {
+  /// Keys whose values are masked, matched case-insensitively in `key=value`,
+  /// `key: value` and json `"key":"value"` forms
+  pub fields: Vec<String>,
+  pub emails: bool,
+}
+
+impl Default for RedactConfig {
+  fn default() -> Self {
+    RedactConfig {
+      fields: ["password", "token", "authorization", "apikey", "secret"]
+        .map(String::from)
+        .to_vec(),
+      emails: true,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+  pub format: LogFormat,
+  /// Level for every target which is not listed in `modules`
+  pub level: String,
+  pub modules: BTreeMap<String, String>,
+  pub stdout: bool,
+  pub file: Option<FileConfig>,
+  pub redact: RedactConfig,
+  /// Whether question, answer and profanity check texts are written to the logs
+  pub user_content: bool,
+  /// How often the config file is checked for level changes, 0 disables hot reload
+  pub reload_interval_secs: u64,
+}
+
+impl Default for LoggingConfig {
+  fn default() -> Self {
+    LoggingConfig {
+      format: LogFormat::default(),
+      level: "info".to_string(),
+      modules: BTreeMap::new(),
+      stdout: true,
+      file: None,
+      redact: RedactConfig::default(),
+      user_content: false,
+      reload_interval_secs: 30,
+    }
+  }
+}
+
+impl LoggingConfig {
+  /// `EnvFilter` directives, e.g. `warn,helloworld=debug,sqlx=info`
+  pub fn directives(&self) -> String {
+    std::iter::once(self.level.clone())
+      .chain(self.modules.iter().map(|(m, l)| format!("{}={}", m, l)))
+      .collect::<Vec<_>>()
+      .join(",")
+  }
+}
+
+static USER_CONTENT: AtomicBool = AtomicBool::new(false);
+
+/// Returns `text` when user content logging is enabled, otherwise only its length.
+pub fn user_content(text: &str) -> Cow<'_, str> {
+  if USER_CONTENT.load(Ordering::Relaxed) {
+    Cow::Borrowed(text)
+  } else {
+    Cow::Owned(format!("<{} chars>", text.chars().count()))
+  }
+}
+
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+pub type BoxedLayer = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// Keeps the writers flushing and lets the level filter be swapped at runtime.
+/// Dropping it loses buffered log lines, so it must live as long as the app.
+pub struct Logging {
+  handle: reload::Handle<EnvFilter, Registry>,
+  directives: String,
+  _guards: Vec<WorkerGuard>,
+}
+
+/// Installs the global subscriber. `extra` layers receive the same filtered
+/// spans and events as the log outputs, e.g. the OpenTelemetry layer.
+pub fn init(conf: &LoggingConfig, extra: Vec<BoxedLayer>) -> Logging {
+  let directives = conf.directives();
+  let filter = EnvFilter::try_new(&directives).unwrap_or_else(|e| {
+    eprintln!("Invalid log directives {:?}: {}", directives, e);
+    EnvFilter::new("info")
+  });
+  let (filter, handle) = reload::Layer::new(filter);
+  USER_CONTENT.store(conf.user_content, Ordering::Relaxed);
+
+  let redactor = Redactor::new(&conf.redact);
+  let mut guards = Vec::new();
+  let mut layers = extra;
+
+  if conf.stdout {
+    let (writer, guard) = tracing_appender::non_blocking(io::stdout());
+    guards.push(guard);
+    layers.push(output_layer(conf.format, redactor.wrap(writer), true));
+  }
+
+  if let Some(file) = &conf.file {
+    let rotation = match file.rotation {
+      LogRotation::Minutely => Rotation::MINUTELY,
+      LogRotation::Hourly => Rotation::HOURLY,
+      LogRotation::Daily => Rotation::DAILY,
+      LogRotation::Never => Rotation::NEVER,
+    };
+    let mut builder = RollingFileAppender::builder()
+      .rotation(rotation)
+      .filename_prefix(&file.prefix);
+    if let Some(max) = file.max_files {
+      builder = builder.max_log_files(max);
+    }
+    let appender = builder
+      .build(&file.directory)
+      .expect("Could not create log file appender");
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    guards.push(guard);
+    layers.push(output_layer(conf.format, redactor.wrap(writer), false));
+  }
+
+  tracing_subscriber::registry().with(filter).with(layers).init();
+
+  Logging {
+    handle,
+    directives,
+    _guards: guards,
+  }
+}
+
+fn output_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+  W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+  let layer = fmt::layer()
+    .with_writer(writer)
+    .with_span_events(FmtSpan::CLOSE);
+
+  match format {
+    LogFormat::Pretty => layer.with_ansi(ansi).boxed(),
+    LogFormat::Json => layer.json().with_current_span(true).with_span_list(false).boxed(),
+  }
+}
+
+impl Logging {
+  /// Polls the config file and applies level and `user_content` changes without a restart.
+  /// Format, outputs and redaction rules are only read at startup.
+  pub fn watch(&self, load: fn() -> Result<LoggingConfig, config::ConfigError>, interval_secs: u64) {
+    if interval_secs == 0 {
+      return;
+    }
+
+    let handle = self.handle.clone();
+    let mut current = self.directives.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+      ticker.tick().await;
+      loop {
+        ticker.tick().await;
+        let directives = match load() {
+          Ok(conf) => {
+            USER_CONTENT.store(conf.user_content, Ordering::Relaxed);
+            conf.directives()
+          }
+          Err(e) => {
+            error!("Failed to reload logging config: {}", e);
+            continue;
+          }
+        };
+        if directives == current {
+          continue;
+        }
+
+        match EnvFilter::try_new(&directives).map(|f| handle.reload(f)) {
+          Ok(Ok(_)) => {
+            info!("Log levels changed to {}", directives);
+            current = directives;
+          }
+          Ok(Err(e)) => error!("Failed to reload log levels: {:?}", e),
+          Err(e) => error!("Ignore invalid log directives {:?}: {}", directives, e),
+        }
+      }
+    });
+  }
+}
+
+static EMAIL: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"[A-Za-z0-9._%+-]+@([A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,})").unwrap());
+
+/// Masks sensitive values in formatted log lines before they reach any output.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+  fields: Option<Regex>,
+  emails: bool,
+}
+
+impl Redactor {
+  pub fn new(conf: &RedactConfig) -> Self {
+    let fields = if conf.fields.is_empty() {
+      None
+    } else {
+      let names = conf
+        .fields
+        .iter()
+        .map(|f| regex::escape(f))
+        .collect::<Vec<_>>()
+        .join("|");
+      // key, optionally quoted (escaped when nested in a json string), then the value:
+      // an escaped-quoted string, a quoted string or a bare word
+      let pattern = format!(
+        r#"(?i)(\\?"?\b(?:{})\b\\?"?\s*[:=]\s*)(\\"(?:[^\\]|\\[^"])*?\\"|"(?:[^"\\]|\\.)*"|[^\s,;}}\]]+)"#,
+        names
+      );
+      Some(Regex::new(&pattern).expect("Invalid redacted field name"))
+    };
+
+    Redactor {
+      fields,
+      emails: conf.emails,
+    }
+  }
+
+  pub fn redact<'a>(&self, line: &'a str) -> Cow<'a, str> {
+    let mut line = Cow::Borrowed(line);
+
+    if let Some(fields) = &self.fields {
+      if let Cow::Owned(s) = fields.replace_all(&line, |c: &Captures| {
+        let value = &c[2];
+        let masked = if value.starts_with("\\\"") {
+          "\\\"***\\\""
+        } else if value.starts_with('"') {
+          "\"***\""
+        } else {
+          "***"
+        };
+        format!("{}{}", &c[1], masked)
+      }) {
+        line = Cow::Owned(s);
+      }
+    }
+
+    if self.emails {
+      if let Cow::Owned(s) = EMAIL.replace_all(&line, "***@$1") {
+        line = Cow::Owned(s);
+      }
+    }
+
+    line
+  }
+
+  fn wrap<W>(&self, inner: W) -> RedactingMakeWriter<W> {
+    RedactingMakeWriter {
+      inner,
+      redactor: self.clone(),
+    }
+  }
+}
+
+pub struct RedactingMakeWriter<W> {
+  inner: W,
+  redactor: Redactor,
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<W> {
+  type Writer = RedactingWriter<'a, W::Writer>;
+
+  fn make_writer(&'a self) -> Self::Writer {
+    RedactingWriter {
+      inner: self.inner.make_writer(),
+      redactor: &self.redactor,
+    }
+  }
+}
+
+/// The fmt layer hands over one complete line per write, so each write is redacted as a whole.
+pub struct RedactingWriter<'a, W> {
+  inner: W,
+  redactor: &'a Redactor,
+}
+
+impl<W: io::Write> io::Write for RedactingWriter<'_, W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match std::str::from_utf8(buf) {
+      Ok(line) => self.inner.write_all(self.redactor.redact(line).as_bytes())?,
+      Err(_) => self.inner.write_all(buf)?,
+    }
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(test)]
+mod logging_tests {
+  use super::{LoggingConfig, RedactConfig, Redactor};
+
+  #[test]
+  fn test_directives() {
+    let mut conf = LoggingConfig {
+      level: "warn".to_string(),
+      ..Default::default()
+    };
+    conf.modules.insert("helloworld".to_string(), "debug".to_string());
+    conf.modules.insert("sqlx".to_string(), "info".to_string());
+    assert_eq!(conf.directives(), "warn,helloworld=debug,sqlx=info");
+  }
+
+  #[test]
+  fn test_redact() {
+    let r = Redactor::new(&RedactConfig::default());
+
+    assert_eq!(
+      r.redact(r#"{"fields":{"password":"secret","id":1}}"#),
+      r#"{"fields":{"password":"***","id":1}}"#
+    );
+    assert_eq!(r.redact("login token=abc.def id=2"), "login token=*** id=2");
+    assert_eq!(
+      r.redact(r#"Account { id: None, password: "$argon2i$v=19$xx" }"#),
+      r#"Account { id: None, password: "***" }"#
+    );
+    assert_eq!(
+      r.redact(r#"{"message":"Account { Password: \"hash\", id: 1 }"}"#),
+      r#"{"message":"Account { Password: \"***\", id: 1 }"}"#
+    );
+    assert_eq!(r.redact("new user foo.bar@example.com"), "new user ***@example.com");
+    assert_eq!(r.redact("nothing to hide"), "nothing to hide");
+  }
+}
@@ -0,0 +1,279 @@
+use std::{error::Error, fmt, fs, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{
+  message::Mailbox,
+  transport::smtp::authentication::Credentials,
+  AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Deserialize;
+use tracing::{error, info, Instrument};
+
+use crate::utils::mask_email;
+
+pub type MailError = Box<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+  pub to: String,
+  pub subject: String,
+  pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: fmt::Debug + Send + Sync {
+  async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+  /// Prints mails to the log, for local development only
+  #[default]
+  Log,
+  /// Writes every mail to a file of `directory`
+  File,
+  Smtp,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+  None,
+  #[default]
+  Starttls,
+  /// Implicit TLS, usually on port 465
+  Tls,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SmtpConfig {
+  pub host: String,
+  pub port: Option<u16>,
+  pub username: Option<String>,
+  pub password: Option<String>,
+  pub tls: SmtpTls,
+}
+
+impl Default for SmtpConfig {
+  fn default() -> Self {
+    SmtpConfig {
+      host: "localhost".to_string(),
+      port: None,
+      username: None,
+      password: None,
+      tls: SmtpTls::default(),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MailConfig {
+  pub transport: Transport,
+  pub from: String,
+  /// Base of the links put in mails, the frontend pages reading the token from the query
+  pub base_url: String,
+  pub directory: String,
+  pub smtp: SmtpConfig,
+}
+
+impl Default for MailConfig {
+  fn default() -> Self {
+    MailConfig {
+      transport: Transport::default(),
+      from: "no-reply@localhost".to_string(),
+      base_url: "http://localhost:3033".to_string(),
+      directory: "mails".to_string(),
+      smtp: SmtpConfig::default(),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+  async fn send(&self, mail: Mail) -> Result<(), MailError> {
+    info!("Mail to {}: {}\n{}", mask_email(&mail.to), mail.subject, mail.body);
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub struct FileMailer {
+  directory: PathBuf,
+}
+
+impl FileMailer {
+  pub fn new(directory: impl Into<PathBuf>) -> std::io::Result<Self> {
+    let directory = directory.into();
+    fs::create_dir_all(&directory)?;
+    Ok(FileMailer { directory })
+  }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+  async fn send(&self, mail: Mail) -> Result<(), MailError> {
+    let name = format!("{}-{}.txt", Utc::now().format("%Y%m%dT%H%M%S%.6f"), uuid::Uuid::new_v4());
+    let content = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+    tokio::fs::write(self.directory.join(name), content).await?;
+    Ok(())
+  }
+}
+
+pub struct SmtpMailer {
+  transport: AsyncSmtpTransport<Tokio1Executor>,
+  from: Mailbox,
+}
+
+impl fmt::Debug for SmtpMailer {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SmtpMailer").field("from", &self.from).finish_non_exhaustive()
+  }
+}
+
+impl SmtpMailer {
+  pub fn new(conf: &SmtpConfig, from: &str) -> Result<Self, MailError> {
+    let mut builder = match conf.tls {
+      SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&conf.host),
+      SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&conf.host)?,
+      SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&conf.host)?,
+    };
+    if let Some(port) = conf.port {
+      builder = builder.port(port);
+    }
+    if let (Some(user), Some(password)) = (&conf.username, &conf.password) {
+      builder = builder.credentials(Credentials::new(user.clone(), password.clone()));
+    }
+
+    Ok(SmtpMailer {
+      transport: builder.build(),
+      from: from.parse()?,
+    })
+  }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+  async fn send(&self, mail: Mail) -> Result<(), MailError> {
+    let message = Message::builder()
+      .from(self.from.clone())
+      .to(mail.to.parse()?)
+      .subject(mail.subject)
+      .body(mail.body)?;
+    self.transport.send(message).await?;
+    Ok(())
+  }
+}
+
+/// Builds the mails of the account flows and sends them through the configured transport
+#[derive(Debug, Clone)]
+pub struct Outbox {
+  mailer: Arc<dyn Mailer>,
+  base_url: String,
+}
+
+impl Outbox {
+  pub fn new(conf: &MailConfig) -> Result<Self, MailError> {
+    let mailer: Arc<dyn Mailer> = match conf.transport {
+      Transport::Log => Arc::new(LogMailer),
+      Transport::File => Arc::new(FileMailer::new(&conf.directory)?),
+      Transport::Smtp => Arc::new(SmtpMailer::new(&conf.smtp, &conf.from)?),
+    };
+    Ok(Outbox {
+      mailer,
+      base_url: conf.base_url.trim_end_matches('/').to_string(),
+    })
+  }
+
+  pub fn send_verification(&self, to: &str, token: &str) {
+    self.send(Mail {
+      to: to.to_string(),
+      subject: "Verify your email".to_string(),
+      body: format!(
+        "Welcome! Confirm your email address by opening\n\n{}/verify-email?token={}\n",
+        self.base_url, token
+      ),
+    });
+  }
+
+  pub fn send_password_reset(&self, to: &str, token: &str) {
+    self.send(Mail {
+      to: to.to_string(),
+      subject: "Reset your password".to_string(),
+      body: format!(
+        "Choose a new password by opening\n\n{}/reset-password?token={}\n\nIgnore this mail if you did not ask for it.\n",
+        self.base_url, token
+      ),
+    });
+  }
+
+  pub fn send_email_change(&self, to: &str, token: &str) {
+    self.send(Mail {
+      to: to.to_string(),
+      subject: "Confirm your new email".to_string(),
+      body: format!(
+        "Confirm this address as the new email of your account by opening\n\n{}/confirm-email?token={}\n",
+        self.base_url, token
+      ),
+    });
+  }
+
+  /// Tells the previous address, so that an account takeover does not go unnoticed
+  pub fn send_email_changed(&self, to: &str, new_email: &str) {
+    self.send(Mail {
+      to: to.to_string(),
+      subject: "Your email was changed".to_string(),
+      body: format!(
+        "The email of your account was changed to {}.\nContact us right away if you did not do this.\n",
+        new_email
+      ),
+    });
+  }
+
+  /// Sends in the background, callers answer the same way and just as fast whether a mail goes out or not
+  fn send(&self, mail: Mail) {
+    let mailer = self.mailer.clone();
+    tokio::spawn(
+      async move {
+        let to = mask_email(&mail.to);
+        if let Err(e) = mailer.send(mail).await {
+          error!("Failed to send mail to {}: {:?}", to, e);
+        }
+      }
+      .in_current_span(),
+    );
+  }
+}
+
+#[cfg(test)]
+mod mail_tests {
+  use super::{FileMailer, Mail, Mailer};
+
+  #[tokio::test]
+  async fn test_file_mailer() {
+    let dir = std::env::temp_dir().join(format!("mail-test-{}", uuid::Uuid::new_v4()));
+    let mailer = FileMailer::new(&dir).unwrap();
+
+    mailer
+      .send(Mail {
+        to: "foo@example.com".to_string(),
+        subject: "Hi".to_string(),
+        body: "token=abc".to_string(),
+      })
+      .await
+      .unwrap();
+
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert_eq!(content, "To: foo@example.com\nSubject: Hi\n\ntoken=abc\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,25 +1,40 @@
 #![warn(clippy::all)]
 
+mod cli;
+mod duplicates;
+mod export;
+mod logging;
+mod mail;
+mod metrics;
 mod profanity;
+mod rate_limit;
+mod reputation;
+mod retention;
 mod routes;
 mod store;
+mod telemetry;
+mod totp;
 mod types;
 mod utils;
+mod validation;
 
 use std::env;
 
 use routes::{
-    answers::add_a,
-    questions::{add_q, del_q, detail_q, get_q, upd_q},
+    answers::{add_a, answer, del_a, patch_a},
+    questions::{accept_a, add_q, del_q, detail_q, get_q, patch_q, retag_q, unaccept_a, upd_q},
 };
 use store::Store;
+use types::api_key::Scope;
+use types::reputation::Privilege;
+use types::post::PostTarget;
 
 use tracing::info;
 use tracing::Span;
-use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::Layer;
 use uuid::Uuid;
 use warp::{
-    reject::Reject,
     trace::{Info, Trace},
     Filter,
 };
@@ -27,130 +42,879 @@ use warp::{
 use crate::routes::auth::{login, register};
 use config::Config;
 
+const CONFIG_FILE: &str = "config";
+
 #[derive(Debug, serde::Deserialize)]
 struct AppConfig {
-    log_level: String,
+    #[serde(default)]
+    logging: logging::LoggingConfig,
     database_host: String,
     database_port: u16,
     database_name: String,
     port: u16,
+    #[serde(default)]
+    trust_forwarded_for: bool,
+    #[serde(default = "default_trusted_proxies")]
+    trusted_proxies: usize,
+    #[serde(default)]
+    auth: routes::auth::AuthConfig,
+    #[serde(default)]
+    mail: mail::MailConfig,
+    #[serde(default)]
+    telemetry: telemetry::TelemetryConfig,
+    #[serde(default)]
+    rate_limit: rate_limit::RateLimitConfig,
+    #[serde(default)]
+    reputation: reputation::ReputationConfig,
+    #[serde(default)]
+    retention: retention::RetentionConfig,
+    #[serde(default)]
+    duplicates: duplicates::DuplicateConfig,
+}
+
+fn default_trusted_proxies() -> usize {
+    1
 }
 
 #[tokio::main]
 async fn main() -> Result<(), sqlx::Error> {
-  println!("{:?}", env::args());
-  
-    let conf = Config::builder()
-        .add_source(config::File::with_name("config"))
-        .build()
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = cli::parse(&args).unwrap_or_else(|usage| {
+        eprintln!("{}", usage);
+        std::process::exit(2);
+    });
+
+    let app_config = load_config()
+        .and_then(|conf| conf.try_deserialize::<AppConfig>())
         .unwrap();
 
-    let app_config = conf.try_deserialize::<AppConfig>().unwrap();
+    if let Some(command) = command {
+        if let Err(e) = cli::run(command, &db_url(&app_config), &app_config.reputation, &app_config.retention).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     setup_app(app_config).await;
     Ok(())
 }
 
-async fn setup_app(conf: AppConfig) {
-    tracing_subscriber::fmt()
-        .with_env_filter(format!(
-            "helloworld={},warp={},error-handler={},sqlx={}",
-            conf.log_level, conf.log_level, conf.log_level, conf.log_level
-        ))
-        .with_span_events(FmtSpan::CLOSE)
-        .init();
-
-    let db_url = env::var("DB_URL").unwrap_or(format!(
+fn db_url(conf: &AppConfig) -> String {
+    env::var("DB_URL").unwrap_or(format!(
         "postgres://dev:dev@{}:{}/{}",
         conf.database_host, conf.database_port, conf.database_name
-    ));
-    info!("DB connection url: {}", db_url);
+    ))
+}
+
+fn load_config() -> Result<Config, config::ConfigError> {
+    Config::builder()
+        .add_source(config::File::with_name(CONFIG_FILE))
+        .build()
+}
+
+async fn setup_app(conf: AppConfig) {
+    let tracer = telemetry::init_tracer(&conf.telemetry).expect("Could not set up tracing export");
+
+    let logging = logging::init(
+        &conf.logging,
+        vec![tracing_opentelemetry::layer().with_tracer(tracer).boxed()],
+    );
+    logging.watch(
+        || Ok(load_config()?.try_deserialize::<AppConfig>()?.logging),
+        conf.logging.reload_interval_secs,
+    );
+
+    let db_url = db_url(&conf);
+    info!("DB connection url: {}", utils::mask_db_url(&db_url));
 
     let store = Store::new(&db_url).await;
 
     info!("Start db migration");
-    sqlx::migrate!()
+    store::MIGRATOR
         .run(&store.clone().pool)
         .await
         .expect("Could not run db migration");
 
     info!("Finish db migration");
 
+    metrics::init();
+
+    conf.reputation.schedule_recompute(store.clone());
+    conf.retention.schedule_purge(store.clone());
+
+    let limiter = rate_limit::RateLimiter::new(conf.rate_limit, store.clone());
+    let client_ip = utils::client_ip(conf.trust_forwarded_for, conf.trusted_proxies);
+    let auth_conf = conf.auth.init().expect("Could not load auth config");
+    let auth_conf_filter = warp::any().map(move || auth_conf.clone());
+
+    let outbox = mail::Outbox::new(&conf.mail).expect("Could not set up mail transport");
+    let outbox_filter = warp::any().map(move || outbox.clone());
+
+    let admin = routes::auth::admin(store.clone());
+    let login_session = routes::auth::login_session(store.clone());
+    let any_session = routes::auth::auth(store.clone());
+    let write_questions = routes::auth::scoped(store.clone(), Scope::QuestionsWrite);
+    let write_answers = routes::auth::scoped(store.clone(), Scope::AnswersWrite);
+    let write_votes = routes::auth::scoped(store.clone(), Scope::VotesWrite);
+    let write_comments = routes::auth::scoped(store.clone(), Scope::CommentsWrite);
+    let edit_tags = routes::auth::privileged(store.clone(), Scope::QuestionsWrite, Privilege::EditTags, &conf.reputation);
+    let flag = routes::auth::privileged(store.clone(), Scope::FlagsWrite, Privilege::Flag, &conf.reputation);
+    let reputation_conf = conf.reputation.clone();
+    let reputation_filter = warp::any().map(move || reputation_conf.clone());
+    let duplicate_conf = conf.duplicates.clone();
+    let duplicate_filter = warp::any().map(move || duplicate_conf.clone());
     let store_filter = warp::any().map(move || store.clone());
 
+    let healthz = warp::get()
+        .and(warp::path("healthz"))
+        .and(warp::path::end())
+        .and_then(routes::health::healthz);
+
+    let readyz = warp::get()
+        .and(warp::path("readyz"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::health::readyz);
+
+    let metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(metrics::render);
+
     let get_q = warp::get()
         .and(warp::path("q"))
         .and(warp::path::end())
         .and(warp::query())
         .and(store_filter.clone())
-        .and_then(get_q);
+        .and_then(get_q)
+        .boxed();
 
     let add_q = warp::post()
         .and(warp::path("q"))
         .and(warp::path::end())
-        .and(routes::auth::auth())
+        .and(limiter.by_account("add_q", write_questions.clone()))
         .and(store_filter.clone())
+        .and(duplicate_filter)
         .and(warp::body::json())
-        .and_then(add_q);
+        .and_then(add_q)
+        .boxed();
 
     let detail_q = warp::get()
         .and(warp::path("q"))
         .and(warp::path::param::<u32>())
         .and(warp::path::end())
         .and(store_filter.clone())
-        .and_then(detail_q);
+        .and_then(detail_q)
+        .boxed();
 
     let upd_q = warp::put()
         .and(warp::path("q"))
         .and(warp::path::param::<u32>())
         .and(warp::path::end())
+        .and(write_questions.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(warp::body::json())
+        .and_then(upd_q)
+        .boxed();
+
+    let patch_q = warp::patch()
+        .and(warp::path!("q" / i32))
+        .and(write_questions.clone())
         .and(store_filter.clone())
+        .and(warp::header::optional::<String>("if-match"))
         .and(warp::body::json())
-        .and_then(upd_q);
+        .and_then(patch_q)
+        .boxed();
 
     let del_q = warp::delete()
         .and(warp::path("q"))
         .and(warp::path::param::<u32>())
         .and(warp::path::end())
+        .and(write_questions.clone())
+        .and(store_filter.clone())
+        .and_then(del_q)
+        .boxed();
+
+    let accept_a = warp::put()
+        .and(warp::path!("q" / i32 / "accept"))
+        .and(write_questions.clone())
+        .and(store_filter.clone())
+        .and(reputation_filter.clone())
+        .and(warp::body::json())
+        .and_then(accept_a)
+        .boxed();
+
+    let unaccept_a = warp::delete()
+        .and(warp::path!("q" / i32 / "accept"))
+        .and(write_questions.clone())
+        .and(store_filter.clone())
+        .and(reputation_filter.clone())
+        .and_then(unaccept_a)
+        .boxed();
+
+    let retag_q = warp::put()
+        .and(warp::path!("q" / i32 / "tags"))
+        .and(write_questions.clone())
         .and(store_filter.clone())
-        .and_then(del_q);
+        .and(reputation_filter.clone())
+        .and(warp::body::json())
+        .and_then(retag_q)
+        .boxed();
 
     let add_a = warp::post()
         .and(warp::path("a"))
         .and(warp::path::end())
+        .and(limiter.by_account("add_a", write_answers.clone()))
         .and(store_filter.clone())
+        .and(reputation_filter.clone())
         .and(warp::body::form())
-        .and_then(add_a);
+        .and_then(add_a)
+        .boxed();
+
+    let get_a = warp::get()
+        .and(warp::path!("a" / i32))
+        .and(store_filter.clone())
+        .and_then(answer)
+        .boxed();
+
+    let patch_a = warp::patch()
+        .and(warp::path!("a" / i32))
+        .and(write_answers.clone())
+        .and(store_filter.clone())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(warp::body::json())
+        .and_then(patch_a)
+        .boxed();
+
+    let del_a = warp::delete()
+        .and(warp::path!("a" / i32))
+        .and(write_answers)
+        .and(store_filter.clone())
+        .and_then(del_a)
+        .boxed();
+
+    let vote_q = warp::put()
+        .and(warp::path!("q" / i32 / "vote").map(PostTarget::Question))
+        .and(limiter.by_account("vote", write_votes.clone()))
+        .and(store_filter.clone())
+        .and(reputation_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::votes::vote)
+        .boxed();
+
+    let unvote_q = warp::delete()
+        .and(warp::path!("q" / i32 / "vote").map(PostTarget::Question))
+        .and(write_votes.clone())
+        .and(store_filter.clone())
+        .and(reputation_filter.clone())
+        .and_then(routes::votes::unvote)
+        .boxed();
+
+    let vote_a = warp::put()
+        .and(warp::path!("a" / i32 / "vote").map(PostTarget::Answer))
+        .and(limiter.by_account("vote", write_votes.clone()))
+        .and(store_filter.clone())
+        .and(reputation_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::votes::vote)
+        .boxed();
+
+    let unvote_a = warp::delete()
+        .and(warp::path!("a" / i32 / "vote").map(PostTarget::Answer))
+        .and(write_votes)
+        .and(store_filter.clone())
+        .and(reputation_filter)
+        .and_then(routes::votes::unvote)
+        .boxed();
+
+    let add_comment_q = warp::post()
+        .and(warp::path!("q" / i32 / "comments").map(PostTarget::Question))
+        .and(limiter.by_account("comment", write_comments.clone()))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comments::add_comment)
+        .boxed();
+
+    let add_comment_a = warp::post()
+        .and(warp::path!("a" / i32 / "comments").map(PostTarget::Answer))
+        .and(limiter.by_account("comment", write_comments.clone()))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comments::add_comment)
+        .boxed();
+
+    let comments_q = warp::get()
+        .and(warp::path!("q" / i32 / "comments").map(PostTarget::Question))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::comments::comments)
+        .boxed();
+
+    let comments_a = warp::get()
+        .and(warp::path!("a" / i32 / "comments").map(PostTarget::Answer))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::comments::comments)
+        .boxed();
+
+    let update_comment = warp::put()
+        .and(warp::path!("comments" / i32))
+        .and(write_comments.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::comments::update_comment)
+        .boxed();
+
+    let delete_comment = warp::delete()
+        .and(warp::path!("comments" / i32))
+        .and(write_comments)
+        .and(store_filter.clone())
+        .and_then(routes::comments::delete_comment)
+        .boxed();
+
+    let flag_q = warp::post()
+        .and(warp::path!("q" / i32 / "flag").map(PostTarget::Question))
+        .and(limiter.by_account("flag", flag.clone()))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::flags::flag)
+        .boxed();
+
+    let flag_a = warp::post()
+        .and(warp::path!("a" / i32 / "flag").map(PostTarget::Answer))
+        .and(limiter.by_account("flag", flag))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::flags::flag)
+        .boxed();
 
     let register = warp::post()
         .and(warp::path("reg"))
         .and(warp::path::end())
+        .and(limiter.by_ip("register", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(outbox_filter.clone())
+        .and(warp::body::json())
+        .and_then(register)
+        .boxed();
+
+    let login_2fa = warp::post()
+        .and(warp::path!("login" / "2fa"))
+        .and(limiter.by_ip("login", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(client_ip.clone())
+        .and(warp::body::json())
+        .and_then(routes::mfa::login)
+        .boxed();
+
+    let enroll_2fa = warp::post()
+        .and(warp::path!("2fa" / "enroll"))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and_then(routes::mfa::enroll)
+        .boxed();
+
+    let confirm_2fa = warp::post()
+        .and(warp::path!("2fa" / "confirm"))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::mfa::confirm)
+        .boxed();
+
+    let add_api_key = warp::post()
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::api_keys::create)
+        .boxed();
+
+    let list_api_keys = warp::get()
+        .and(warp::path("api-keys"))
+        .and(warp::path::end())
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and_then(routes::api_keys::list)
+        .boxed();
+
+    let revoke_api_key = warp::delete()
+        .and(warp::path!("api-keys" / i32))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and_then(|id, session, store| routes::api_keys::revoke(session, id, store))
+        .boxed();
+
+    let logout = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        .and(any_session)
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and_then(routes::auth::logout)
+        .boxed();
+
+    let me = warp::get()
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and_then(routes::profile::me)
+        .boxed();
+
+    let update_me = warp::patch()
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::profile::update_me)
+        .boxed();
+
+    let delete_me = warp::delete()
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::profile::delete_me)
+        .boxed();
+
+    let change_email = warp::post()
+        .and(warp::path!("me" / "email"))
+        .and(limiter.by_ip("password_reset", client_ip.clone()))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(outbox_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::profile::change_email)
+        .boxed();
+
+    let confirm_email = warp::post()
+        .and(warp::path!("me" / "email" / "confirm"))
+        .and(limiter.by_ip("verify_email", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(outbox_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::profile::confirm_email_change)
+        .boxed();
+
+    let change_password = warp::post()
+        .and(warp::path!("me" / "password"))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::profile::change_password)
+        .boxed();
+
+    let user_profile = warp::get()
+        .and(warp::path!("users" / i32))
+        .and(store_filter.clone())
+        .and_then(routes::profile::public_profile)
+        .boxed();
+
+    let user_questions = warp::get()
+        .and(warp::path!("users" / i32 / "questions"))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::profile::user_questions)
+        .boxed();
+
+    let user_answers = warp::get()
+        .and(warp::path!("users" / i32 / "answers"))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::profile::user_answers)
+        .boxed();
+
+    let user_reputation = warp::get()
+        .and(warp::path!("users" / i32 / "reputation"))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::profile::user_reputation)
+        .boxed();
+
+    let verify_email = warp::post()
+        .and(warp::path("verify-email"))
+        .and(warp::path::end())
+        .and(limiter.by_ip("verify_email", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::auth::verify_email)
+        .boxed();
+
+    let request_reset = warp::post()
+        .and(warp::path("password-reset"))
+        .and(warp::path::end())
+        .and(limiter.by_ip("password_reset", client_ip.clone()))
         .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(outbox_filter.clone())
+        .and(client_ip.clone())
         .and(warp::body::json())
-        .and_then(register);
+        .and_then(routes::auth::request_password_reset)
+        .boxed();
+
+    let confirm_reset = warp::post()
+        .and(warp::path!("password-reset" / "confirm"))
+        .and(limiter.by_ip("password_reset", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(client_ip.clone())
+        .and(warp::body::json())
+        .and_then(routes::auth::confirm_password_reset)
+        .boxed();
 
     let login = warp::post()
         .and(warp::path("login"))
         .and(warp::path::end())
+        .and(limiter.by_ip("login", client_ip.clone()))
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(client_ip.clone())
+        .and(warp::body::json())
+        .and_then(login)
+        .boxed();
+
+    let audit_log = warp::get()
+        .and(warp::path!("admin" / "audit"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::admin::audit_log)
+        .boxed();
+
+    let restore_q = warp::post()
+        .and(warp::path!("admin" / "q" / i32 / "restore").map(PostTarget::Question))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(routes::moderation::restore)
+        .boxed();
+
+    let restore_a = warp::post()
+        .and(warp::path!("admin" / "a" / i32 / "restore").map(PostTarget::Answer))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(routes::moderation::restore)
+        .boxed();
+
+    let mark_duplicate = warp::put()
+        .and(warp::path!("admin" / "q" / i32 / "duplicate"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::moderation::mark_duplicate)
+        .boxed();
+
+    let unmark_duplicate = warp::delete()
+        .and(warp::path!("admin" / "q" / i32 / "duplicate"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(routes::moderation::unmark_duplicate)
+        .boxed();
+
+    let set_state = warp::put()
+        .and(warp::path!("admin" / "q" / i32 / "state"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(routes::moderation::set_state)
+        .boxed();
+
+    let moderation_log = warp::get()
+        .and(warp::path!("admin" / "moderation"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::moderation::moderation_log)
+        .boxed();
+
+    let list_flags = warp::get()
+        .and(warp::path!("admin" / "flags"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::flags::flags)
+        .boxed();
+
+    let export_me = warp::get()
+        .and(warp::path!("me" / "export"))
+        .and(login_session.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::privacy::export_me)
+        .boxed();
+
+    let export_account = warp::get()
+        .and(warp::path!("admin" / "accounts" / i32 / "export"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(|id, admin, store, q| routes::privacy::admin_export(admin, id, store, q))
+        .boxed();
+
+    let erase_account = warp::post()
+        .and(warp::path!("admin" / "accounts" / i32 / "erase"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(|id, admin, store| routes::privacy::admin_erase(admin, id, store))
+        .boxed();
+
+    let reset_2fa = warp::delete()
+        .and(warp::path!("admin" / "accounts" / i32 / "2fa"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(|id, admin, store| routes::mfa::admin_reset(admin, id, store))
+        .boxed();
+
+    let list_accounts = warp::get()
+        .and(warp::path!("admin" / "accounts"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::admin::accounts)
+        .boxed();
+
+    let get_account = warp::get()
+        .and(warp::path!("admin" / "accounts" / i32))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(|id, admin, store| routes::admin::account(admin, id, store))
+        .boxed();
+
+    let suspend = warp::post()
+        .and(warp::path!("admin" / "accounts" / i32 / "suspend"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(|id, admin, store, body| routes::admin::suspend(admin, id, store, body))
+        .boxed();
+
+    let unsuspend = warp::delete()
+        .and(warp::path!("admin" / "accounts" / i32 / "suspend"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(|id, admin, store| routes::admin::unsuspend(admin, id, store))
+        .boxed();
+
+    let set_role = warp::put()
+        .and(warp::path!("admin" / "accounts" / i32 / "role"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(|id, admin, store, body| routes::admin::set_role(admin, id, store, body))
+        .boxed();
+
+    let force_reset = warp::post()
+        .and(warp::path!("admin" / "accounts" / i32 / "password-reset"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(outbox_filter.clone())
+        .and_then(|id, admin, store, conf, outbox| {
+            routes::admin::force_password_reset(admin, id, store, conf, outbox)
+        })
+        .boxed();
+
+    let impersonate = warp::post()
+        .and(warp::path!("admin" / "accounts" / i32 / "impersonate"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(auth_conf_filter.clone())
+        .and(warp::body::json())
+        .and_then(|id, admin, store, conf, body| routes::admin::impersonate(admin, id, store, conf, body))
+        .boxed();
+
+    // Every route is boxed, unboxed the future of the whole `or` chain overflows the worker stack of debug builds
+    // Areas are boxed on their own as well, a single chain this long exceeds the compiler's query depth
+    let account_api = register
+        .or(login)
+        .or(login_2fa)
+        .or(logout)
+        .or(enroll_2fa)
+        .or(confirm_2fa)
+        .or(me)
+        .or(update_me)
+        .or(delete_me)
+        .or(change_email)
+        .or(confirm_email)
+        .or(change_password)
+        .or(user_profile)
+        .or(user_questions)
+        .or(user_answers)
+        .or(user_reputation)
+        .or(export_me)
+        .or(add_api_key)
+        .or(list_api_keys)
+        .or(revoke_api_key)
+        .or(verify_email)
+        .or(request_reset)
+        .or(confirm_reset)
+        .boxed();
+
+    let list_tags = warp::get()
+        .and(warp::path("tags"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::tags::tags)
+        .boxed();
+
+    let get_tag = warp::get()
+        .and(warp::path!("tags" / String))
+        .and(store_filter.clone())
+        .and_then(routes::tags::tag)
+        .boxed();
+
+    let update_tag = warp::put()
+        .and(warp::path!("tags" / String))
+        .and(edit_tags.clone())
         .and(store_filter.clone())
         .and(warp::body::json())
-        .and_then(login);
+        .and_then(routes::tags::update_tag)
+        .boxed();
+
+    let add_synonym = warp::post()
+        .and(warp::path!("admin" / "tags" / String / "synonyms"))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and(warp::body::json())
+        .and_then(|slug, admin, store, body| routes::tags::add_synonym(admin, slug, store, body))
+        .boxed();
+
+    let remove_synonym = warp::delete()
+        .and(warp::path!("admin" / "tags" / String / "synonyms" / String))
+        .and(admin.clone())
+        .and(store_filter.clone())
+        .and_then(|slug, synonym, admin, store| routes::tags::remove_synonym(admin, slug, synonym, store))
+        .boxed();
+
+    let list_revisions = warp::get()
+        .and(warp::path!("q" / i32 / "revisions"))
+        .and(store_filter.clone())
+        .and_then(routes::revisions::revisions)
+        .boxed();
+
+    // Before `get_revision`, which would take "diff" for a revision number and reject it
+    let diff_revisions = warp::get()
+        .and(warp::path!("q" / i32 / "revisions" / "diff"))
+        .and(store_filter.clone())
+        .and(warp::query())
+        .and_then(routes::revisions::diff)
+        .boxed();
+
+    let get_revision = warp::get()
+        .and(warp::path!("q" / i32 / "revisions" / i32))
+        .and(store_filter.clone())
+        .and_then(routes::revisions::revision)
+        .boxed();
+
+    let rollback_q = warp::post()
+        .and(warp::path!("q" / i32 / "revisions" / i32 / "rollback"))
+        .and(write_questions)
+        .and(store_filter.clone())
+        .and_then(routes::revisions::rollback)
+        .boxed();
+
+    let revision_api = list_revisions
+        .or(diff_revisions)
+        .or(get_revision)
+        .or(rollback_q)
+        .boxed();
+
+    let tag_api = list_tags
+        .or(get_tag)
+        .or(update_tag)
+        .or(add_synonym)
+        .or(remove_synonym)
+        .boxed();
+
+    let comment_api = add_comment_q
+        .or(add_comment_a)
+        .or(comments_q)
+        .or(comments_a)
+        .or(update_comment)
+        .or(delete_comment)
+        .boxed();
+
+    let admin_api = audit_log
+        .or(list_flags)
+        .or(restore_q)
+        .or(restore_a)
+        .or(mark_duplicate)
+        .or(unmark_duplicate)
+        .or(set_state)
+        .or(moderation_log)
+        .or(reset_2fa)
+        .or(export_account)
+        .or(erase_account)
+        .or(list_accounts)
+        .or(get_account)
+        .or(suspend)
+        .or(unsuspend)
+        .or(set_role)
+        .or(force_reset)
+        .or(impersonate)
+        .boxed();
 
-    let routes = get_q
+    let api = get_q
         .or(add_q)
         .or(detail_q)
         .or(upd_q)
+        .or(patch_q)
         .or(del_q)
+        .or(accept_a)
+        .or(unaccept_a)
         .or(add_a)
+        .or(get_a)
+        .or(patch_a)
+        .or(del_a)
+        .or(vote_q)
+        .or(unvote_q)
+        .or(vote_a)
+        .or(unvote_a)
+        .or(retag_q)
+        .or(flag_q)
+        .or(flag_a)
         // .with(log)
-        .or(register)
-        .or(login)
+        .or(comment_api)
+        .or(revision_api)
+        .or(tag_api)
+        .or(account_api)
+        .or(admin_api)
         .with(cors_conf())
-        .with(trace_conf())
+        .recover(error_handler::error_hanling)
+        .map(telemetry::with_trace_id)
         .with(warp::trace::request())
-        .recover(error_handler::error_hanling);
+        // Outermost so that the request span is the root of the distributed trace
+        .with(trace_conf())
+        .with(metrics::metrics_conf());
+
+    // Probes and scrapes are polled every few seconds, keep them out of the request traces
+    let routes = healthz
+        .or(readyz)
+        .or(metrics)
+        .or(api);
 
-    warp::serve(routes).run(([0, 0, 0, 0], conf.port)).await
+    let (_, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], conf.port), async {
+            tokio::signal::ctrl_c().await.ok();
+        });
+    server.await;
+
+    info!("Shutting down");
+    telemetry::shutdown();
 }
 
 fn trace_conf() -> Trace<impl Fn(Info<'_>) -> Span + Clone> {
@@ -159,26 +923,29 @@ fn trace_conf() -> Trace<impl Fn(Info<'_>) -> Span + Clone> {
             Some(id) => String::from(id.to_str().unwrap_or("default")),
             None => Uuid::new_v4().to_string(),
         };
-        tracing::info_span!(
+        let span = tracing::info_span!(
             "ID",
-            id = %id
-        )
+            id = %id,
+            otel.name = %format!("{} {}", info.method(), info.path()),
+            otel.kind = "server",
+        );
+        if let Some(parent) = telemetry::extract_context(info.request_headers()) {
+            span.set_parent(parent);
+        }
+        span
     })
 }
 
 fn cors_conf() -> warp::cors::Builder {
     warp::cors()
         .allow_any_origin()
-        .allow_header("content-type")
+        .allow_headers(["content-type", "authorization", "x-api-key", "if-match", routes::auth::CSRF_HEADER])
+        .expose_headers(["etag"])
         .allow_methods(&[
             warp::http::Method::PUT,
+            warp::http::Method::PATCH,
             warp::http::Method::DELETE,
             warp::http::Method::GET,
             warp::http::Method::POST,
         ])
 }
-
-#[derive(Debug)]
-struct InvalidId;
-
-impl Reject for InvalidId {}
@@ -0,0 +1,278 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+  exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+  IntGauge, Opts, Registry, TextEncoder,
+};
+use warp::{
+  http::{header, StatusCode},
+  log::{Info, Log},
+  reply, Rejection, Reply,
+};
+
+use crate::store::Store;
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+pub static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register(IntCounterVec::new(
+    Opts::new("http_requests_total", "Number of handled http requests"),
+    &["method", "route", "status"],
+  ))
+});
+
+pub static HTTP_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+  register(HistogramVec::new(
+    HistogramOpts::new("http_request_duration_seconds", "Http request latency"),
+    &["method", "route", "status"],
+  ))
+});
+
+pub static DB_POOL_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+  register(IntGauge::new("db_pool_connections", "Number of open db connections"))
+});
+
+pub static DB_POOL_IDLE: Lazy<IntGauge> = Lazy::new(|| {
+  register(IntGauge::new("db_pool_idle_connections", "Number of idle db connections"))
+});
+
+pub static DB_ACQUIRE_DURATION: Lazy<Histogram> = Lazy::new(|| {
+  register(Histogram::with_opts(
+    HistogramOpts::new(
+      "db_pool_acquire_duration_seconds",
+      "Time spent waiting for a db connection from the pool",
+    )
+    .buckets(exponential_buckets(0.0005, 4.0, 8).unwrap()),
+  ))
+});
+
+pub static PROFANITY_CALLS: Lazy<IntCounterVec> = Lazy::new(|| {
+  register(IntCounterVec::new(
+    Opts::new("profanity_api_calls_total", "Number of calls to the profanity api"),
+    &["outcome"],
+  ))
+});
+
+pub static PROFANITY_DURATION: Lazy<Histogram> = Lazy::new(|| {
+  register(Histogram::with_opts(HistogramOpts::new(
+    "profanity_api_call_duration_seconds",
+    "Profanity api call latency, retries included",
+  )))
+});
+
+pub static QUESTIONS_CREATED: Lazy<IntCounter> = Lazy::new(|| {
+  register(IntCounter::new("questions_created_total", "Number of created questions"))
+});
+
+pub static ANSWERS_CREATED: Lazy<IntCounter> = Lazy::new(|| {
+  register(IntCounter::new("answers_created_total", "Number of created answers"))
+});
+
+pub static COMMENTS_CREATED: Lazy<IntCounter> = Lazy::new(|| {
+  register(IntCounter::new("comments_created_total", "Number of created comments"))
+});
+
+pub static ACCOUNTS_REGISTERED: Lazy<IntCounter> = Lazy::new(|| {
+  register(IntCounter::new("accounts_registered_total", "Number of registered accounts"))
+});
+
+/// Registers every metric up front so that they are exported before their first update.
+pub fn init() {
+  Lazy::force(&HTTP_REQUESTS);
+  Lazy::force(&HTTP_DURATION);
+  Lazy::force(&DB_POOL_SIZE);
+  Lazy::force(&DB_POOL_IDLE);
+  Lazy::force(&DB_ACQUIRE_DURATION);
+  Lazy::force(&PROFANITY_CALLS);
+  Lazy::force(&PROFANITY_DURATION);
+  Lazy::force(&QUESTIONS_CREATED);
+  Lazy::force(&ANSWERS_CREATED);
+  Lazy::force(&COMMENTS_CREATED);
+  Lazy::force(&ACCOUNTS_REGISTERED);
+}
+
+fn register<C>(collector: prometheus::Result<C>) -> C
+where
+  C: prometheus::core::Collector + Clone + 'static,
+{
+  let collector = collector.expect("Invalid metric definition");
+  REGISTRY
+    .register(Box::new(collector.clone()))
+    .expect("Metric registered twice");
+  collector
+}
+
+/// Every path the api serves, `{id}` stands for a numeric segment and `{slug}` for any other one.
+/// Paths missing here are labelled as unmatched, so a new route has to be added to show up on its own.
+const ROUTES: &[&str] = &[
+  "/healthz",
+  "/readyz",
+  "/metrics",
+  "/q",
+  "/q/{id}",
+  "/q/{id}/accept",
+  "/q/{id}/tags",
+  "/q/{id}/vote",
+  "/q/{id}/comments",
+  "/q/{id}/flag",
+  "/q/{id}/revisions",
+  "/q/{id}/revisions/diff",
+  "/q/{id}/revisions/{id}",
+  "/q/{id}/revisions/{id}/rollback",
+  "/a",
+  "/a/{id}",
+  "/a/{id}/vote",
+  "/a/{id}/comments",
+  "/a/{id}/flag",
+  "/comments/{id}",
+  "/tags",
+  "/tags/{slug}",
+  "/reg",
+  "/login",
+  "/login/2fa",
+  "/logout",
+  "/2fa/enroll",
+  "/2fa/confirm",
+  "/api-keys",
+  "/api-keys/{id}",
+  "/me",
+  "/me/email",
+  "/me/email/confirm",
+  "/me/password",
+  "/me/export",
+  "/users/{id}",
+  "/users/{id}/questions",
+  "/users/{id}/answers",
+  "/users/{id}/reputation",
+  "/verify-email",
+  "/password-reset",
+  "/password-reset/confirm",
+  "/admin/audit",
+  "/admin/flags",
+  "/admin/moderation",
+  "/admin/q/{id}/restore",
+  "/admin/q/{id}/duplicate",
+  "/admin/q/{id}/state",
+  "/admin/a/{id}/restore",
+  "/admin/accounts",
+  "/admin/accounts/{id}",
+  "/admin/accounts/{id}/export",
+  "/admin/accounts/{id}/erase",
+  "/admin/accounts/{id}/2fa",
+  "/admin/accounts/{id}/suspend",
+  "/admin/accounts/{id}/role",
+  "/admin/accounts/{id}/password-reset",
+  "/admin/accounts/{id}/impersonate",
+  "/admin/tags/{slug}/synonyms",
+  "/admin/tags/{slug}/synonyms/{slug}",
+];
+
+/// Records count and latency of every request passing through the wrapped filter.
+pub fn metrics_conf() -> Log<impl Fn(Info<'_>) + Clone> {
+  warp::log::custom(|info| {
+    let method = info.method().as_str();
+    let status = info.status();
+    let labels = [method, route_label(info.path(), status), status.as_str()];
+
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_DURATION
+      .with_label_values(&labels)
+      .observe(info.elapsed().as_secs_f64());
+  })
+}
+
+/// The route serving `path`, so that the labels stay bounded whatever clients request.
+/// Unknown paths share one label, these are answered by the error handler fallback (406)
+/// or, for a CORS preflight, before any route is looked at.
+fn route_label(path: &str, status: StatusCode) -> &'static str {
+  if status == StatusCode::NOT_ACCEPTABLE {
+    return "unmatched";
+  }
+
+  let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+  ROUTES
+    .iter()
+    .find(|route| {
+      let parts: Vec<&str> = route.trim_start_matches('/').split('/').collect();
+      parts.len() == segments.len()
+        && parts.iter().zip(&segments).all(|(part, seg)| match *part {
+          "{id}" => seg.parse::<i64>().is_ok(),
+          "{slug}" => !seg.is_empty(),
+          part => part == *seg,
+        })
+    })
+    .copied()
+    .unwrap_or("unmatched")
+}
+
+pub async fn render(store: Store) -> Result<impl Reply, Rejection> {
+  DB_POOL_SIZE.set(store.pool.size() as i64);
+  DB_POOL_IDLE.set(store.pool.num_idle() as i64);
+
+  let encoder = TextEncoder::new();
+  let mut buf = Vec::new();
+  if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buf) {
+    tracing::error!("Failed to encode metrics: {:?}", e);
+    return Ok(reply::with_status(
+      reply::with_header(String::new(), header::CONTENT_TYPE, encoder.format_type()),
+      StatusCode::INTERNAL_SERVER_ERROR,
+    ));
+  }
+
+  Ok(reply::with_status(
+    reply::with_header(
+      String::from_utf8_lossy(&buf).into_owned(),
+      header::CONTENT_TYPE,
+      encoder.format_type(),
+    ),
+    StatusCode::OK,
+  ))
+}
+
+#[cfg(test)]
+mod metrics_tests {
+  use warp::{http::StatusCode, Filter};
+
+  use super::{metrics_conf, route_label, HTTP_REQUESTS};
+
+  #[test]
+  fn test_route_label() {
+    assert_eq!(route_label("/q", StatusCode::OK), "/q");
+    assert_eq!(route_label("/q/12", StatusCode::ACCEPTED), "/q/{id}");
+    assert_eq!(route_label("/q/12/x", StatusCode::NOT_ACCEPTABLE), "unmatched");
+    assert_eq!(route_label("/q/abc", StatusCode::NOT_FOUND), "unmatched");
+    assert_eq!(route_label("/q/12/revisions/diff", StatusCode::OK), "/q/{id}/revisions/diff");
+    assert_eq!(route_label("/admin/tags/rust/synonyms/rustlang", StatusCode::OK), "/admin/tags/{slug}/synonyms/{slug}");
+  }
+
+  #[tokio::test]
+  async fn test_route_cardinality() {
+    let cors = warp::cors().allow_any_origin().allow_method("GET");
+    let filter = warp::path!("tags" / String)
+      .map(|_| warp::reply())
+      .with(cors)
+      .with(metrics_conf());
+    let count = |method: &str, route: &str, status: &str| {
+      HTTP_REQUESTS.with_label_values(&[method, route, status]).get()
+    };
+    let preflights = count("OPTIONS", "unmatched", "200");
+    let tags = count("GET", "/tags/{slug}", "200");
+
+    for path in ["/x7f3", "/q/12/nope"] {
+      let res = warp::test::request()
+        .method("OPTIONS")
+        .path(path)
+        .header("Origin", "https://example.com")
+        .header("Access-Control-Request-Method", "GET")
+        .reply(&filter)
+        .await;
+      assert_eq!(res.status(), StatusCode::OK);
+    }
+    for slug in ["rust", "made-up-slug"] {
+      let res = warp::test::request().path(&format!("/tags/{}", slug)).reply(&filter).await;
+      assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    assert_eq!(count("OPTIONS", "unmatched", "200"), preflights + 2);
+    assert_eq!(count("GET", "/tags/{slug}", "200"), tags + 2);
+  }
+}
@@ -4,11 +4,14 @@ use std::{
 };
 
 use error_handler::AppError;
+use reqwest::header::HeaderMap;
 use reqwest_middleware::ClientBuilder;
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use serde::{Deserialize, Serialize};
 use tracing::{info, instrument};
 
+use crate::{logging, metrics, telemetry};
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BadWordResponse {
     pub bad_words_list: Vec<BadWordsList>,
@@ -31,18 +34,49 @@ pub struct BadWordErrorRes {
 }
 
 pub async fn check_profanity(text: String) -> Result<BadWordResponse, AppError> {
-    info!("Starting check text: {}", &text);
+    info!("Starting check text: {}", logging::user_content(&text));
     let start = Instant::now();
     let rs = api_layer(text.clone()).await;
+    let elapsed = start.elapsed();
     info!(
         "Finish check text: {}, consumes: {}ms",
-        &text,
-        start.elapsed().as_millis()
+        logging::user_content(&text),
+        elapsed.as_millis()
     );
+
+    metrics::PROFANITY_DURATION.observe(elapsed.as_secs_f64());
+    metrics::PROFANITY_CALLS
+        .with_label_values(&[if rs.is_ok() { "success" } else { "failure" }])
+        .inc();
     rs
 }
 
-#[instrument]
+/// Censored `text`, a field a patch leaves out stays out
+pub async fn censor(text: Option<String>) -> Result<Option<String>, AppError> {
+    match text {
+        Some(text) => Ok(Some(check_profanity(text).await?.censored_content)),
+        None => Ok(None),
+    }
+}
+
+/// Checks that the profanity backend answers http requests at all,
+/// any status code counts as reachable.
+pub async fn ping() -> Result<(), AppError> {
+    reqwest::Client::new()
+        .get(api_url())
+        .timeout(Duration::from_secs(2))
+        .send()
+        .await
+        .map(|_| ())
+        .map_err(|e| AppError::ApiCallErr(e.to_string()))
+}
+
+fn api_url() -> String {
+    env::var("API_LAYER_URL").map_or("https://api.apilayer.com".to_string(), |url| url)
+}
+
+// Skip the text, user content does not belong in exported span attributes
+#[instrument(skip(text), fields(otel.kind = "client", http.method = "POST"))]
 async fn api_layer(text: String) -> Result<BadWordResponse, AppError> {
     let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
     let client = ClientBuilder::new(reqwest::Client::new())
@@ -50,15 +84,16 @@ async fn api_layer(text: String) -> Result<BadWordResponse, AppError> {
         .build();
 
     let api_key = option_env!("API_LAYER_K").map_or("", |k| k);
-    let api_url =
-        env::var("API_LAYER_URL").map_or("https://api.apilayer.com".to_string(), |url| url);
-
-    let endpoint = format!("{}/bad_words?censor_character=*", api_url);
+    let endpoint = format!("{}/bad_words?censor_character=*", api_url());
     println!("Will connect to {:?}", endpoint);
 
+    let mut headers = HeaderMap::new();
+    telemetry::inject_context(&mut headers);
+
     let res = client
         .post(endpoint)
         .timeout(Duration::from_secs(3))
+        .headers(headers)
         .header("apikey", api_key)
         .body(text)
         .send()
@@ -0,0 +1,307 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use error_handler::AppError;
+use serde::Deserialize;
+use tracing::{debug, error, warn};
+use warp::{reject, Filter, Rejection};
+
+use crate::{routes::auth::One, store::Store, types::account::Session};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+  #[default]
+  Memory,
+  /// Shares the buckets between instances through the `rate_limit_buckets` table
+  Postgres,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct Limit {
+  /// Burst size, the number of requests allowed at once
+  pub capacity: u32,
+  /// Tokens given back per minute
+  pub refill_per_minute: f64,
+}
+
+impl Limit {
+  fn rate_per_sec(&self) -> f64 {
+    self.refill_per_minute / 60.0
+  }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+  pub enabled: bool,
+  pub backend: Backend,
+  /// Limits by route name, routes without an entry are not throttled
+  pub routes: HashMap<String, Limit>,
+}
+
+impl Default for RateLimitConfig {
+  fn default() -> Self {
+    RateLimitConfig {
+      enabled: true,
+      backend: Backend::default(),
+      routes: HashMap::new(),
+    }
+  }
+}
+
+/// Outcome of taking one token out of a bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+  pub allowed: bool,
+  pub limit: u32,
+  pub remaining: u32,
+  /// Seconds until the bucket is full again
+  pub reset: u64,
+  /// Seconds until the next request is allowed, 0 when allowed
+  pub retry_after: u64,
+}
+
+/// Refills a bucket holding `tokens` for `elapsed` seconds then tries to take one token.
+/// Returns the tokens left in the bucket.
+pub fn take(tokens: f64, elapsed: f64, limit: &Limit) -> (f64, Decision) {
+  let capacity = limit.capacity as f64;
+  let rate = limit.rate_per_sec();
+  let mut tokens = (tokens + elapsed.max(0.0) * rate).min(capacity);
+
+  let allowed = tokens >= 1.0;
+  if allowed {
+    tokens -= 1.0;
+  }
+
+  let secs_until = |target: f64| {
+    if tokens >= target || rate <= 0.0 {
+      0
+    } else {
+      ((target - tokens) / rate).ceil() as u64
+    }
+  };
+
+  let decision = Decision {
+    allowed,
+    limit: limit.capacity,
+    remaining: tokens.floor() as u32,
+    reset: secs_until(capacity),
+    retry_after: if allowed { 0 } else { secs_until(1.0).max(1) },
+  };
+  (tokens, decision)
+}
+
+#[derive(Debug)]
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+#[derive(Debug, Clone)]
+enum Buckets {
+  Memory(Arc<Mutex<HashMap<String, Bucket>>>),
+  Postgres(Store),
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+  conf: Arc<RateLimitConfig>,
+  buckets: Buckets,
+}
+
+impl RateLimiter {
+  pub fn new(conf: RateLimitConfig, store: Store) -> Self {
+    let buckets = match conf.backend {
+      Backend::Memory => {
+        let buckets = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(sweep(buckets.clone(), conf.routes.values().copied().collect()));
+        Buckets::Memory(buckets)
+      }
+      Backend::Postgres => {
+        tokio::spawn(sweep_table(store.clone(), conf.routes.values().copied().collect()));
+        Buckets::Postgres(store)
+      }
+    };
+
+    RateLimiter {
+      conf: Arc::new(conf),
+      buckets,
+    }
+  }
+
+  /// Takes a token for `key` out of the bucket of `route`.
+  /// Requests are let through when the route has no limit or the store is failing.
+  pub async fn check(&self, route: &str, key: &str) -> Result<(), AppError> {
+    let limit = match self.conf.routes.get(route) {
+      Some(limit) if self.conf.enabled => limit,
+      _ => return Ok(()),
+    };
+    let key = format!("{}:{}", route, key);
+
+    let decision = match &self.buckets {
+      Buckets::Memory(buckets) => {
+        let mut buckets = buckets.lock().unwrap();
+        let now = Instant::now();
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+          tokens: limit.capacity as f64,
+          updated: now,
+        });
+        let (tokens, decision) = take(
+          bucket.tokens,
+          now.duration_since(bucket.updated).as_secs_f64(),
+          limit,
+        );
+        bucket.tokens = tokens;
+        bucket.updated = now;
+        decision
+      }
+      Buckets::Postgres(store) => match store.take_rate_limit_token(&key, limit).await {
+        Ok(decision) => decision,
+        Err(e) => {
+          error!("Failed to check rate limit, let the request through: {:?}", e);
+          return Ok(());
+        }
+      },
+    };
+
+    if decision.allowed {
+      return Ok(());
+    }
+
+    warn!("Rate limit of {} exceeded by {}", route, key);
+    Err(AppError::RateLimited {
+      limit: decision.limit,
+      remaining: decision.remaining,
+      reset: decision.reset,
+      retry_after: decision.retry_after,
+    })
+  }
+
+  /// Throttles requests of `route` by the client ip extracted by `client_ip`.
+  pub fn by_ip<F>(&self, route: &'static str, client_ip: F) -> impl Filter<Extract = (), Error = Rejection> + Clone
+  where
+    F: Filter<Extract = One<String>, Error = Rejection> + Clone,
+  {
+    let limiter = self.clone();
+
+    client_ip
+      .and_then(move |ip: String| {
+        let limiter = limiter.clone();
+        async move {
+          limiter
+            .check(route, &format!("ip:{}", ip))
+            .await
+            .map_err(reject::custom)
+        }
+      })
+      .untuple_one()
+  }
+
+  /// Throttles requests of `route` by the account of the session extracted by `auth`.
+  pub fn by_account<F>(
+    &self,
+    route: &'static str,
+    auth: F,
+  ) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone
+  where
+    F: Filter<Extract = One<Session>, Error = Rejection> + Clone,
+  {
+    let limiter = self.clone();
+
+    auth.and_then(move |session: Session| {
+      let limiter = limiter.clone();
+      async move {
+        let key = format!("account:{}", session.id.unwrap_or_default());
+        limiter
+          .check(route, &key)
+          .await
+          .map(|_| session)
+          .map_err(reject::custom)
+      }
+    })
+  }
+}
+
+/// Seconds after which an untouched bucket is full again whatever its limit, 60 at least
+fn max_idle_secs(limits: &[Limit]) -> f64 {
+  limits
+    .iter()
+    .filter(|l| l.refill_per_minute > 0.0)
+    .map(|l| l.capacity as f64 / l.rate_per_sec())
+    .fold(60.0, f64::max)
+}
+
+/// Drops in-memory buckets which have been refilled completely, they are equal to a fresh bucket.
+async fn sweep(buckets: Arc<Mutex<HashMap<String, Bucket>>>, limits: Vec<Limit>) {
+  let max_idle = max_idle_secs(&limits);
+
+  let mut ticker = tokio::time::interval(Duration::from_secs(60));
+  loop {
+    ticker.tick().await;
+    buckets
+      .lock()
+      .unwrap()
+      .retain(|_, b| b.updated.elapsed().as_secs_f64() < max_idle);
+  }
+}
+
+/// Same as `sweep` for the shared table, which would otherwise keep a row for every client ip ever seen.
+/// Every instance runs it, deleting the same rows twice does no harm.
+async fn sweep_table(store: Store, limits: Vec<Limit>) {
+  let max_idle = max_idle_secs(&limits);
+
+  let mut ticker = tokio::time::interval(Duration::from_secs(60));
+  loop {
+    ticker.tick().await;
+    match store.delete_idle_rate_limit_buckets(max_idle).await {
+      Ok(0) => {}
+      Ok(deleted) => debug!(deleted, "Dropped idle rate limit buckets"),
+      Err(e) => error!("Failed to drop idle rate limit buckets: {:?}", e),
+    }
+  }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+  use super::{max_idle_secs, take, Limit};
+
+  #[test]
+  fn test_max_idle_secs() {
+    let limit = |capacity, refill_per_minute| Limit {
+      capacity,
+      refill_per_minute,
+    };
+    assert_eq!(max_idle_secs(&[]), 60.0);
+    assert_eq!(max_idle_secs(&[limit(10, 10.0), limit(3, 1.0)]), 180.0);
+    assert_eq!(max_idle_secs(&[limit(5, 0.0), limit(2, 1.0)]), 120.0);
+  }
+
+  #[test]
+  fn test_take() {
+    let limit = Limit {
+      capacity: 2,
+      refill_per_minute: 6.0,
+    };
+
+    let (tokens, d) = take(2.0, 0.0, &limit);
+    assert!(d.allowed);
+    assert_eq!((d.limit, d.remaining, d.reset, d.retry_after), (2, 1, 10, 0));
+
+    let (tokens, d) = take(tokens, 0.0, &limit);
+    assert!(d.allowed);
+    assert_eq!((d.remaining, d.reset), (0, 20));
+
+    let (tokens, d) = take(tokens, 4.0, &limit);
+    assert!(!d.allowed);
+    assert_eq!((d.remaining, d.retry_after), (0, 6));
+
+    // refill never goes above capacity
+    let (_, d) = take(tokens, 3600.0, &limit);
+    assert!(d.allowed);
+    assert_eq!(d.remaining, 1);
+  }
+}
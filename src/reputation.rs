@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::{
+  store::Store,
+  types::{
+    account::Role,
+    post::PostTarget,
+    reputation::{Privilege, DOWNVOTED, UPVOTED},
+  },
+};
+
+/// Points per reputation event and the thresholds of the privileges
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReputationConfig {
+  pub question_upvoted: i32,
+  pub answer_upvoted: i32,
+  pub downvoted: i32,
+  pub answer_accepted: i32,
+  pub recompute_interval_secs: u64,
+  pub privileges: PrivilegeConfig,
+}
+
+impl Default for ReputationConfig {
+  fn default() -> Self {
+    ReputationConfig {
+      question_upvoted: 5,
+      answer_upvoted: 10,
+      downvoted: -2,
+      answer_accepted: 15,
+      recompute_interval_secs: 86400,
+      privileges: PrivilegeConfig::default(),
+    }
+  }
+}
+
+/// Reputation needed for each `Privilege`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PrivilegeConfig {
+  pub edit_tags: i32,
+  pub flag: i32,
+  pub answer_protected: i32,
+}
+
+impl Default for PrivilegeConfig {
+  fn default() -> Self {
+    PrivilegeConfig {
+      edit_tags: 500,
+      flag: 15,
+      answer_protected: 10,
+    }
+  }
+}
+
+impl ReputationConfig {
+  /// Kind and points of the event the author of `target` gets for a vote of `value`
+  pub fn vote_event(&self, target: PostTarget, value: i16) -> (&'static str, i32) {
+    match (value > 0, target) {
+      (true, PostTarget::Question(_)) => (UPVOTED, self.question_upvoted),
+      (true, PostTarget::Answer(_)) => (UPVOTED, self.answer_upvoted),
+      (false, _) => (DOWNVOTED, self.downvoted),
+    }
+  }
+
+  pub fn required(&self, privilege: Privilege) -> i32 {
+    match privilege {
+      Privilege::EditTags => self.privileges.edit_tags,
+      Privilege::Flag => self.privileges.flag,
+      Privilege::AnswerProtected => self.privileges.answer_protected,
+    }
+  }
+
+  /// Whether an account of `standing`, as given by `Store::account_standing`, has `privilege`.
+  /// Admins have every privilege
+  pub fn grants(&self, privilege: Privilege, standing: Option<(Role, i32)>) -> bool {
+    match standing {
+      Some((Role::Admin, _)) => true,
+      Some((_, reputation)) => reputation >= self.required(privilege),
+      None => false,
+    }
+  }
+
+  /// Runs `Store::recompute_reputation` in the background every `recompute_interval_secs`
+  pub fn schedule_recompute(&self, store: Store) {
+    if self.recompute_interval_secs == 0 {
+      return;
+    }
+
+    let conf = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(Duration::from_secs(conf.recompute_interval_secs));
+      ticker.tick().await;
+      loop {
+        ticker.tick().await;
+        match store.recompute_reputation(&conf).await {
+          Ok(drifted) => info!(drifted, "Recomputed reputation"),
+          Err(e) => error!("Failed to recompute reputation: {:?}", e),
+        }
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod reputation_tests {
+  use super::ReputationConfig;
+  use crate::types::{
+    account::Role,
+    post::PostTarget,
+    reputation::{Privilege, DOWNVOTED, UPVOTED},
+  };
+
+  #[test]
+  fn test_vote_event() {
+    let conf = ReputationConfig::default();
+    assert_eq!(conf.vote_event(PostTarget::Question(1), 1), (UPVOTED, 5));
+    assert_eq!(conf.vote_event(PostTarget::Answer(1), 1), (UPVOTED, 10));
+    assert_eq!(conf.vote_event(PostTarget::Question(1), -1), (DOWNVOTED, -2));
+    assert_eq!(conf.vote_event(PostTarget::Answer(1), -1), (DOWNVOTED, -2));
+    assert_eq!(conf.required(Privilege::EditTags), 500);
+    assert_eq!(conf.required(Privilege::Flag), 15);
+    assert_eq!(conf.required(Privilege::AnswerProtected), 10);
+  }
+
+  #[test]
+  fn test_grants() {
+    let conf = ReputationConfig::default();
+    assert!(conf.grants(Privilege::AnswerProtected, Some((Role::User, 10))));
+    assert!(!conf.grants(Privilege::AnswerProtected, Some((Role::User, 9))));
+    assert!(conf.grants(Privilege::AnswerProtected, Some((Role::Admin, 0))));
+    assert!(conf.grants(Privilege::EditTags, Some((Role::Admin, -20))));
+    assert!(!conf.grants(Privilege::Flag, None));
+  }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::store::Store;
+
+/// How long soft-deleted posts can still be restored before they are purged
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+  pub deleted_post_days: i32,
+  pub purge_interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+  fn default() -> Self {
+    RetentionConfig {
+      deleted_post_days: 30,
+      purge_interval_secs: 3600,
+    }
+  }
+}
+
+impl RetentionConfig {
+  /// Time between two purges, none when `purge_interval_secs` turns the purge off
+  fn purge_interval(&self) -> Option<Duration> {
+    Some(Duration::from_secs(self.purge_interval_secs)).filter(|d| !d.is_zero())
+  }
+
+  /// Runs `Store::purge_deleted` in the background every `purge_interval_secs`, starting right away
+  pub fn schedule_purge(&self, store: Store) {
+    let Some(interval) = self.purge_interval() else {
+      return;
+    };
+
+    let conf = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(interval);
+      loop {
+        ticker.tick().await;
+        match store.purge_deleted(conf.deleted_post_days).await {
+          Ok((questions, answers)) => info!(questions, answers, "Purged deleted posts"),
+          Err(e) => error!("Failed to purge deleted posts: {:?}", e),
+        }
+      }
+    });
+  }
+}
+
+#[cfg(test)]
+mod retention_tests {
+  use std::time::Duration;
+
+  use super::RetentionConfig;
+  use crate::{
+    reputation::ReputationConfig,
+    store::{Store, MIGRATOR},
+    types::{account::Account, answer::AddAnswerOutcome, post::PostTarget, question::QuestionPayload},
+  };
+
+  #[test]
+  fn test_purge_interval() {
+    let conf = RetentionConfig::default();
+    assert_eq!(conf.deleted_post_days, 30);
+    assert_eq!(conf.purge_interval(), Some(Duration::from_secs(3600)));
+    let off = RetentionConfig {
+      purge_interval_secs: 0,
+      ..conf
+    };
+    assert_eq!(off.purge_interval(), None);
+  }
+
+  // Needs a database, skipped unless TEST_DB_URL is set
+  #[tokio::test]
+  async fn test_deleted_posts_void_reputation() {
+    let Ok(url) = std::env::var("TEST_DB_URL") else {
+      return;
+    };
+    let store = Store::new(&url).await;
+    MIGRATOR.run(&store.pool).await.unwrap();
+
+    let account = |name: &str| Account {
+      id: None,
+      email: format!("{}-{}@example.com", name, rand::random::<u32>()),
+      password: "!".to_string(),
+    };
+    let author = store.add_account(account("author")).await.unwrap();
+    let voter = store.add_account(account("voter")).await.unwrap();
+    let q = store
+      .add_q(
+        QuestionPayload {
+          title: "How do soft deletes work?".to_string(),
+          content: "Asking for the tests".to_string(),
+          tags: None,
+        },
+        Some(author),
+      )
+      .await
+      .unwrap();
+    let qid = q.id.0 as i32;
+    let added = store.add_a(qid, "Like this".to_string(), Some(author), false).await.unwrap();
+    assert!(matches!(added, AddAnswerOutcome::Added));
+    let aid: i32 = sqlx::query_scalar("SELECT id FROM answers WHERE corresponding_question = $1")
+      .bind(qid)
+      .fetch_one(&store.pool)
+      .await
+      .unwrap();
+
+    let conf = ReputationConfig::default();
+    store.vote(PostTarget::Question(qid), voter, Some(1), &conf).await.unwrap();
+    store.vote(PostTarget::Answer(aid), voter, Some(1), &conf).await.unwrap();
+    let reputation = || async { store.account_standing(author).await.unwrap().unwrap().1 };
+    assert_eq!(reputation().await, 15);
+
+    assert!(store.delete_post(PostTarget::Question(qid), Some(author)).await.unwrap());
+    assert!(store.answer(aid).await.unwrap().is_none());
+    assert_eq!(reputation().await, 0);
+    assert!(store.restore_post(PostTarget::Question(qid)).await.unwrap());
+    assert!(store.answer(aid).await.unwrap().is_some());
+    assert_eq!(reputation().await, 15);
+
+    assert!(store.delete_post(PostTarget::Answer(aid), Some(author)).await.unwrap());
+    assert_eq!(reputation().await, 5);
+    store.recompute_reputation(&conf).await.unwrap();
+    assert_eq!(reputation().await, 5);
+    sqlx::query("UPDATE answers SET deleted_at = now() - interval '31 days' WHERE id = $1")
+      .bind(aid)
+      .execute(&store.pool)
+      .await
+      .unwrap();
+    store.purge_deleted(30).await.unwrap();
+    assert_eq!(reputation().await, 5);
+  }
+}
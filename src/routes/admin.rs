@@ -0,0 +1,150 @@
+use error_handler::AppError;
+use tracing::error;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  mail::Outbox,
+  routes::auth::{add_audit, db_error, issue_impersonation_token, issue_mail_token, AuthConfig},
+  store::Store,
+  types::{
+    account::{AccountId, LoginResponse, Session, TokenPurpose},
+    admin::{AccountQuery, Impersonation, RoleChange, Suspension},
+    audit::{self, AuditQuery, NewAuditEntry},
+  },
+  validation::field_errors,
+};
+
+pub async fn audit_log(_admin: Session, store: Store, q: AuditQuery) -> Result<impl Reply, Rejection> {
+  match store.find_audit(q).await {
+    Ok(entries) => Ok(reply::json(&entries)),
+    Err(e) => {
+      error!("Failed to query audit log: {:?}", e);
+      Err(reject::custom(AppError::DbQueryError))
+    }
+  }
+}
+
+pub async fn accounts(_admin: Session, store: Store, q: AccountQuery) -> Result<impl Reply, Rejection> {
+  let accounts = store.search_accounts(q).await.map_err(db_error)?;
+  Ok(reply::json(&accounts))
+}
+
+/// The account with counts of what it did, for support
+pub async fn account(_admin: Session, id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  match store.admin_account(id).await.map_err(db_error)? {
+    Some(account) => Ok(reply::json(&account)),
+    None => Err(reject::custom(AppError::AccountNotFound)),
+  }
+}
+
+pub async fn suspend(admin: Session, id: i32, store: Store, body: Suspension) -> Result<impl Reply, Rejection> {
+  not_self(&admin, id)?;
+  let reason = body.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+  if !store.set_suspended(id, true, reason.clone()).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::AccountNotFound));
+  }
+
+  let detail = format!("suspended: {}", reason.as_deref().unwrap_or("no reason given"));
+  add_audit(&store, admin_entry(audit::ADMIN, &admin, id, detail)).await;
+  Ok(reply::with_status("Suspended", StatusCode::OK))
+}
+
+pub async fn unsuspend(admin: Session, id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  if !store.set_suspended(id, false, None).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::AccountNotFound));
+  }
+
+  add_audit(&store, admin_entry(audit::ADMIN, &admin, id, "unsuspended".to_string())).await;
+  Ok(reply::with_status("Unsuspended", StatusCode::OK))
+}
+
+pub async fn set_role(admin: Session, id: i32, store: Store, body: RoleChange) -> Result<impl Reply, Rejection> {
+  // an admin demoting itself could leave nobody to undo it
+  not_self(&admin, id)?;
+  if !store.set_role(id, body.role).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::AccountNotFound));
+  }
+
+  let detail = format!("role set to {}", body.role.as_str());
+  add_audit(&store, admin_entry(audit::ADMIN, &admin, id, detail)).await;
+  Ok(reply::with_status("Role changed", StatusCode::OK))
+}
+
+/// Logs the account out everywhere and mails it a reset link, logins are refused until it is used
+pub async fn force_password_reset(
+  admin: Session,
+  id: i32,
+  store: Store,
+  conf: AuthConfig,
+  outbox: Outbox,
+) -> Result<impl Reply, Rejection> {
+  let email = store
+    .force_password_reset(id)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::AccountNotFound))?;
+
+  let token = issue_mail_token(&store, id, TokenPurpose::ResetPassword, conf.email.reset_ttl_secs)
+    .await
+    .map_err(db_error)?;
+  outbox.send_password_reset(&email, &token);
+
+  add_audit(&store, admin_entry(audit::ADMIN, &admin, id, "password reset forced".to_string())).await;
+  Ok(reply::with_status("Password reset forced", StatusCode::OK))
+}
+
+/// Short session acting as the user, to reproduce what support is told about.
+/// It cannot manage the account's credentials nor reach admin routes.
+pub async fn impersonate(
+  admin: Session,
+  id: i32,
+  store: Store,
+  conf: AuthConfig,
+  body: Impersonation,
+) -> Result<impl Reply, Rejection> {
+  not_self(&admin, id)?;
+  let reason = body.reason.trim().to_string();
+  if reason.is_empty() {
+    return Err(reject::custom(AppError::Validation(field_errors("reason", ["is required"]))));
+  }
+
+  let status = store
+    .account_status(id)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::AccountNotFound))?;
+  if status.suspended {
+    return Err(reject::custom(AppError::AccountSuspended));
+  }
+
+  let issued = issue_impersonation_token(AccountId(id), admin.id.unwrap_or_default(), conf.session.impersonation_ttl_secs);
+  add_audit(&store, admin_entry(audit::IMPERSONATE, &admin, id, reason)).await;
+  // always in the body, a cookie would replace the admin's own session
+  Ok(reply::json(&LoginResponse {
+    token: Some(issued.token),
+    token_type: Some("Bearer"),
+    expires_at: issued.expires_at,
+    csrf_token: None,
+  }))
+}
+
+fn not_self(admin: &Session, id: i32) -> Result<(), Rejection> {
+  if admin.id == Some(id) {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "id",
+      ["cannot be your own account"],
+    ))));
+  }
+  Ok(())
+}
+
+fn admin_entry(action: &'static str, admin: &Session, account_id: i32, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action,
+    account_id: Some(account_id),
+    actor_id: admin.id,
+    success: true,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
@@ -2,19 +2,55 @@ use std::collections::HashMap;
 
 use error_handler::AppError;
 use tracing::{error, info};
-use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+use warp::{http::{header::ETAG, StatusCode}, reject, reply, Rejection, Reply};
 
-use crate::store::Store;
+use crate::{
+    logging, metrics,
+    profanity::censor,
+    reputation::ReputationConfig,
+    routes::{auth::{db_error, is_admin}, questions::check_unlocked},
+    store::Store,
+    types::{
+        account::Session,
+        answer::{AddAnswerOutcome, AnswerPatch},
+        moderation::QuestionState,
+        post::{self, etag, PostTarget, UpdateOutcome},
+        reputation::Privilege,
+    },
+    validation::validate_post,
+};
 
-pub async fn add_a(store: Store, body: HashMap<String, String>) -> Result<impl Reply, Rejection> {
-    info!("{:?}", body);
+/// Closed and locked questions take no answers, protected ones only from accounts with the `AnswerProtected` privilege
+pub async fn add_a(
+    session: Session,
+    store: Store,
+    conf: ReputationConfig,
+    body: HashMap<String, String>,
+) -> Result<impl Reply, Rejection> {
+    info!(
+        "Add answer to q {:?}: {}",
+        body.get("qid"),
+        logging::user_content(body.get("content").map_or("", |c| c.as_str()))
+    );
     let id = match get_qid(&body) {
         Err(_e) => return Err(reject::custom(AppError::QuestionNotFound)),
         Ok(id) => id,
     };
 
-    match store.add_a(id, body.get("content").unwrap().clone()).await {
-        Ok(_) => Ok(reply::with_status("Added", StatusCode::CREATED)),
+    let content = body.get("content").ok_or(reject::custom(AppError::MissingParams))?;
+    let standing = store.account_standing(session.id.unwrap_or_default()).await.map_err(db_error)?;
+    let answer_protected = conf.grants(Privilege::AnswerProtected, standing);
+    match store.add_a(id, content.clone(), session.id, answer_protected).await {
+        Ok(AddAnswerOutcome::Added) => {
+            metrics::ANSWERS_CREATED.inc();
+            Ok(reply::with_status("Added", StatusCode::CREATED))
+        }
+        Ok(AddAnswerOutcome::QuestionNotFound) => Err(reject::custom(AppError::QuestionNotFound)),
+        Ok(AddAnswerOutcome::Refused(QuestionState::Closed)) => Err(reject::custom(AppError::QuestionClosed)),
+        Ok(AddAnswerOutcome::Refused(QuestionState::Locked)) => Err(reject::custom(AppError::QuestionLocked)),
+        Ok(AddAnswerOutcome::Refused(_)) => Err(reject::custom(AppError::InsufficientReputation {
+            required: conf.required(Privilege::AnswerProtected),
+        })),
         Err(e) => {
             error!("Failed to add ans: {:?}", e);
             Err(reject::custom(AppError::DbQueryError))
@@ -22,6 +58,72 @@ pub async fn add_a(store: Store, body: HashMap<String, String>) -> Result<impl R
     }
 }
 
+pub async fn answer(id: i32, store: Store) -> Result<impl Reply, Rejection> {
+    match store.answer(id).await.map_err(db_error)? {
+        Some(a) => {
+            let version = a.version;
+            Ok(reply::with_header(reply::json(&a), ETAG, etag(version)))
+        }
+        None => Err(reject::custom(AppError::AnswerNotFound)),
+    }
+}
+
+/// `PATCH /a/{id}`, for the author or an admin. Honours `If-Match` like `questions::patch_q`
+pub async fn patch_a(
+    id: i32,
+    session: Session,
+    store: Store,
+    if_match: Option<String>,
+    mut patch: AnswerPatch,
+) -> Result<impl Reply, Rejection> {
+    let expected = post::if_match(if_match.as_deref()).map_err(reject::custom)?;
+    check_editor(&store, &session, id, false).await?;
+    let errors = validate_post(None, patch.content.as_deref());
+    if !errors.is_empty() {
+        return Err(reject::custom(AppError::Validation(errors)));
+    }
+    patch.content = censor(patch.content.take()).await.map_err(reject::custom)?;
+    match store.upd_a(id, patch, expected).await.map_err(db_error)? {
+        UpdateOutcome::Updated(a) => {
+            let version = a.version;
+            Ok(reply::with_header(reply::json(&a), ETAG, etag(version)))
+        }
+        UpdateOutcome::NotFound => Err(reject::custom(AppError::AnswerNotFound)),
+        UpdateOutcome::Stale { current } => Err(reject::custom(AppError::PreconditionFailed { current })),
+        UpdateOutcome::Locked => Err(reject::custom(AppError::QuestionLocked)),
+    }
+}
+
+/// Soft deletion by the author or an admin, like `questions::del_q`
+pub async fn del_a(id: i32, session: Session, store: Store) -> Result<impl Reply, Rejection> {
+    check_editor(&store, &session, id, true).await?;
+    if !store.delete_post(PostTarget::Answer(id), session.id).await.map_err(db_error)? {
+        return Err(reject::custom(AppError::AnswerNotFound));
+    }
+    Ok(reply::with_status(format!("{id} has been deleted"), StatusCode::ACCEPTED))
+}
+
+/// Refuses everybody but the author of answer `id` and admins, and everybody once its question is locked.
+/// With `admin_over_lock` admins still get through on a locked question
+async fn check_editor(store: &Store, session: &Session, id: i32, admin_over_lock: bool) -> Result<(), Rejection> {
+    let answer = store
+        .answer(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| reject::custom(AppError::AnswerNotFound))?;
+    let admin = is_admin(store, session).await?;
+    if answer.account_id != session.id && !admin {
+        return Err(reject::custom(AppError::Forbidden));
+    }
+    if admin && admin_over_lock {
+        return Ok(());
+    }
+    match store.question(answer.qid.0 as i32).await.map_err(db_error)? {
+        Some(question) => check_unlocked(&question),
+        None => Err(reject::custom(AppError::AnswerNotFound)),
+    }
+}
+
 fn get_qid(body: &HashMap<String, String>) -> std::io::Result<i32> {
     let id = body.get("qid").ok_or(std::io::Error::new(
         std::io::ErrorKind::InvalidData,
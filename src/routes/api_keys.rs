@@ -0,0 +1,85 @@
+use error_handler::AppError;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::auth::{add_audit, db_error},
+  store::Store,
+  types::{
+    account::Session,
+    api_key::{CreatedApiKey, NewApiKey, Scope, KEY_PREFIX},
+    audit::{self, NewAuditEntry},
+  },
+  utils::{hash_token, new_token},
+  validation::field_errors,
+};
+
+/// Length of the key start kept in clear, the prefix and 8 random characters
+const SHOWN_LEN: usize = KEY_PREFIX.len() + 8;
+
+pub async fn create(session: Session, store: Store, body: NewApiKey) -> Result<impl Reply, Rejection> {
+  let account_id = session.id.unwrap_or_default();
+  let name = body.name.trim();
+
+  let mut errors = vec![];
+  if name.is_empty() || name.chars().count() > 100 {
+    errors.extend(field_errors("name", ["must be 1 to 100 characters"]));
+  }
+  if body.scopes.is_empty() {
+    errors.extend(field_errors("scopes", ["must not be empty"]));
+  }
+  let mut scopes: Vec<Scope> = vec![];
+  for s in &body.scopes {
+    match s.parse::<Scope>() {
+      Ok(scope) if !scopes.contains(&scope) => scopes.push(scope),
+      Ok(_) => {}
+      Err(e) => errors.extend(field_errors("scopes", [e])),
+    }
+  }
+  if !errors.is_empty() {
+    return Err(reject::custom(AppError::Validation(errors)));
+  }
+
+  let (secret, _) = new_token();
+  let key = format!("{}{}", KEY_PREFIX, secret);
+  let info = store
+    .add_api_key(account_id, name, &key[..SHOWN_LEN], &hash_token(&key), &scopes)
+    .await
+    .map_err(db_error)?;
+
+  add_audit(&store, key_entry(account_id, format!("created {} ({})", info.id, info.name))).await;
+  Ok(reply::with_status(
+    reply::json(&CreatedApiKey { info, key }),
+    StatusCode::CREATED,
+  ))
+}
+
+pub async fn list(session: Session, store: Store) -> Result<impl Reply, Rejection> {
+  let keys = store
+    .find_api_keys(session.id.unwrap_or_default())
+    .await
+    .map_err(db_error)?;
+  Ok(reply::json(&keys))
+}
+
+pub async fn revoke(session: Session, id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  let account_id = session.id.unwrap_or_default();
+  if !store.revoke_api_key(account_id, id).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "id",
+      ["is not an active key of yours"],
+    ))));
+  }
+
+  add_audit(&store, key_entry(account_id, format!("revoked {}", id))).await;
+  Ok(reply::with_status("Revoked", StatusCode::OK))
+}
+
+fn key_entry(account_id: i32, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action: audit::API_KEY,
+    account_id: Some(account_id),
+    success: true,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
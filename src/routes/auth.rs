@@ -2,29 +2,75 @@ use chrono::prelude::*;
 use chrono::Duration;
 use error_handler::AppError;
 use paseto::PasetoBuilder;
+use serde::Deserialize;
 use std::future;
+use std::sync::Arc;
 use tracing::{error, info};
-use warp::http::StatusCode;
+use warp::http::header::{HeaderValue, SET_COOKIE};
+use warp::http::{Method, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
 use warp::{reject, reply, Filter, Rejection, Reply};
 
-use crate::types::account::{AccountId, Session};
-use crate::utils::{check_password, hash_password};
+use crate::mail::Outbox;
+use crate::metrics;
+use crate::reputation::ReputationConfig;
+use crate::totp::TotpConfig;
+use crate::types::account::{
+    AccountId, LoginResponse, MfaChallenge, PasswordReset, PasswordResetRequest, Role, Session, TokenPurpose,
+    VerifyEmail,
+};
+use crate::types::api_key::{Scope, KEY_PREFIX};
+use crate::types::audit::{self, NewAuditEntry};
+use crate::types::reputation::Privilege;
+use crate::utils::{check_password, hash_password, hash_password_blocking, hash_token, mask_email, new_token, HashConfig};
+use crate::validation::{field_errors, normalize_email, validate_email, PasswordPolicy};
 use crate::{store::Store, types::account::Account};
 
 const SECRET: &str = "RANDOM WORDS WINTER MACINTOSH PC";
 
-pub async fn register(store: Store, account: Account) -> Result<impl Reply, Rejection> {
-    let hashed = hash_password(account.password);
+pub async fn register(
+    store: Store,
+    conf: AuthConfig,
+    outbox: Outbox,
+    account: Account,
+) -> Result<impl Reply, Rejection> {
+    let email = normalize_email(&account.email);
+
+    let mut errors = match validate_email(&email) {
+        Ok(_) => vec![],
+        Err(msg) => field_errors("email", [msg]),
+    };
+    errors.extend(field_errors("password", conf.password.check(&account.password, &email)));
+    if errors.is_empty() && store.find_account(email.clone()).await.map_err(db_error)?.is_some() {
+        errors.extend(field_errors("email", ["is already registered"]));
+    }
+    if !errors.is_empty() {
+        return Err(reject::custom(AppError::Validation(errors)));
+    }
+
     let account = Account {
         id: None,
-        email: account.email,
-        password: hashed,
+        email: email.clone(),
+        password: hash_password(account.password, &conf.hashing).await,
     };
 
-    info!("Register new user {:#?}", account);
+    info!("Register new user {:?}", account);
 
     match store.add_account(account).await {
-        Ok(_) => Ok(reply::with_status("Created", StatusCode::CREATED)),
+        Ok(id) => {
+            metrics::ACCOUNTS_REGISTERED.inc();
+            // a lost mail is recovered through a password reset, which verifies the email as well
+            match issue_mail_token(&store, id, TokenPurpose::VerifyEmail, conf.email.verify_ttl_secs).await {
+                Ok(token) => outbox.send_verification(&email, &token),
+                Err(e) => error!("Failed to issue email verification token: {:?}", e),
+            }
+            Ok(reply::with_status("Created", StatusCode::CREATED))
+        }
+        // lost a race against another registration of the same email
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(reject::custom(
+            AppError::Validation(field_errors("email", ["is already registered"])),
+        )),
         Err(e) => {
             error!("Failed to add account: {e:#?}");
             Err(reject::custom(AppError::DbQueryError))
@@ -32,40 +78,526 @@ pub async fn register(store: Store, account: Account) -> Result<impl Reply, Reje
     }
 }
 
-pub async fn login(store: Store, account: Account) -> Result<impl Reply, Rejection> {
-    let store_acc = match store.find_account(account.email).await {
-        Err(_e) => return Err(reject::custom(AppError::DbQueryError)),
-        Ok(a) => a,
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LockoutConfig {
+    /// Failed attempts in a row before an email gets locked
+    pub max_failures: i32,
+    /// A failure streak starts over this long after its last failure or the end of its lockout, whichever is later
+    pub failure_window_secs: i64,
+    /// First lockout duration, doubled by every failure past `max_failures`
+    pub base_lockout_secs: i64,
+    pub max_lockout_secs: i64,
+    /// Failed attempts from one ip, on any email, before the ip gets locked
+    pub ip_max_failures: i64,
+    pub ip_window_secs: i64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            max_failures: 5,
+            failure_window_secs: 900,
+            base_lockout_secs: 30,
+            max_lockout_secs: 3600,
+            ip_max_failures: 50,
+            ip_window_secs: 900,
+        }
+    }
+}
+
+impl LockoutConfig {
+    /// Lockout duration after `failures` failed attempts in a row, if any
+    pub fn lockout_secs(&self, failures: i32) -> Option<i64> {
+        if failures < self.max_failures {
+            return None;
+        }
+        let exp = (failures - self.max_failures).min(30) as u32;
+        Some(
+            self.base_lockout_secs
+                .saturating_mul(2i64.saturating_pow(exp))
+                .min(self.max_lockout_secs),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct EmailConfig {
+    /// Refuse to log in accounts which have not followed their verification link
+    pub require_verified: bool,
+    pub verify_ttl_secs: i64,
+    pub reset_ttl_secs: i64,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            require_verified: true,
+            verify_ttl_secs: 86400,
+            reset_ttl_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameSite {
+    #[default]
+    Strict,
+    Lax,
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub ttl_secs: i64,
+    /// Hand sessions out as an HttpOnly cookie instead of in the login response, for browser frontends.
+    /// Requests authenticated by the cookie must echo the CSRF token in `X-CSRF-Token`.
+    pub cookie: bool,
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub domain: Option<String>,
+    /// Lifetime of the tokens admins get to act as a user, see `routes::admin::impersonate`
+    pub impersonation_ttl_secs: i64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            ttl_secs: 86400,
+            cookie: false,
+            secure: true,
+            same_site: SameSite::default(),
+            domain: None,
+            impersonation_ttl_secs: 900,
+        }
+    }
+}
+
+impl SessionConfig {
+    fn cookie(&self, name: &str, value: &str, max_age: i64, http_only: bool) -> String {
+        let mut cookie = format!(
+            "{}={}; Path=/; Max-Age={}; SameSite={:?}",
+            name, value, max_age, self.same_site
+        );
+        if let Some(domain) = &self.domain {
+            cookie.push_str(&format!("; Domain={}", domain));
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        cookie
+    }
+}
+
+pub const SESSION_COOKIE: &str = "session";
+/// Readable by the frontend, which sends it back in `CSRF_HEADER`
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub lockout: LockoutConfig,
+    pub email: EmailConfig,
+    pub session: SessionConfig,
+    pub totp: TotpConfig,
+    pub password: PasswordPolicy,
+    pub hashing: HashConfig,
+    // Unknown emails are verified against this hash so that they cost as much as a wrong password
+    #[serde(skip)]
+    dummy_hash: Arc<String>,
+}
+
+impl AuthConfig {
+    /// Loads the breached password list and prepares the dummy hash with the configured parameters
+    pub fn init(mut self) -> std::io::Result<Self> {
+        self.password = self.password.load()?;
+        self.dummy_hash = Arc::new(hash_password_blocking("not a real password", &self.hashing));
+        Ok(self)
+    }
+}
+
+pub async fn login(
+    store: Store,
+    conf: AuthConfig,
+    ip: String,
+    account: Account,
+) -> Result<Response, Rejection> {
+    let email = normalize_email(&account.email);
+    let entry = |account_id: Option<i32>, success: bool, detail: &str| NewAuditEntry {
+        action: audit::LOGIN,
+        account_id,
+        actor_id: None,
+        email: Some(email.clone()),
+        ip: Some(ip.clone()),
+        success,
+        detail: Some(detail.to_string()),
     };
 
-    if check_password(account.password, store_acc.password) {
-        Ok(reply::with_header(
-            "",
-            "Authorization",
-            issue_token(store_acc.id.unwrap()),
-        ))
-    } else {
-        Err(reject::custom(AppError::InvalidCredential))
+    if let Some(until) = store.login_locked_until(&email).await.map_err(db_error)? {
+        add_audit(&store, entry(None, false, audit::LOCKED)).await;
+        return Err(reject::custom(AppError::LoginLocked {
+            retry_after: (until - Utc::now()).num_seconds().max(1) as u64,
+        }));
+    }
+
+    let ip_failures = store
+        .ip_login_failures(&ip, conf.lockout.ip_window_secs)
+        .await
+        .map_err(db_error)?;
+    if ip_failures >= conf.lockout.ip_max_failures {
+        add_audit(&store, entry(None, false, audit::IP_LOCKED)).await;
+        return Err(reject::custom(AppError::LoginLocked {
+            retry_after: conf.lockout.ip_window_secs as u64,
+        }));
+    }
+
+    let (account_id, stored_hash) = match store.find_account(email.clone()).await.map_err(db_error)? {
+        Some(a) => (a.id.map(|id| id.0), a.password),
+        None => (None, conf.dummy_hash.to_string()),
+    };
+    let valid = check_password(account.password.clone(), stored_hash.clone()).await;
+
+    if let (Some(id), true) = (account_id, valid) {
+        // only told once the password is known to be right, not to give away the state of accounts
+        if let Some(status) = store.account_status(id).await.map_err(db_error)? {
+            if status.suspended {
+                add_audit(&store, entry(Some(id), false, "suspended")).await;
+                return Err(reject::custom(AppError::AccountSuspended));
+            }
+            if status.password_reset_required {
+                add_audit(&store, entry(Some(id), false, "password reset required")).await;
+                return Err(reject::custom(AppError::PasswordResetRequired));
+            }
+        }
+        if conf.email.require_verified && !store.email_verified(id).await.map_err(db_error)? {
+            add_audit(&store, entry(Some(id), false, "unverified email")).await;
+            return Err(reject::custom(AppError::EmailNotVerified));
+        }
+        if conf.hashing.needs_rehash(&stored_hash) {
+            let hashed = hash_password(account.password, &conf.hashing).await;
+            if let Err(e) = store.update_password(id, hashed).await {
+                error!("Failed to rehash password: {:?}", e);
+            }
+        }
+
+        let totp = store.totp_state(id).await.map_err(db_error)?;
+        if totp.is_some_and(|t| t.secret.is_some()) {
+            // failures are only cleared by the second factor, a known password does not buy more code guesses
+            add_audit(&store, entry(Some(id), true, "password, mfa pending")).await;
+            return Ok(reply::json(&MfaChallenge {
+                mfa_required: true,
+                mfa_token: issue_mfa_token(AccountId(id), conf.totp.mfa_token_ttl_secs),
+            })
+            .into_response());
+        }
+
+        if let Err(e) = store.reset_login_failures(&email).await {
+            error!("Failed to reset login failures: {:?}", e);
+        }
+        add_audit(&store, entry(Some(id), true, "password")).await;
+        return Ok(session_reply(&conf.session, AccountId(id)));
+    }
+
+    record_login_failure(&store, &conf, &email).await?;
+
+    let detail = if account_id.is_some() { "bad password" } else { "unknown email" };
+    add_audit(&store, entry(account_id, false, detail)).await;
+    Err(reject::custom(AppError::InvalidCredential))
+}
+
+pub async fn verify_email(store: Store, body: VerifyEmail) -> Result<impl Reply, Rejection> {
+    let id = store
+        .use_account_token(TokenPurpose::VerifyEmail, &hash_token(&body.token))
+        .await
+        .map_err(db_error)?
+        .ok_or_else(invalid_token)?;
+
+    store.verify_email(id).await.map_err(db_error)?;
+    add_audit(
+        &store,
+        NewAuditEntry {
+            action: audit::VERIFY_EMAIL,
+            account_id: Some(id),
+            success: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    Ok(reply::with_status("Verified", StatusCode::OK))
+}
+
+/// Mails a reset link when the email belongs to an account.
+/// The answer is the same either way so that it does not tell which emails are registered,
+/// the link is issued and sent in the background so that known emails take no longer to answer.
+pub async fn request_password_reset(
+    store: Store,
+    conf: AuthConfig,
+    outbox: Outbox,
+    ip: String,
+    body: PasswordResetRequest,
+) -> Result<impl Reply, Rejection> {
+    let email = normalize_email(&body.email);
+    let account_id = store
+        .find_account(email.clone())
+        .await
+        .map_err(db_error)?
+        .and_then(|a| a.id.map(|id| id.0));
+
+    if let Some(id) = account_id {
+        let store = store.clone();
+        let email = email.clone();
+        tokio::spawn(async move {
+            match issue_mail_token(&store, id, TokenPurpose::ResetPassword, conf.email.reset_ttl_secs).await {
+                Ok(token) => outbox.send_password_reset(&email, &token),
+                Err(e) => error!("Failed to issue reset token: {:?}", e),
+            }
+        });
+    }
+
+    add_audit(
+        &store,
+        NewAuditEntry {
+            action: audit::PASSWORD_RESET,
+            account_id,
+            actor_id: None,
+            email: Some(email),
+            ip: Some(ip),
+            success: account_id.is_some(),
+            detail: Some(if account_id.is_some() { "requested" } else { "unknown email" }.to_string()),
+        },
+    )
+    .await;
+    Ok(reply::with_status("Accepted", StatusCode::ACCEPTED))
+}
+
+pub async fn confirm_password_reset(
+    store: Store,
+    conf: AuthConfig,
+    ip: String,
+    body: PasswordReset,
+) -> Result<impl Reply, Rejection> {
+    let token_hash = hash_token(&body.token);
+
+    // Check the password before spending the token so that a rejected password can be retried
+    let email = store
+        .account_token_email(TokenPurpose::ResetPassword, &token_hash)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(invalid_token)?;
+    let errors = conf.password.check(&body.password, &email);
+    if !errors.is_empty() {
+        return Err(reject::custom(AppError::Validation(field_errors("password", errors))));
+    }
+
+    let id = store
+        .use_account_token(TokenPurpose::ResetPassword, &token_hash)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(invalid_token)?;
+
+    let email = store
+        .reset_password(id, hash_password(body.password, &conf.hashing).await)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(invalid_token)?;
+
+    if let Err(e) = store.revoke_account_tokens(id, TokenPurpose::ResetPassword).await {
+        error!("Failed to revoke reset tokens: {:?}", e);
+    }
+    if let Err(e) = store.reset_login_failures(&normalize_email(&email)).await {
+        error!("Failed to reset login failures: {:?}", e);
+    }
+
+    add_audit(
+        &store,
+        NewAuditEntry {
+            action: audit::PASSWORD_RESET,
+            account_id: Some(id),
+            actor_id: None,
+            email: Some(email),
+            ip: Some(ip),
+            success: true,
+            detail: Some("confirmed".to_string()),
+        },
+    )
+    .await;
+    Ok(reply::with_status("Password changed", StatusCode::OK))
+}
+
+pub(crate) async fn issue_mail_token(
+    store: &Store,
+    account_id: i32,
+    purpose: TokenPurpose,
+    ttl_secs: i64,
+) -> Result<String, sqlx::Error> {
+    let (token, hash) = new_token();
+    store.add_account_token(account_id, purpose, &hash, ttl_secs).await?;
+    Ok(token)
+}
+
+pub(crate) fn invalid_token() -> Rejection {
+    reject::custom(AppError::Validation(field_errors(
+        "token",
+        ["is invalid, used or expired"],
+    )))
+}
+
+/// Counts a failed attempt against `email`, locking it once the streak is long enough
+pub(crate) async fn record_login_failure(store: &Store, conf: &AuthConfig, email: &str) -> Result<(), Rejection> {
+    let failures = store
+        .add_login_failure(email, conf.lockout.failure_window_secs)
+        .await
+        .map_err(db_error)?;
+    if let Some(secs) = conf.lockout.lockout_secs(failures) {
+        info!("Lock login of {} for {}s after {} failures", mask_email(email), secs, failures);
+        store.lock_login(email, secs).await.map_err(db_error)?;
     }
+    Ok(())
 }
 
-fn issue_token(id: AccountId) -> String {
+/// Audit failures are logged but never fail the audited request
+pub(crate) async fn add_audit(store: &Store, entry: NewAuditEntry) {
+    if let Err(e) = store.add_audit(entry).await {
+        error!("Failed to write audit log: {:?}", e);
+    }
+}
+
+/// Whether the session may moderate posts of others, an impersonated admin does not lend its rights
+pub(crate) async fn is_admin(store: &Store, session: &Session) -> Result<bool, Rejection> {
+    if session.act.is_some() {
+        return Ok(false);
+    }
+    let role = store.account_role(session.id.unwrap_or_default()).await.map_err(db_error)?;
+    Ok(role == Some(Role::Admin))
+}
+
+pub(crate) fn db_error(e: sqlx::Error) -> Rejection {
+    error!("DB query failed: {:?}", e);
+    reject::custom(AppError::DbQueryError)
+}
+
+/// Session token with the CSRF token it is bound to
+pub(crate) struct IssuedToken {
+    pub token: String,
+    pub csrf_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub(crate) fn issue_token(id: AccountId, ttl_secs: i64) -> IssuedToken {
+    build_token(id, ttl_secs, None)
+}
+
+/// Session of `id` for the admin `actor`, every request made with it is logged with both
+pub(crate) fn issue_impersonation_token(id: AccountId, actor: i32, ttl_secs: i64) -> IssuedToken {
+    build_token(id, ttl_secs, Some(actor))
+}
+
+fn build_token(id: AccountId, ttl_secs: i64, act: Option<i32>) -> IssuedToken {
     let now = Utc::now();
-    let exp = now + Duration::days(1);
+    let exp = now + Duration::seconds(ttl_secs);
+    let (csrf_token, _) = new_token();
     // let state = serde_json::to_string(&id).expect("Failed to serialize");
-    PasetoBuilder::new()
+    let mut builder = PasetoBuilder::new();
+    let builder = builder
         .set_encryption_key(SECRET.as_bytes())
         .set_expiration(&exp)
         .set_not_before(&now)
         .set_claim("id", serde_json::json!(id))
+        .set_claim("csrf", serde_json::json!(csrf_token));
+    let builder = match act {
+        Some(actor) => builder.set_claim("act", serde_json::json!(actor)),
+        None => builder,
+    };
+    let token = builder.build().expect("Failed to create token");
+    // local_paseto(&state, None, b"RANDOM WORDS WINTER MACINTOSH PC").expect("Failed to create token")
+    IssuedToken {
+        token,
+        csrf_token,
+        expires_at: exp,
+    }
+}
+
+/// Answer of a completed login, the token goes in the body or in cookies depending on `conf.cookie`
+pub(crate) fn session_reply(conf: &SessionConfig, id: AccountId) -> Response {
+    let issued = issue_token(id, conf.ttl_secs);
+    if !conf.cookie {
+        return reply::json(&LoginResponse {
+            token: Some(issued.token),
+            token_type: Some("Bearer"),
+            expires_at: issued.expires_at,
+            csrf_token: None,
+        })
+        .into_response();
+    }
+
+    let mut res = reply::json(&LoginResponse {
+        token: None,
+        token_type: None,
+        expires_at: issued.expires_at,
+        csrf_token: Some(issued.csrf_token.clone()),
+    })
+    .into_response();
+    set_cookies(
+        &mut res,
+        [
+            conf.cookie(SESSION_COOKIE, &issued.token, conf.ttl_secs, true),
+            conf.cookie(CSRF_COOKIE, &issued.csrf_token, conf.ttl_secs, false),
+        ],
+    );
+    res
+}
+
+/// Voids every login session of the account and drops the session cookies.
+/// Impersonation and API key sessions only lose their cookies, they must not log the account out.
+pub async fn logout(session: Session, store: Store, conf: AuthConfig) -> Result<Response, Rejection> {
+    if session.api_key.is_none() && session.act.is_none() {
+        let id = session.id.unwrap_or_default();
+        store.revoke_sessions(id, Utc::now()).await.map_err(db_error)?;
+        info!("Sessions of account {} revoked on logout", id);
+    }
+
+    let mut res = reply::with_status("Logged out", StatusCode::OK).into_response();
+    set_cookies(
+        &mut res,
+        [
+            conf.session.cookie(SESSION_COOKIE, "", 0, true),
+            conf.session.cookie(CSRF_COOKIE, "", 0, false),
+        ],
+    );
+    Ok(res)
+}
+
+fn set_cookies(res: &mut Response, cookies: [String; 2]) {
+    for cookie in cookies {
+        res.headers_mut().append(
+            SET_COOKIE,
+            HeaderValue::from_str(&cookie).expect("Invalid cookie"),
+        );
+    }
+}
+
+/// Short-lived token proving the password of `id`, only good for the second login step
+pub(crate) fn issue_mfa_token(id: AccountId, ttl_secs: i64) -> String {
+    let now = Utc::now();
+    PasetoBuilder::new()
+        .set_encryption_key(SECRET.as_bytes())
+        .set_expiration(&(now + Duration::seconds(ttl_secs)))
+        .set_not_before(&now)
+        .set_claim("id", serde_json::json!(id))
+        .set_claim("mfa", serde_json::json!(true))
         .build()
         .expect("Failed to create token")
-    // local_paseto(&state, None, b"RANDOM WORDS WINTER MACINTOSH PC").expect("Failed to create token")
 }
 
-fn verify_token(token: String) -> Result<Session, AppError> {
-    let json = paseto::tokens::validate_local_token(
-        token.as_str(),
+fn decrypt_token(token: &str) -> Result<serde_json::Value, AppError> {
+    paseto::tokens::validate_local_token(
+        token,
         None,
         SECRET.as_bytes(),
         &paseto::tokens::TimeBackend::Chrono,
@@ -73,7 +605,15 @@ fn verify_token(token: String) -> Result<Session, AppError> {
     .map_err(|e| {
         error!("Failed to decrypt token: {:?}", e);
         AppError::InvalidToken
-    })?;
+    })
+}
+
+fn verify_token(token: String) -> Result<Session, AppError> {
+    let json = decrypt_token(&token)?;
+    // a pending login must not pass for a session
+    if json.get("mfa").is_some() {
+        return Err(AppError::InvalidToken);
+    }
 
     serde_json::from_value::<Session>(json).map_err(|e| {
         error!("Failed to parse token: {:?}", e);
@@ -81,33 +621,288 @@ fn verify_token(token: String) -> Result<Session, AppError> {
     })
 }
 
+/// Account of a token made by `issue_mfa_token`
+pub(crate) fn verify_mfa_token(token: &str) -> Result<i32, AppError> {
+    let json = decrypt_token(token)?;
+    if json.get("mfa") != Some(&serde_json::Value::Bool(true)) {
+        return Err(AppError::InvalidToken);
+    }
+    json.get("id").and_then(|id| id.as_i64()).map(|id| id as i32).ok_or(AppError::InvalidToken)
+}
+
 pub type One<T> = (T,);
 
-pub(crate) fn auth() -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
-    warp::header::<String>("Authorization").and_then(|token: String| {
-        let session = match verify_token(token) {
-            Ok(session) => session,
-            Err(_) => return future::ready(Err(warp::reject::custom(AppError::InvalidToken))),
-        };
-        future::ready(Ok(session))
+/// Where a request put its credential
+#[derive(Debug, PartialEq)]
+enum Credential {
+    ApiKey(String),
+    Token(String),
+    Cookie(String),
+}
+
+/// Picks the credential of a request, `None` when there is none or `Authorization` is not a `Bearer` one
+fn credential(
+    authorization: Option<String>,
+    api_key: Option<String>,
+    cookie: Option<String>,
+) -> Option<Credential> {
+    if let Some(key) = api_key {
+        return Some(Credential::ApiKey(key.trim().to_string()));
+    }
+    if let Some(value) = authorization {
+        let (scheme, value) = value.trim().split_once(' ')?;
+        let value = value.trim();
+        if !scheme.eq_ignore_ascii_case("bearer") || value.is_empty() {
+            return None;
+        }
+        return Some(if value.starts_with(KEY_PREFIX) {
+            Credential::ApiKey(value.to_string())
+        } else {
+            Credential::Token(value.to_string())
+        });
+    }
+    cookie.map(Credential::Cookie)
+}
+
+// Compares in constant time so that the CSRF token cannot be guessed byte by byte
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Cookies are sent by the browser on its own, state-changing requests must prove
+/// they come from our frontend by echoing the CSRF token bound to the session
+fn check_csrf(session: &Session, method: &Method, header: Option<&str>) -> Result<(), AppError> {
+    if method.is_safe() {
+        return Ok(());
+    }
+    match (&session.csrf, header) {
+        (Some(expected), Some(given)) if same_token(expected, given) => Ok(()),
+        _ => Err(AppError::Forbidden),
+    }
+}
+
+/// Extracts the session of a login token or an API key, from `Authorization: Bearer`,
+/// `X-Api-Key` or the session cookie, and refuses it when its account may not be used anymore
+pub(crate) fn auth(store: Store) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    authenticate(store.clone())
+        .and(warp::method())
+        .and(warp::path::full())
+        .and_then(move |session: Session, method: Method, path: FullPath| {
+            let store = store.clone();
+            async move {
+                let session = check_account(&store, session).await?;
+                if let Some(actor) = session.act {
+                    check_actor(&store, &session, actor).await?;
+                    info!(actor, account = session.id, "Request made by impersonating admin");
+                    if method != Method::GET {
+                        add_audit(
+                            &store,
+                            NewAuditEntry {
+                                action: audit::IMPERSONATED_REQUEST,
+                                account_id: session.id,
+                                actor_id: Some(actor),
+                                success: true,
+                                detail: Some(format!("{} {}", method, path.as_str())),
+                                ..Default::default()
+                            },
+                        )
+                        .await;
+                    }
+                }
+                Ok::<_, Rejection>(session)
+            }
+        })
+}
+
+/// Session of the credential of a request, the account itself is not looked at
+fn authenticate(store: Store) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    warp::header::optional::<String>("Authorization")
+        .and(warp::header::optional::<String>("X-Api-Key"))
+        .and(warp::cookie::optional::<String>(SESSION_COOKIE))
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .and(warp::method())
+        .and_then(
+            move |authorization: Option<String>,
+                  api_key: Option<String>,
+                  cookie: Option<String>,
+                  csrf: Option<String>,
+                  method: Method| {
+                let store = store.clone();
+                async move {
+                    match credential(authorization, api_key, cookie) {
+                        Some(Credential::Token(token)) => verify_token(token).map_err(reject::custom),
+                        Some(Credential::Cookie(token)) => {
+                            let session = verify_token(token).map_err(reject::custom)?;
+                            check_csrf(&session, &method, csrf.as_deref()).map_err(reject::custom)?;
+                            Ok(session)
+                        }
+                        Some(Credential::ApiKey(key)) => verify_api_key(&store, &key).await,
+                        None => Err(reject::custom(AppError::InvalidToken)),
+                    }
+                }
+            },
+        )
+}
+
+/// Suspension applies at once, a password reset voids the tokens issued before it
+async fn check_account(store: &Store, session: Session) -> Result<Session, Rejection> {
+    let id = session.id.unwrap_or_default();
+    let status = store
+        .account_status(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| reject::custom(AppError::InvalidToken))?;
+
+    if status.suspended {
+        return Err(reject::custom(AppError::AccountSuspended));
+    }
+    // API keys are only revoked by a forced reset
+    if session.api_key.is_none() && status.sessions_valid_after.is_some_and(|after| session.nbf < after) {
+        return Err(reject::custom(AppError::InvalidToken));
+    }
+    Ok(session)
+}
+
+/// Impersonation ends as soon as the admin behind it is suspended, demoted or has its sessions voided
+async fn check_actor(store: &Store, session: &Session, actor: i32) -> Result<(), Rejection> {
+    let status = store.account_status(actor).await.map_err(db_error)?;
+    let role = store.account_role(actor).await.map_err(db_error)?;
+    match (status, role) {
+        (Some(status), Some(Role::Admin))
+            if !status.suspended && status.sessions_valid_after.is_none_or(|after| session.nbf >= after) =>
+        {
+            Ok(())
+        }
+        _ => Err(reject::custom(AppError::InvalidToken)),
+    }
+}
+
+async fn verify_api_key(store: &Store, key: &str) -> Result<Session, Rejection> {
+    if !key.starts_with(KEY_PREFIX) {
+        return Err(reject::custom(AppError::InvalidToken));
+    }
+    match store.use_api_key(&hash_token(key)).await.map_err(db_error)? {
+        Some((account_id, grant)) => Ok(Session {
+            id: Some(account_id),
+            // keys are valid until revoked
+            exp: DateTime::<Utc>::MAX_UTC,
+            nbf: DateTime::<Utc>::MIN_UTC,
+            csrf: None,
+            act: None,
+            api_key: Some(grant),
+        }),
+        None => Err(reject::custom(AppError::InvalidToken)),
+    }
+}
+
+/// Like `auth` but API keys need `scope` to get through
+pub(crate) fn scoped(store: Store, scope: Scope) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    auth(store).and_then(move |session: Session| {
+        future::ready(if session.allows(scope) {
+            Ok(session)
+        } else {
+            Err(reject::custom(AppError::Forbidden))
+        })
+    })
+}
+
+/// Like `scoped` but also needs the reputation `conf` sets for `privilege`, admins get through regardless
+pub(crate) fn privileged(
+    store: Store,
+    scope: Scope,
+    privilege: Privilege,
+    conf: &ReputationConfig,
+) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    let conf = conf.clone();
+    scoped(store.clone(), scope).and_then(move |session: Session| {
+        let store = store.clone();
+        let conf = conf.clone();
+        async move {
+            match store.account_standing(session.id.unwrap_or_default()).await {
+                Ok(standing) if conf.grants(privilege, standing) => Ok(session),
+                Ok(_) => Err(reject::custom(AppError::InsufficientReputation {
+                    required: conf.required(privilege),
+                })),
+                Err(e) => Err(db_error(e)),
+            }
+        }
+    })
+}
+
+/// Like `auth` but refuses API keys and impersonating admins, for managing the account and its credentials
+pub(crate) fn login_session(store: Store) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    auth(store).and_then(|session: Session| {
+        future::ready(match (&session.api_key, session.act) {
+            (None, None) => Ok(session),
+            _ => Err(reject::custom(AppError::Forbidden)),
+        })
+    })
+}
+
+/// Like `auth` but only lets sessions of admin accounts through.
+/// The role is read on every request so that demoting an admin takes effect at once.
+pub(crate) fn admin(store: Store) -> impl Filter<Extract = One<Session>, Error = Rejection> + Clone {
+    scoped(store.clone(), Scope::Admin).and_then(move |session: Session| {
+        let store = store.clone();
+        async move {
+            // an impersonated admin does not lend its rights
+            if session.act.is_some() {
+                return Err(reject::custom(AppError::Forbidden));
+            }
+            match store.account_role(session.id.unwrap_or_default()).await {
+                Ok(Some(Role::Admin)) => Ok(session),
+                Ok(_) => Err(reject::custom(AppError::Forbidden)),
+                Err(e) => Err(db_error(e)),
+            }
+        }
     })
 }
 
 #[cfg(test)]
 mod auth_tests {
     use error_handler::AppError;
+    use sqlx::postgres::PgPoolOptions;
 
     use crate::{
-        routes::auth::{auth, issue_token},
-        types::account::AccountId,
+        routes::auth::{
+            authenticate, credential, issue_impersonation_token, issue_mfa_token, issue_token, login,
+            record_login_failure, verify_mfa_token, AuthConfig, Credential, LockoutConfig, CSRF_HEADER,
+        },
+        store::{Store, MIGRATOR},
+        types::account::{Account, AccountId},
     };
+    use warp::Rejection;
+
+    // Never connects, login tokens are checked without the database
+    fn store() -> Store {
+        Store {
+            pool: PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/unused")
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_lockout_secs() {
+        let conf = LockoutConfig {
+            max_failures: 3,
+            base_lockout_secs: 30,
+            max_lockout_secs: 100,
+            ..Default::default()
+        };
+        assert_eq!(conf.lockout_secs(2), None);
+        assert_eq!(conf.lockout_secs(3), Some(30));
+        assert_eq!(conf.lockout_secs(4), Some(60));
+        assert_eq!(conf.lockout_secs(5), Some(100));
+        assert_eq!(conf.lockout_secs(500), Some(100));
+    }
 
     #[tokio::test]
     async fn test_auth() {
-        let token = issue_token(AccountId(2));
-        let filter = auth();
+        let token = issue_token(AccountId(2), 60).token;
+        let filter = authenticate(store());
         let res = warp::test::request()
-            .header("Authorization", token)
+            .header("Authorization", format!("Bearer {}", token))
             .filter(&filter)
             .await;
 
@@ -115,11 +910,160 @@ mod auth_tests {
         assert_eq!(res.unwrap().id.unwrap(), 2);
     }
 
+    // Needs a database, skipped unless TEST_DB_URL is set
+    #[tokio::test]
+    async fn test_ip_lockout_not_extended_by_retries() {
+        let Ok(url) = std::env::var("TEST_DB_URL") else {
+            return;
+        };
+        let store = Store::new(&url).await;
+        MIGRATOR.run(&store.pool).await.unwrap();
+
+        let mut conf = AuthConfig::default();
+        conf.lockout.ip_max_failures = 3;
+        let ip = format!("198.51.100.{}", rand::random::<u8>());
+        let attempt = |n: u32| {
+            login(
+                store.clone(),
+                conf.clone(),
+                ip.clone(),
+                Account {
+                    id: None,
+                    email: format!("nobody-{}-{}@example.com", ip, n),
+                    password: "wrong".to_string(),
+                },
+            )
+        };
+        fn error<T>(res: Result<T, Rejection>) -> String {
+            res.err().unwrap().find::<AppError>().unwrap().to_string()
+        }
+
+        for n in 0..3 {
+            assert_eq!(error(attempt(n).await), AppError::InvalidCredential.to_string());
+        }
+        // every refused retry would otherwise count as one more failure and keep the window sliding
+        for n in 3..6 {
+            assert!(error(attempt(n).await).starts_with("Too many failed logins"));
+        }
+        assert_eq!(store.ip_login_failures(&ip, conf.lockout.ip_window_secs).await.unwrap(), 3);
+    }
+
+    // Needs a database, skipped unless TEST_DB_URL is set
+    #[tokio::test]
+    async fn test_lockout_escalates_past_window() {
+        let Ok(url) = std::env::var("TEST_DB_URL") else {
+            return;
+        };
+        let store = Store::new(&url).await;
+        MIGRATOR.run(&store.pool).await.unwrap();
+
+        let conf = AuthConfig {
+            lockout: LockoutConfig {
+                max_failures: 3,
+                failure_window_secs: 60,
+                base_lockout_secs: 30,
+                max_lockout_secs: 200,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let email = format!("streak-{}@example.com", rand::random::<u32>());
+        for _ in 0..2 {
+            record_login_failure(&store, &conf, &email).await.unwrap();
+        }
+
+        let mut lockouts = vec![];
+        for _ in 0..5 {
+            record_login_failure(&store, &conf, &email).await.unwrap();
+            // the next attempt comes right when the lockout ends, longer than the window after the last failure
+            let secs = sqlx::query_scalar::<_, f64>(
+                "UPDATE login_throttle SET last_failure_at = now() - (locked_until - last_failure_at), locked_until = now()
+                    WHERE email = $1
+                    RETURNING extract(epoch FROM now() - last_failure_at)::float8",
+            )
+            .bind(&email)
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+            lockouts.push(secs.round() as i64);
+        }
+        assert_eq!(lockouts, [30, 60, 120, 200, 200]);
+    }
+
+    #[tokio::test]
+    async fn test_auth_cookie() {
+        let issued = issue_token(AccountId(2), 60);
+        let filter = authenticate(store());
+        let request = || {
+            warp::test::request()
+                .method("POST")
+                .header("Cookie", format!("session={}", issued.token))
+        };
+
+        let res = warp::test::request()
+            .header("Cookie", format!("session={}", issued.token))
+            .filter(&filter)
+            .await;
+        assert_eq!(res.unwrap().id.unwrap(), 2);
+
+        let res = request().filter(&filter).await;
+        assert_eq!(
+            res.unwrap_err().find::<AppError>().unwrap().to_string(),
+            AppError::Forbidden.to_string()
+        );
+        assert!(request().header(CSRF_HEADER, "nope").filter(&filter).await.is_err());
+        assert!(request().header(CSRF_HEADER, issued.csrf_token.as_str()).filter(&filter).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_token() {
+        let filter = authenticate(store());
+        let request = |token: String| {
+            warp::test::request().header("Authorization", format!("Bearer {}", token))
+        };
+
+        let impersonated = request(issue_impersonation_token(AccountId(2), 1, 60).token)
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!((impersonated.id, impersonated.act), (Some(2), Some(1)));
+        let own = request(issue_token(AccountId(2), 60).token).filter(&filter).await.unwrap();
+        assert_eq!(own.act, None);
+    }
+
+    #[test]
+    fn test_credential() {
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(credential(None, None, None), None);
+        assert_eq!(credential(some("v2.local.x"), None, None), None);
+        assert_eq!(credential(some("Basic dXNlcjpwdw=="), None, None), None);
+        assert_eq!(credential(some("Bearer "), None, None), None);
+        assert_eq!(credential(some("bearer v2.local.x"), None, None), Some(Credential::Token("v2.local.x".into())));
+        assert_eq!(credential(some("Bearer qak_123"), None, None), Some(Credential::ApiKey("qak_123".into())));
+        assert_eq!(credential(some("Bearer qak_1"), some("qak_2"), None), Some(Credential::ApiKey("qak_2".into())));
+        assert_eq!(credential(some("Bearer x"), None, some("y")), Some(Credential::Token("x".into())));
+        assert_eq!(credential(None, None, some("y")), Some(Credential::Cookie("y".into())));
+    }
+
+    #[tokio::test]
+    async fn test_mfa_token() {
+        let token = issue_mfa_token(AccountId(3), 60);
+        assert_eq!(verify_mfa_token(&token).unwrap(), 3);
+        assert!(verify_mfa_token(&issue_token(AccountId(3), 60).token).is_err());
+
+        // a pending login is no session
+        let res = warp::test::request()
+            .header("Authorization", format!("Bearer {}", token))
+            .filter(&authenticate(store()))
+            .await;
+        assert!(res.is_err());
+    }
+
     #[tokio::test]
     async fn test_auth_error() {
-        let filter = auth();
+        let filter = authenticate(store());
         let res = warp::test::request()
-            .header("Authorization", "token")
+            .header("Authorization", "Bearer token")
             .filter(&filter)
             .await;
 
@@ -0,0 +1,117 @@
+use error_handler::AppError;
+use tracing::info;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  metrics,
+  profanity::check_profanity,
+  routes::auth::{db_error, is_admin},
+  store::Store,
+  types::{
+    account::Session,
+    comment::{Comment, CommentPayload},
+    paging::PageQuery,
+    post::PostTarget,
+  },
+  validation::field_errors,
+};
+
+pub async fn add_comment(
+  target: PostTarget,
+  session: Session,
+  store: Store,
+  body: CommentPayload,
+) -> Result<impl Reply, Rejection> {
+  let content = checked_content(body.content).await?;
+  match store
+    .add_comment(target, session.id.unwrap_or_default(), content)
+    .await
+    .map_err(db_error)?
+  {
+    Some(comment) => {
+      metrics::COMMENTS_CREATED.inc();
+      Ok(reply::with_status(reply::json(&comment), StatusCode::CREATED))
+    }
+    None => Err(reject::custom(target.not_found())),
+  }
+}
+
+pub async fn comments(target: PostTarget, store: Store, page: PageQuery) -> Result<impl Reply, Rejection> {
+  match store.comments(target, &page).await.map_err(db_error)? {
+    Some(comments) => Ok(reply::json(&comments)),
+    None => Err(reject::custom(target.not_found())),
+  }
+}
+
+/// Only the author may edit a comment
+pub async fn update_comment(id: i32, session: Session, store: Store, body: CommentPayload) -> Result<impl Reply, Rejection> {
+  let comment = find_comment(&store, id).await?;
+  if comment.account_id != session.id {
+    return Err(reject::custom(AppError::Forbidden));
+  }
+
+  let content = checked_content(body.content).await?;
+  match store.update_comment(id, content).await.map_err(db_error)? {
+    Some(comment) => Ok(reply::json(&comment)),
+    None => Err(reject::custom(AppError::CommentNotFound)),
+  }
+}
+
+/// The author or an admin may delete a comment
+pub async fn delete_comment(id: i32, session: Session, store: Store) -> Result<impl Reply, Rejection> {
+  let comment = find_comment(&store, id).await?;
+  if comment.account_id != session.id {
+    if !is_admin(&store, &session).await? {
+      return Err(reject::custom(AppError::Forbidden));
+    }
+    info!(admin = session.id, comment = id, author = comment.account_id, "Comment deleted by admin");
+  }
+
+  store.delete_comment(id).await.map_err(db_error)?;
+  Ok(reply::with_status("Deleted", StatusCode::OK))
+}
+
+async fn find_comment(store: &Store, id: i32) -> Result<Comment, Rejection> {
+  store
+    .comment(id)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::CommentNotFound))
+}
+
+/// Trimmed, between 1 and 600 characters and censored
+async fn checked_content(content: String) -> Result<String, Rejection> {
+  let content = trimmed_content(&content)?;
+  let checked = check_profanity(content).await.map_err(reject::custom)?;
+  Ok(checked.censored_content)
+}
+
+fn trimmed_content(content: &str) -> Result<String, Rejection> {
+  let content = content.trim();
+  if content.is_empty() || content.chars().count() > 600 {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "content",
+      ["must be between 1 and 600 characters"],
+    ))));
+  }
+  Ok(content.to_string())
+}
+
+#[cfg(test)]
+mod comments_tests {
+  use super::trimmed_content;
+  use error_handler::AppError;
+
+  #[test]
+  fn test_trimmed_content() {
+    assert_eq!(trimmed_content("  Thanks!\n").unwrap(), "Thanks!");
+    assert_eq!(trimmed_content(&"ä".repeat(600)).unwrap().chars().count(), 600);
+
+    for content in ["", " \n\t ", &"a".repeat(601)] {
+      match trimmed_content(content).unwrap_err().find() {
+        Some(AppError::Validation(errors)) => assert_eq!(errors[0].field, "content"),
+        other => panic!("unexpected rejection {:?}", other),
+      }
+    }
+  }
+}
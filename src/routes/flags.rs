@@ -0,0 +1,36 @@
+use error_handler::AppError;
+use tracing::info;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::auth::db_error,
+  store::Store,
+  types::{account::Session, flag::NewFlag, paging::PageQuery, post::PostTarget},
+  validation::field_errors,
+};
+
+/// `POST /q/{id}/flag` and `POST /a/{id}/flag`, needs the `Flag` privilege
+pub async fn flag(target: PostTarget, session: Session, store: Store, body: NewFlag) -> Result<impl Reply, Rejection> {
+  let reason = body.reason.trim().to_string();
+  if reason.is_empty() || reason.chars().count() > 500 {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "reason",
+      ["must be between 1 and 500 characters"],
+    ))));
+  }
+
+  if !store
+    .flag(target, session.id.unwrap_or_default(), reason)
+    .await
+    .map_err(db_error)?
+  {
+    return Err(reject::custom(target.not_found()));
+  }
+  info!(?target, account = session.id, "Post flagged");
+  Ok(reply::with_status("Flagged", StatusCode::ACCEPTED))
+}
+
+pub async fn flags(_admin: Session, store: Store, page: PageQuery) -> Result<impl Reply, Rejection> {
+  let flags = store.flags(&page).await.map_err(db_error)?;
+  Ok(reply::json(&flags))
+}
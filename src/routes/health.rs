@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, future::Future, time::Duration, time::Instant};
+
+use serde_json::json;
+use tokio::time::timeout;
+use warp::{http::StatusCode, reply, Rejection, Reply};
+
+use crate::{
+  profanity,
+  store::Store,
+  types::health::{DependencyCheck, Readiness, Status},
+};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness probe, answers as long as the process is able to serve requests.
+pub async fn healthz() -> Result<impl Reply, Rejection> {
+  Ok(reply::json(&json!({ "status": "up" })))
+}
+
+/// Readiness probe, checks every dependency the api needs to serve traffic.
+pub async fn readyz(store: Store) -> Result<impl Reply, Rejection> {
+  let (db, migrations, profanity) = tokio::join!(
+    probe(true, async { store.ping().await.map_err(|e| e.to_string()) }),
+    probe(true, async {
+      match store.pending_migrations().await {
+        Ok(pending) if pending.is_empty() => Ok(()),
+        Ok(pending) => Err(format!("pending migrations: {:?}", pending)),
+        Err(e) => Err(e.to_string()),
+      }
+    }),
+    probe(false, async { profanity::ping().await.map_err(|e| e.to_string()) }),
+  );
+
+  let readiness = Readiness::new(BTreeMap::from([
+    ("database", db),
+    ("migrations", migrations),
+    ("profanity", profanity),
+  ]));
+
+  let status = match readiness.status {
+    Status::Down => StatusCode::SERVICE_UNAVAILABLE,
+    _ => StatusCode::OK,
+  };
+
+  Ok(reply::with_status(reply::json(&readiness), status))
+}
+
+async fn probe<F>(critical: bool, check: F) -> DependencyCheck
+where
+  F: Future<Output = Result<(), String>>,
+{
+  let start = Instant::now();
+  let res = match timeout(CHECK_TIMEOUT, check).await {
+    Ok(res) => res,
+    Err(_) => Err(format!("timed out after {}ms", CHECK_TIMEOUT.as_millis())),
+  };
+
+  DependencyCheck {
+    status: if res.is_ok() { Status::Up } else { Status::Down },
+    critical,
+    latency_ms: start.elapsed().as_millis(),
+    detail: res.err(),
+  }
+}
@@ -0,0 +1,153 @@
+use chrono::Utc;
+use error_handler::AppError;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::auth::{add_audit, db_error, record_login_failure, session_reply, verify_mfa_token, AuthConfig},
+  store::Store,
+  totp,
+  types::{
+    account::{AccountId, MfaLogin, RecoveryCodes, Session, TotpCode, TotpEnrollment, TotpState},
+    audit::{self, NewAuditEntry},
+  },
+  utils::hash_token,
+  validation::field_errors,
+};
+
+/// Starts an enrollment, 2FA is only turned on once `confirm` gets a first code of the new secret
+pub async fn enroll(session: Session, store: Store, conf: AuthConfig) -> Result<impl Reply, Rejection> {
+  let id = session.id.unwrap_or_default();
+  let state = account_totp(&store, id).await?;
+  if state.secret.is_some() {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "totp",
+      ["is already enabled"],
+    ))));
+  }
+
+  let secret = totp::new_secret();
+  let otpauth_uri = totp::otpauth_uri(&conf.totp, &secret, &state.email);
+  store.set_pending_totp(id, &secret).await.map_err(db_error)?;
+
+  Ok(reply::json(&TotpEnrollment { secret, otpauth_uri }))
+}
+
+/// Turns 2FA on and hands out the recovery codes, the only time they are shown
+pub async fn confirm(
+  session: Session,
+  store: Store,
+  conf: AuthConfig,
+  body: TotpCode,
+) -> Result<impl Reply, Rejection> {
+  let id = session.id.unwrap_or_default();
+  let state = account_totp(&store, id).await?;
+  let pending = state.pending_secret.ok_or_else(|| {
+    reject::custom(AppError::Validation(field_errors("totp", ["has no enrollment in progress"])))
+  })?;
+
+  let step = totp::verify(&conf.totp, &pending, &body.code, Utc::now().timestamp() as u64)
+    .ok_or_else(|| reject::custom(AppError::Validation(field_errors("code", ["is invalid"]))))?;
+
+  let codes = totp::new_recovery_codes(conf.totp.recovery_codes);
+  let hashes: Vec<String> = codes
+    .iter()
+    .map(|c| hash_token(&totp::normalize_recovery_code(c)))
+    .collect();
+  store.enable_totp(id, step as i64, &hashes).await.map_err(db_error)?;
+
+  add_audit(&store, mfa_entry(Some(id), true, "enabled".to_string())).await;
+  Ok(reply::with_status(
+    reply::json(&RecoveryCodes { recovery_codes: codes }),
+    StatusCode::CREATED,
+  ))
+}
+
+/// Second login step, trades the token of `login` and a code for a session token
+pub async fn login(store: Store, conf: AuthConfig, ip: String, body: MfaLogin) -> Result<impl Reply, Rejection> {
+  let id = verify_mfa_token(&body.mfa_token).map_err(reject::custom)?;
+  let state = account_totp(&store, id).await?;
+  let email = state.email.to_lowercase();
+  let entry = |success: bool, detail: &str| NewAuditEntry {
+    action: audit::LOGIN,
+    account_id: Some(id),
+    actor_id: None,
+    email: Some(email.clone()),
+    ip: Some(ip.clone()),
+    success,
+    detail: Some(detail.to_string()),
+  };
+
+  if let Some(until) = store.login_locked_until(&email).await.map_err(db_error)? {
+    add_audit(&store, entry(false, audit::LOCKED)).await;
+    return Err(reject::custom(AppError::LoginLocked {
+      retry_after: (until - Utc::now()).num_seconds().max(1) as u64,
+    }));
+  }
+
+  let Some(secret) = &state.secret else {
+    // 2FA was reset since the password step
+    return Err(reject::custom(AppError::InvalidToken));
+  };
+
+  let (valid, method) = match (&body.code, &body.recovery_code) {
+    (Some(code), _) => {
+      let step = totp::verify(&conf.totp, secret, code, Utc::now().timestamp() as u64)
+        .filter(|step| state.last_step.is_none_or(|last| *step as i64 > last));
+      let valid = match step {
+        Some(step) => store.use_totp_step(id, step as i64).await.map_err(db_error)?,
+        None => false,
+      };
+      (valid, "totp")
+    }
+    (None, Some(code)) => {
+      let hash = hash_token(&totp::normalize_recovery_code(code));
+      (store.use_recovery_code(id, &hash).await.map_err(db_error)?, "recovery code")
+    }
+    (None, None) => {
+      return Err(reject::custom(AppError::Validation(field_errors("code", ["is required"]))));
+    }
+  };
+
+  if !valid {
+    record_login_failure(&store, &conf, &email).await?;
+    add_audit(&store, entry(false, &format!("bad {}", method))).await;
+    return Err(reject::custom(AppError::InvalidCredential));
+  }
+
+  store.reset_login_failures(&email).await.map_err(db_error)?;
+  add_audit(&store, entry(true, method)).await;
+  Ok(session_reply(&conf.session, AccountId(id)))
+}
+
+/// Lets an admin turn 2FA off for a user who lost both the authenticator and the recovery codes
+pub async fn admin_reset(admin: Session, id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  if !store.reset_totp(id).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::Validation(field_errors("id", ["is not an account"]))));
+  }
+
+  let detail = format!("reset by admin {}", admin.id.unwrap_or_default());
+  let entry = NewAuditEntry {
+    actor_id: admin.id,
+    ..mfa_entry(Some(id), true, detail)
+  };
+  add_audit(&store, entry).await;
+  Ok(reply::with_status("2FA reset", StatusCode::OK))
+}
+
+async fn account_totp(store: &Store, id: i32) -> Result<TotpState, Rejection> {
+  store
+    .totp_state(id)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::InvalidToken))
+}
+
+fn mfa_entry(account_id: Option<i32>, success: bool, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action: audit::MFA,
+    account_id,
+    success,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
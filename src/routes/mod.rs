@@ -1,3 +1,15 @@
+pub mod admin;
 pub mod answers;
+pub mod api_keys;
 pub mod questions;
+pub mod tags;
 pub mod auth;
+pub mod comments;
+pub mod flags;
+pub mod health;
+pub mod mfa;
+pub mod moderation;
+pub mod privacy;
+pub mod profile;
+pub mod revisions;
+pub mod votes;
@@ -0,0 +1,82 @@
+use error_handler::AppError;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::auth::{add_audit, db_error},
+  store::Store,
+  types::{
+    account::Session,
+    audit::{self, NewAuditEntry},
+    moderation::{ModerationQuery, StateChange, StateOutcome},
+    post::PostTarget,
+    question::{DuplicateOutcome, MarkDuplicate},
+  },
+  validation::field_errors,
+};
+
+/// `POST /admin/q|a/{id}/restore`, brings back a soft-deleted post that has not been purged yet
+pub async fn restore(target: PostTarget, admin: Session, store: Store) -> Result<impl Reply, Rejection> {
+  if !store.restore_post(target).await.map_err(db_error)? {
+    return Err(reject::custom(target.not_found()));
+  }
+
+  let detail = format!("restored {} {}", target.table(), target.id());
+  add_audit(&store, moderation_entry(&admin, detail)).await;
+  Ok(reply::with_status("Restored", StatusCode::OK))
+}
+
+/// `PUT /admin/q/{id}/duplicate`, closes the question as a duplicate of another
+pub async fn mark_duplicate(id: i32, admin: Session, store: Store, body: MarkDuplicate) -> Result<impl Reply, Rejection> {
+  match store.mark_duplicate(id, body.duplicate_of, admin.id).await.map_err(db_error)? {
+    DuplicateOutcome::Marked(q) => Ok(reply::json(&q)),
+    DuplicateOutcome::NotFound => Err(reject::custom(AppError::QuestionNotFound)),
+    DuplicateOutcome::CanonicalNotFound => Err(reject::custom(AppError::Validation(field_errors(
+      "duplicate_of",
+      ["is not an existing question"],
+    )))),
+    DuplicateOutcome::SameQuestion => Err(reject::custom(AppError::Validation(field_errors(
+      "duplicate_of",
+      ["must be another question"],
+    )))),
+  }
+}
+
+/// `DELETE /admin/q/{id}/duplicate`, reopens the question
+pub async fn unmark_duplicate(id: i32, admin: Session, store: Store) -> Result<impl Reply, Rejection> {
+  match store.unmark_duplicate(id, admin.id).await.map_err(db_error)? {
+    Some(q) => Ok(reply::json(&q)),
+    None => Err(reject::custom(AppError::QuestionNotFound)),
+  }
+}
+
+/// `PUT /admin/q/{id}/state`, closes, reopens, locks or protects the question
+pub async fn set_state(id: i32, admin: Session, store: Store, body: StateChange) -> Result<impl Reply, Rejection> {
+  if let Some(message) = body.reason_error() {
+    return Err(reject::custom(AppError::Validation(field_errors("reason", [message]))));
+  }
+
+  match store.set_state(id, body.state, body.reason, admin.id).await.map_err(db_error)? {
+    StateOutcome::Changed(q) => Ok(reply::json(&q)),
+    StateOutcome::NotFound => Err(reject::custom(AppError::QuestionNotFound)),
+    StateOutcome::Unchanged => Err(reject::custom(AppError::Validation(field_errors(
+      "state",
+      ["is the state of the question already"],
+    )))),
+  }
+}
+
+/// `GET /admin/moderation`, the state changes of questions
+pub async fn moderation_log(_admin: Session, store: Store, q: ModerationQuery) -> Result<impl Reply, Rejection> {
+  let entries = store.moderation_log(q).await.map_err(db_error)?;
+  Ok(reply::json(&entries))
+}
+
+fn moderation_entry(admin: &Session, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action: audit::ADMIN,
+    actor_id: admin.id,
+    success: true,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
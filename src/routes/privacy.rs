@@ -0,0 +1,85 @@
+use error_handler::AppError;
+use tracing::error;
+use warp::{
+  http::{header, StatusCode},
+  reject, reply,
+  reply::Response,
+  Rejection, Reply,
+};
+
+use crate::{
+  export,
+  routes::auth::{add_audit, db_error},
+  store::Store,
+  types::{
+    account::Session,
+    audit::{self, NewAuditEntry},
+    export::{ExportFormat, ExportQuery},
+  },
+};
+
+/// `GET /me/export`, a copy of everything stored about the caller
+pub async fn export_me(session: Session, store: Store, q: ExportQuery) -> Result<Response, Rejection> {
+  let id = session.id.unwrap_or_default();
+  let res = export_reply(&store, id, q.format).await?;
+  add_audit(&store, privacy_entry(audit::DATA_EXPORT, Some(id), "by the account".to_string())).await;
+  Ok(res)
+}
+
+/// Export of any account, for data subject requests received outside the API
+pub async fn admin_export(admin: Session, id: i32, store: Store, q: ExportQuery) -> Result<Response, Rejection> {
+  let res = export_reply(&store, id, q.format).await?;
+  let detail = format!("by admin {}", admin.id.unwrap_or_default());
+  let entry = NewAuditEntry {
+    actor_id: admin.id,
+    ..privacy_entry(audit::DATA_EXPORT, Some(id), detail)
+  };
+  add_audit(&store, entry).await;
+  Ok(res)
+}
+
+pub async fn admin_erase(admin: Session, id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  if store.erase_account(id).await.map_err(db_error)?.is_none() {
+    return Err(reject::custom(AppError::AccountNotFound));
+  }
+
+  let detail = format!("account {} erased by admin {}", id, admin.id.unwrap_or_default());
+  // the row is gone, the id only stays in the detail
+  let entry = NewAuditEntry {
+    actor_id: admin.id,
+    ..privacy_entry(audit::ERASURE, None, detail)
+  };
+  add_audit(&store, entry).await;
+  Ok(reply::with_status("Erased", StatusCode::OK))
+}
+
+async fn export_reply(store: &Store, id: i32, format: ExportFormat) -> Result<Response, Rejection> {
+  let data = store
+    .export_account(id)
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::AccountNotFound))?;
+
+  match format {
+    ExportFormat::Json => Ok(reply::json(&data).into_response()),
+    ExportFormat::Zip => {
+      let bytes = export::to_zip(&data).map_err(|e| {
+        error!("Failed to build export of account {}: {:?}", id, e);
+        reject::custom(AppError::DbQueryError)
+      })?;
+      let disposition = format!("attachment; filename=\"account-{}-export.zip\"", id);
+      let res = reply::with_header(bytes, header::CONTENT_TYPE, "application/zip");
+      Ok(reply::with_header(res, header::CONTENT_DISPOSITION, disposition).into_response())
+    }
+  }
+}
+
+pub(crate) fn privacy_entry(action: &'static str, account_id: Option<i32>, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action,
+    account_id,
+    success: true,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
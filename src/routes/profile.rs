@@ -0,0 +1,227 @@
+use chrono::Utc;
+use error_handler::AppError;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  mail::Outbox,
+  routes::auth::{
+    add_audit, db_error, invalid_token, issue_mail_token, record_login_failure, session_reply,
+    AuthConfig,
+  },
+  store::Store,
+  types::{
+    account::{
+      Account, AccountId, ChangeEmail, ChangePassword, DeleteAccount, ProfileUpdate, Session, TokenPurpose,
+      VerifyEmail,
+    },
+    audit::{self, NewAuditEntry},
+    paging::PageQuery,
+  },
+  utils::{check_password, hash_password, hash_token},
+  validation::{field_errors, normalize_email, validate_email, validate_profile},
+};
+
+pub async fn me(session: Session, store: Store) -> Result<impl Reply, Rejection> {
+  let profile = store
+    .profile(session.id.unwrap_or_default())
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::InvalidToken))?;
+  Ok(reply::json(&profile))
+}
+
+pub async fn update_me(session: Session, store: Store, mut body: ProfileUpdate) -> Result<impl Reply, Rejection> {
+  for value in [&mut body.display_name, &mut body.bio, &mut body.avatar_url].into_iter().flatten() {
+    *value = value.trim().to_string();
+  }
+  let errors = validate_profile(&body);
+  if !errors.is_empty() {
+    return Err(reject::custom(AppError::Validation(errors)));
+  }
+
+  store
+    .update_profile(session.id.unwrap_or_default(), body)
+    .await
+    .map_err(db_error)?;
+  me(session, store).await
+}
+
+/// Mails a confirmation link to the new address, the email only changes once it is followed
+pub async fn change_email(
+  session: Session,
+  store: Store,
+  conf: AuthConfig,
+  outbox: Outbox,
+  body: ChangeEmail,
+) -> Result<impl Reply, Rejection> {
+  let account = current_account(&store, &session).await?;
+  check_current_password(&store, &conf, &account, body.current_password).await?;
+
+  let email = normalize_email(&body.new_email);
+  if let Err(msg) = validate_email(&email) {
+    return Err(reject::custom(AppError::Validation(field_errors("new_email", [msg]))));
+  }
+  if store.find_account(email.clone()).await.map_err(db_error)?.is_some() {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "new_email",
+      ["is already registered"],
+    ))));
+  }
+
+  let id = session.id.unwrap_or_default();
+  store.set_pending_email(id, &email).await.map_err(db_error)?;
+  let token = issue_mail_token(&store, id, TokenPurpose::ChangeEmail, conf.email.verify_ttl_secs)
+    .await
+    .map_err(db_error)?;
+  outbox.send_email_change(&email, &token);
+
+  add_audit(&store, account_entry(id, "email change requested")).await;
+  Ok(reply::with_status("Confirmation sent", StatusCode::ACCEPTED))
+}
+
+pub async fn confirm_email_change(store: Store, outbox: Outbox, body: VerifyEmail) -> Result<impl Reply, Rejection> {
+  let id = store
+    .use_account_token(TokenPurpose::ChangeEmail, &hash_token(&body.token))
+    .await
+    .map_err(db_error)?
+    .ok_or_else(invalid_token)?;
+
+  let (old_email, new_email) = match store.confirm_email_change(id).await {
+    Ok(Some(emails)) => emails,
+    Ok(None) => return Err(invalid_token()),
+    // somebody registered the address in the meantime
+    Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+      return Err(reject::custom(AppError::Validation(field_errors(
+        "new_email",
+        ["is already registered"],
+      ))));
+    }
+    Err(e) => return Err(db_error(e)),
+  };
+  outbox.send_email_changed(&old_email, &new_email);
+
+  add_audit(&store, account_entry(id, "email changed")).await;
+  Ok(reply::with_status("Email changed", StatusCode::OK))
+}
+
+pub async fn change_password(
+  session: Session,
+  store: Store,
+  conf: AuthConfig,
+  body: ChangePassword,
+) -> Result<impl Reply, Rejection> {
+  let account = current_account(&store, &session).await?;
+  check_current_password(&store, &conf, &account, body.current_password).await?;
+
+  let errors = conf.password.check(&body.new_password, &normalize_email(&account.email));
+  if !errors.is_empty() {
+    return Err(reject::custom(AppError::Validation(field_errors("new_password", errors))));
+  }
+
+  let id = session.id.unwrap_or_default();
+  // other sessions are dropped, the one making the change gets a new token issued after the cutoff
+  store
+    .change_password(id, hash_password(body.new_password, &conf.hashing).await, Utc::now())
+    .await
+    .map_err(db_error)?;
+  // a reset link mailed earlier must not undo the change
+  store
+    .revoke_account_tokens(id, TokenPurpose::ResetPassword)
+    .await
+    .map_err(db_error)?;
+
+  add_audit(&store, account_entry(id, "password changed")).await;
+  Ok(session_reply(&conf.session, AccountId(id)))
+}
+
+/// Erases the account, its posts and comments are kept under the deleted user
+pub async fn delete_me(
+  session: Session,
+  store: Store,
+  conf: AuthConfig,
+  body: DeleteAccount,
+) -> Result<impl Reply, Rejection> {
+  let account = current_account(&store, &session).await?;
+  check_current_password(&store, &conf, &account, body.current_password).await?;
+
+  let id = session.id.unwrap_or_default();
+  store.erase_account(id).await.map_err(db_error)?;
+
+  // the row is gone, keep the id in the detail only
+  add_audit(
+    &store,
+    NewAuditEntry {
+      action: audit::ACCOUNT,
+      success: true,
+      detail: Some(format!("account {} deleted", id)),
+      ..Default::default()
+    },
+  )
+  .await;
+  Ok(reply::with_status("Deleted", StatusCode::OK))
+}
+
+pub async fn public_profile(id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  match store.public_profile(id).await.map_err(db_error)? {
+    Some(profile) => Ok(reply::json(&profile)),
+    None => Err(reject::custom(AppError::AccountNotFound)),
+  }
+}
+
+pub async fn user_questions(id: i32, store: Store, page: PageQuery) -> Result<impl Reply, Rejection> {
+  let questions = store.questions_by_account(id, &page).await.map_err(db_error)?;
+  Ok(reply::json(&questions))
+}
+
+pub async fn user_answers(id: i32, store: Store, page: PageQuery) -> Result<impl Reply, Rejection> {
+  let answers = store.answers_by_account(id, &page).await.map_err(db_error)?;
+  Ok(reply::json(&answers))
+}
+
+/// Ledger behind the reputation shown on the profile, newest first
+pub async fn user_reputation(id: i32, store: Store, page: PageQuery) -> Result<impl Reply, Rejection> {
+  let events = store.reputation_events(id, &page).await.map_err(db_error)?;
+  Ok(reply::json(&events))
+}
+
+async fn current_account(store: &Store, session: &Session) -> Result<Account, Rejection> {
+  store
+    .find_account_by_id(session.id.unwrap_or_default())
+    .await
+    .map_err(db_error)?
+    .ok_or_else(|| reject::custom(AppError::InvalidToken))
+}
+
+/// Sensitive changes need the password again, wrong guesses count towards the login lockout
+async fn check_current_password(
+  store: &Store,
+  conf: &AuthConfig,
+  account: &Account,
+  password: String,
+) -> Result<(), Rejection> {
+  let email = normalize_email(&account.email);
+  if let Some(until) = store.login_locked_until(&email).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::LoginLocked {
+      retry_after: (until - Utc::now()).num_seconds().max(1) as u64,
+    }));
+  }
+
+  if check_password(password, account.password.clone()).await {
+    return Ok(());
+  }
+  record_login_failure(store, conf, &email).await?;
+  Err(reject::custom(AppError::Validation(field_errors(
+    "current_password",
+    ["is wrong"],
+  ))))
+}
+
+fn account_entry(account_id: i32, detail: &str) -> NewAuditEntry {
+  NewAuditEntry {
+    action: audit::ACCOUNT,
+    account_id: Some(account_id),
+    success: true,
+    detail: Some(detail.to_string()),
+    ..Default::default()
+  }
+}
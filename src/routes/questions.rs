@@ -1,17 +1,32 @@
 use std::collections::HashMap;
 
 use crate::{
+    duplicates::DuplicateConfig,
+    reputation::ReputationConfig,
+    routes::{auth::{db_error, is_admin}, tags::normalize_tags},
     store::Store,
     types::{
         paging::{extract_paging, Pagination},
-        question::QuestionPayload, account::Session,
+        question::{
+            AcceptAnswer, AcceptOutcome, NewQuestion, Question, QuestionFilter, QuestionPatch, QuestionPayload,
+            QuestionSort, Retag,
+        },
+        account::Session,
+        moderation::QuestionState,
+        post::{self, etag, PostTarget, UpdateOutcome},
+        reputation::Privilege,
     },
+    validation::{field_errors, validate_post},
 };
 use error_handler::AppError;
-use tracing::{error, info};
-use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+use tracing::{debug, error, info, Instrument};
+use warp::{
+    http::{header::{HeaderValue, ETAG, LINK}, StatusCode},
+    reject, reply, Rejection, Reply,
+};
 
-use crate::profanity::check_profanity;
+use crate::metrics;
+use crate::profanity::{censor, check_profanity};
 
 // #[instrument]
 pub async fn get_q(
@@ -23,7 +38,29 @@ pub async fn get_q(
     // thread::sleep(Duration::from_secs(1));
     info!("Get list q");
 
-    let paging = if params.is_empty() {
+    let sort = match params.get("sort") {
+        Some(sort) => sort.parse::<QuestionSort>().map_err(|_| {
+            reject::custom(AppError::Validation(field_errors("sort", ["must be oldest, newest or score"])))
+        })?,
+        None => QuestionSort::default(),
+    };
+    let filter = match params.get("filter") {
+        Some(filter) => Some(filter.parse::<QuestionFilter>().map_err(|_| {
+            reject::custom(AppError::Validation(field_errors("filter", ["must be answered or unanswered"])))
+        })?),
+        None => None,
+    };
+    let state = match params.get("state") {
+        Some(state) => Some(state.parse::<QuestionState>().map_err(|_| {
+            reject::custom(AppError::Validation(field_errors(
+                "state",
+                ["must be open, closed, locked or protected"],
+            )))
+        })?),
+        None => None,
+    };
+
+    let paging = if !params.contains_key("limit") && !params.contains_key("offset") {
         info!(paging = false);
         Pagination::default()
     } else {
@@ -31,7 +68,7 @@ pub async fn get_q(
         extract_paging(params)?
     };
 
-    let res = store.get_q(paging.limit, paging.offset).await;
+    let res = store.get_q(paging.limit, paging.offset, sort, filter, state).await;
     match res {
         Ok(qs) => Ok(reply::json(&qs)),
         Err(e) => {
@@ -41,32 +78,47 @@ pub async fn get_q(
     }
 }
 
-pub async fn add_q(s: Session, store: Store, q: QuestionPayload) -> Result<impl Reply, Rejection> {
-    let title = tokio::spawn(check_profanity(q.title));
-    let content = tokio::spawn(check_profanity(q.content));
-
-    println!("{s:#?}");
-    
-
-    let (title, content) = (title.await.unwrap(), content.await.unwrap());
-
-    if title.is_err() {
-        return Err(reject::custom(title.unwrap_err()));
+/// Answers with the new question and the earlier ones that look like it
+pub async fn add_q(
+    s: Session,
+    store: Store,
+    conf: DuplicateConfig,
+    q: QuestionPayload,
+) -> Result<impl Reply, Rejection> {
+    let errors = validate_post(Some(&q.title), Some(&q.content));
+    if !errors.is_empty() {
+        return Err(reject::custom(AppError::Validation(errors)));
     }
+    let tags = match q.tags {
+        Some(tags) => Some(normalize_tags(&store, tags).await?),
+        None => None,
+    };
+    let title = tokio::spawn(check_profanity(q.title).in_current_span());
+    let content = tokio::spawn(check_profanity(q.content).in_current_span());
 
-    if content.is_err() {
-        return Err(reject::custom(content.unwrap_err()));
-    }
+    debug!("Add question for {:?}", s);
+
+    let title = title.await.unwrap().map_err(reject::custom)?;
+    let content = content.await.unwrap().map_err(reject::custom)?;
 
     match store
         .add_q(QuestionPayload {
-            title: title.unwrap().censored_content,
-            content: content.unwrap().censored_content,
-            tags: q.tags,
-        })
+            title: title.censored_content,
+            content: content.censored_content,
+            tags,
+        }, s.id)
         .await
     {
-        Ok(q) => Ok(reply::with_status(reply::json(&q), StatusCode::CREATED)),
+        Ok(q) => {
+            metrics::QUESTIONS_CREATED.inc();
+            // the question is saved already, a failed lookup only costs the hint
+            let possible_duplicates = store.similar_questions(&q, &conf).await.unwrap_or_else(|e| {
+                error!("Failed to look for duplicates {:?}", e);
+                vec![]
+            });
+            let res = NewQuestion { question: q, possible_duplicates };
+            Ok(reply::with_status(reply::json(&res), StatusCode::CREATED))
+        }
         Err(e) => {
             error!("Failed to add question {:?}", e);
             Err(reject::custom(AppError::DbQueryError))
@@ -74,36 +126,236 @@ pub async fn add_q(s: Session, store: Store, q: QuestionPayload) -> Result<impl
     }
 }
 
-pub async fn detail_q(_id: u32, _store: Store) -> Result<impl Reply, Rejection> {
-    // match store.questions.read().await.get(&QuestionId(id)) {
-    //     Some(q) => Ok(reply::json(&q)),
-    //     None => Err(reject::custom(AppError::QuestionNotFound)),
-    // }
-    Ok(reply::with_status("Created", StatusCode::CREATED))
+/// Questions closed as duplicates link to their canonical question in `duplicate_of` and a `Link` header
+pub async fn detail_q(id: u32, store: Store) -> Result<impl Reply, Rejection> {
+    match store.question_detail(id as i32).await.map_err(db_error)? {
+        Some(q) => {
+            let mut res = reply::with_header(reply::json(&q), ETAG, etag(q.question.version)).into_response();
+            if let Some(canonical) = q.question.duplicate_of {
+                if let Ok(link) = HeaderValue::from_str(&format!("</q/{}>; rel=\"canonical\"", canonical)) {
+                    res.headers_mut().insert(LINK, link);
+                }
+            }
+            Ok(res)
+        }
+        None => Err(reject::custom(AppError::QuestionNotFound)),
+    }
 }
 
-pub async fn upd_q(id: u32, store: Store, q: QuestionPayload) -> Result<impl Reply, Rejection> {
-    match store.upd_q(id as i32, q).await {
-        Ok(id) => Ok(reply::with_status(
-            format!("Updated {id}"),
-            StatusCode::ACCEPTED,
-        )),
-        Err(e) => {
-            error!("Failed to update question {:?}", e);
-            Err(warp::reject::custom(AppError::DbQueryError))
+/// `PUT /q/{id}/tags`, open to the author and everybody with the `EditTags` privilege
+pub async fn retag_q(
+    id: i32,
+    session: Session,
+    store: Store,
+    conf: ReputationConfig,
+    body: Retag,
+) -> Result<impl Reply, Rejection> {
+    let question = store
+        .question(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| reject::custom(AppError::QuestionNotFound))?;
+    if question.account_id != session.id {
+        let standing = store.account_standing(session.id.unwrap_or_default()).await.map_err(db_error)?;
+        if !conf.grants(Privilege::EditTags, standing) {
+            return Err(reject::custom(AppError::InsufficientReputation {
+                required: conf.required(Privilege::EditTags),
+            }));
         }
     }
+    let tags = normalize_tags(&store, body.tags).await?;
+    let q = updated(store.set_tags(id, tags, session.id).await.map_err(db_error)?)?;
+    info!("Q {} retagged by {:?}", id, session.id);
+    Ok(reply::json(&q))
+}
+
+/// `PUT /q/{id}/accept`, accepting another answer replaces the previous one
+pub async fn accept_a(
+    id: i32,
+    session: Session,
+    store: Store,
+    conf: ReputationConfig,
+    body: AcceptAnswer,
+) -> Result<impl Reply, Rejection> {
+    set_accepted(id, session, store, conf, Some(body.answer_id)).await
+}
+
+pub async fn unaccept_a(id: i32, session: Session, store: Store, conf: ReputationConfig) -> Result<impl Reply, Rejection> {
+    set_accepted(id, session, store, conf, None).await
+}
+
+async fn set_accepted(
+    id: i32,
+    session: Session,
+    store: Store,
+    conf: ReputationConfig,
+    answer_id: Option<i32>,
+) -> Result<reply::Json, Rejection> {
+    let outcome = store
+        .accept_answer(id, session.id.unwrap_or_default(), answer_id, &conf)
+        .await
+        .map_err(db_error)?;
+    let q = accepted(outcome)?;
+    info!("Accepted answer of q {} set to {:?}", id, answer_id);
+    Ok(reply::json(&q))
+}
+
+fn accepted(outcome: AcceptOutcome) -> Result<Question, Rejection> {
+    match outcome {
+        AcceptOutcome::Accepted(q) => Ok(q),
+        AcceptOutcome::NotFound => Err(reject::custom(AppError::QuestionNotFound)),
+        AcceptOutcome::NotAuthor => Err(reject::custom(AppError::Forbidden)),
+        AcceptOutcome::ForeignAnswer => Err(reject::custom(AppError::Validation(field_errors(
+            "answer_id",
+            ["is not an answer to this question"],
+        )))),
+    }
 }
 
-pub async fn del_q(id: u32, store: Store) -> Result<impl Reply, Rejection> {
-    match store.del_q(id as i32).await {
-        Ok(id) => Ok(reply::with_status(
+/// Replaces the whole question, see `patch_q`
+pub async fn upd_q(
+    id: u32,
+    session: Session,
+    store: Store,
+    if_match: Option<String>,
+    q: QuestionPayload,
+) -> Result<impl Reply, Rejection> {
+    let q = update_q(id as i32, session, store, if_match, q.into()).await?;
+    Ok(reply::with_header(
+        reply::with_status(format!("Updated {}", q.id.0), StatusCode::ACCEPTED),
+        ETAG,
+        etag(q.version),
+    ))
+}
+
+/// `PATCH /q/{id}`, sets only the fields the body has
+pub async fn patch_q(
+    id: i32,
+    session: Session,
+    store: Store,
+    if_match: Option<String>,
+    patch: QuestionPatch,
+) -> Result<impl Reply, Rejection> {
+    let q = update_q(id, session, store, if_match, patch).await?;
+    let version = q.version;
+    Ok(reply::with_header(reply::json(&q), ETAG, etag(version)))
+}
+
+/// Only the author or an admin may edit a question. With `If-Match` the edit only goes through
+/// when nobody else changed the question since, the previous text is kept as a revision
+async fn update_q(
+    id: i32,
+    session: Session,
+    store: Store,
+    if_match: Option<String>,
+    mut patch: QuestionPatch,
+) -> Result<Question, Rejection> {
+    let expected = post::if_match(if_match.as_deref()).map_err(reject::custom)?;
+    check_editor(&store, &session, id).await?;
+    let errors = validate_post(patch.title.as_deref(), patch.content.as_deref());
+    if !errors.is_empty() {
+        return Err(reject::custom(AppError::Validation(errors)));
+    }
+    if let Some(Some(tags)) = patch.tags {
+        patch.tags = Some(Some(normalize_tags(&store, tags).await?));
+    }
+    let title = tokio::spawn(censor(patch.title.take()).in_current_span());
+    let content = tokio::spawn(censor(patch.content.take()).in_current_span());
+    patch.title = title.await.unwrap().map_err(reject::custom)?;
+    patch.content = content.await.unwrap().map_err(reject::custom)?;
+    updated(store.upd_q(id, patch, expected, session.id).await.map_err(db_error)?)
+}
+
+/// Maps the outcome of a write to a question to its rejection
+pub(crate) fn updated(outcome: UpdateOutcome<Question>) -> Result<Question, Rejection> {
+    match outcome {
+        UpdateOutcome::Updated(q) => Ok(q),
+        UpdateOutcome::NotFound => Err(reject::custom(AppError::QuestionNotFound)),
+        UpdateOutcome::Stale { current } => Err(reject::custom(AppError::PreconditionFailed { current })),
+        UpdateOutcome::Locked => Err(reject::custom(AppError::QuestionLocked)),
+    }
+}
+
+/// Refuses everybody but the author of question `id` and admins, and everybody once the question is locked
+pub(crate) async fn check_editor(store: &Store, session: &Session, id: i32) -> Result<(), Rejection> {
+    check_author_or_admin(store, session, id, false).await
+}
+
+/// Like `check_editor` but admins still get through on a locked question, to delete it or roll back an edit
+pub(crate) async fn check_moderator(store: &Store, session: &Session, id: i32) -> Result<(), Rejection> {
+    check_author_or_admin(store, session, id, true).await
+}
+
+async fn check_author_or_admin(store: &Store, session: &Session, id: i32, admin_over_lock: bool) -> Result<(), Rejection> {
+    let question = store
+        .question(id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| reject::custom(AppError::QuestionNotFound))?;
+    let admin = is_admin(store, session).await?;
+    if question.account_id != session.id && !admin {
+        return Err(reject::custom(AppError::Forbidden));
+    }
+    if admin && admin_over_lock {
+        return Ok(());
+    }
+    check_unlocked(&question)
+}
+
+pub(crate) fn check_unlocked(question: &Question) -> Result<(), Rejection> {
+    match question.state {
+        QuestionState::Locked => Err(reject::custom(AppError::QuestionLocked)),
+        _ => Ok(()),
+    }
+}
+
+/// Soft deletion by the author or an admin, admins can restore the question until it is purged
+pub async fn del_q(id: u32, session: Session, store: Store) -> Result<impl Reply, Rejection> {
+    let id = id as i32;
+    check_moderator(&store, &session, id).await?;
+    match store.delete_post(PostTarget::Question(id), session.id).await {
+        Ok(true) => Ok(reply::with_status(
             format!("{id} has been deleted"),
             StatusCode::ACCEPTED,
         )),
+        Ok(false) => Err(reject::custom(AppError::QuestionNotFound)),
         Err(e) => {
             error!("Failed to delete question {:?}", e);
             Err(warp::reject::custom(AppError::DbQueryError))
         }
     }
 }
+
+#[cfg(test)]
+mod questions_tests {
+    use super::accepted;
+    use crate::types::question::{AcceptOutcome, Question};
+    use error_handler::AppError;
+
+    #[test]
+    fn test_accepted() {
+        let q: Question = serde_json::from_value(serde_json::json!({
+            "id": 7,
+            "title": "Title",
+            "content": "Content",
+            "tags": null,
+            "accepted_answer_id": 3,
+        }))
+        .unwrap();
+        let q = accepted(AcceptOutcome::Accepted(q)).unwrap();
+        assert_eq!((q.id.0, q.accepted_answer_id), (7, Some(3)));
+
+        let rejection = accepted(AcceptOutcome::NotFound).unwrap_err();
+        assert!(matches!(rejection.find(), Some(AppError::QuestionNotFound)));
+        let rejection = accepted(AcceptOutcome::NotAuthor).unwrap_err();
+        assert!(matches!(rejection.find(), Some(AppError::Forbidden)));
+        let rejection = accepted(AcceptOutcome::ForeignAnswer).unwrap_err();
+        match rejection.find() {
+            Some(AppError::Validation(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].field, "answer_id");
+            }
+            other => panic!("unexpected rejection {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use error_handler::AppError;
+use tracing::info;
+use warp::{reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::{
+    auth::{db_error, is_admin},
+    questions::{check_moderator, updated},
+  },
+  store::Store,
+  types::{
+    account::Session,
+    revision::{DiffQuery, Revision, RevisionDiff},
+  },
+};
+
+/// Every question has at least the revision it was created with
+pub async fn revisions(id: i32, store: Store) -> Result<impl Reply, Rejection> {
+  let revisions = all_revisions(&store, id).await?;
+  Ok(reply::json(&revisions))
+}
+
+pub async fn revision(id: i32, rev: i32, store: Store) -> Result<impl Reply, Rejection> {
+  let revisions = all_revisions(&store, id).await?;
+  Ok(reply::json(find_revision(&revisions, rev)?))
+}
+
+pub async fn diff(id: i32, store: Store, q: DiffQuery) -> Result<impl Reply, Rejection> {
+  let revisions = all_revisions(&store, id).await?;
+  let latest = revisions.last().map(|r| r.revision).unwrap_or_default();
+  let to = q.to.unwrap_or(latest);
+  let from = q.from.unwrap_or((to - 1).max(1));
+
+  let diff = RevisionDiff::between(find_revision(&revisions, from)?, find_revision(&revisions, to)?);
+  Ok(reply::json(&diff))
+}
+
+/// `POST /q/{id}/revisions/{rev}/rollback`, for the author or an admin, only admins can roll back a locked question.
+/// Tags merged into others since are mapped to their canonical slug.
+pub async fn rollback(id: i32, rev: i32, session: Session, store: Store) -> Result<impl Reply, Rejection> {
+  check_moderator(&store, &session, id).await?;
+  let revisions = all_revisions(&store, id).await?;
+  let to = find_revision(&revisions, rev)?;
+  let tags = match &to.tags {
+    Some(tags) => Some(store.canonical_tags(tags.clone()).await.map_err(db_error)?),
+    None => None,
+  };
+
+  let admin = is_admin(&store, &session).await?;
+  let q = updated(store.rollback_q(to, tags, session.id, admin).await.map_err(db_error)?)?;
+  info!("Q {} rolled back to revision {} by {:?}", id, rev, session.id);
+  Ok(reply::json(&q))
+}
+
+async fn all_revisions(store: &Store, id: i32) -> Result<Vec<Revision>, Rejection> {
+  let revisions = store.revisions(id).await.map_err(db_error)?;
+  if revisions.is_empty() {
+    return Err(reject::custom(AppError::QuestionNotFound));
+  }
+  Ok(revisions)
+}
+
+fn find_revision(revisions: &[Revision], rev: i32) -> Result<&Revision, Rejection> {
+  revisions
+    .iter()
+    .find(|r| r.revision == rev)
+    .ok_or_else(|| reject::custom(AppError::RevisionNotFound))
+}
@@ -0,0 +1,87 @@
+use error_handler::AppError;
+use warp::{http::StatusCode, reject, reply, Rejection, Reply};
+
+use crate::{
+  routes::auth::{add_audit, db_error},
+  store::Store,
+  types::{
+    account::Session,
+    audit::{self, NewAuditEntry},
+    tag::{slugify, NewSynonym, SynonymOutcome, TagQuery, TagUpdate},
+  },
+  validation::{field_errors, validate_tags},
+};
+
+/// Slugs of the tags given to a question, with synonyms replaced by their tag
+pub(crate) async fn normalize_tags(store: &Store, tags: Vec<String>) -> Result<Vec<String>, Rejection> {
+  let slugs: Vec<String> = tags.iter().map(|t| slugify(t)).collect();
+  let errors = validate_tags(&slugs);
+  if !errors.is_empty() {
+    return Err(reject::custom(AppError::Validation(errors)));
+  }
+  store.canonical_tags(slugs).await.map_err(db_error)
+}
+
+pub async fn tags(store: Store, q: TagQuery) -> Result<impl Reply, Rejection> {
+  let tags = store.tags(q).await.map_err(db_error)?;
+  Ok(reply::json(&tags))
+}
+
+pub async fn tag(slug: String, store: Store) -> Result<impl Reply, Rejection> {
+  match store.tag(&slug).await.map_err(db_error)? {
+    Some(tag) => Ok(reply::json(&tag)),
+    None => Err(reject::custom(AppError::TagNotFound)),
+  }
+}
+
+/// `PUT /tags/{slug}`, needs the `EditTags` privilege. An empty description clears it
+pub async fn update_tag(slug: String, _session: Session, store: Store, body: TagUpdate) -> Result<impl Reply, Rejection> {
+  let description = body.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+  if description.as_ref().is_some_and(|d| d.chars().count() > 2000) {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "description",
+      ["must be at most 2000 characters"],
+    ))));
+  }
+
+  match store.set_tag_description(&slug, description).await.map_err(db_error)? {
+    Some(tag) => Ok(reply::json(&tag)),
+    None => Err(reject::custom(AppError::TagNotFound)),
+  }
+}
+
+pub async fn add_synonym(admin: Session, slug: String, store: Store, body: NewSynonym) -> Result<impl Reply, Rejection> {
+  let synonym = slugify(&body.synonym);
+  if synonym.is_empty() || synonym == slug {
+    return Err(reject::custom(AppError::Validation(field_errors(
+      "synonym",
+      ["must be a slug other than the tag"],
+    ))));
+  }
+
+  match store.add_tag_synonym(&slug, &synonym).await.map_err(db_error)? {
+    SynonymOutcome::Added(tag) => {
+      add_audit(&store, tag_entry(&admin, format!("{} made a synonym of {}", synonym, slug))).await;
+      Ok(reply::json(&tag))
+    }
+    SynonymOutcome::TagNotFound => Err(reject::custom(AppError::TagNotFound)),
+  }
+}
+
+pub async fn remove_synonym(admin: Session, slug: String, synonym: String, store: Store) -> Result<impl Reply, Rejection> {
+  if !store.remove_tag_synonym(&slug, &synonym).await.map_err(db_error)? {
+    return Err(reject::custom(AppError::TagNotFound));
+  }
+  add_audit(&store, tag_entry(&admin, format!("{} no longer a synonym of {}", synonym, slug))).await;
+  Ok(reply::with_status("Removed", StatusCode::OK))
+}
+
+fn tag_entry(admin: &Session, detail: String) -> NewAuditEntry {
+  NewAuditEntry {
+    action: audit::ADMIN,
+    actor_id: admin.id,
+    success: true,
+    detail: Some(detail),
+    ..Default::default()
+  }
+}
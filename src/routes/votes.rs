@@ -0,0 +1,50 @@
+use error_handler::AppError;
+use warp::{reject, reply, Rejection, Reply};
+
+use crate::{
+  reputation::ReputationConfig,
+  routes::auth::db_error,
+  store::Store,
+  types::{
+    account::Session,
+    post::PostTarget,
+    vote::{NewVote, VoteOutcome},
+  },
+  validation::field_errors,
+};
+
+/// `PUT /q/{id}/vote` and `PUT /a/{id}/vote`, voting again replaces the previous vote
+pub async fn vote(
+  target: PostTarget,
+  session: Session,
+  store: Store,
+  conf: ReputationConfig,
+  body: NewVote,
+) -> Result<impl Reply, Rejection> {
+  if !matches!(body.value, -1 | 1) {
+    return Err(reject::custom(AppError::Validation(field_errors("value", ["must be 1 or -1"]))));
+  }
+  cast(target, session, store, conf, Some(body.value)).await
+}
+
+pub async fn unvote(target: PostTarget, session: Session, store: Store, conf: ReputationConfig) -> Result<impl Reply, Rejection> {
+  cast(target, session, store, conf, None).await
+}
+
+async fn cast(
+  target: PostTarget,
+  session: Session,
+  store: Store,
+  conf: ReputationConfig,
+  value: Option<i16>,
+) -> Result<reply::Json, Rejection> {
+  match store
+    .vote(target, session.id.unwrap_or_default(), value, &conf)
+    .await
+    .map_err(db_error)?
+  {
+    VoteOutcome::Voted(result) => Ok(reply::json(&result)),
+    VoteOutcome::OwnPost => Err(reject::custom(AppError::OwnPost)),
+    VoteOutcome::NotFound => Err(reject::custom(target.not_found())),
+  }
+}
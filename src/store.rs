@@ -1,9 +1,46 @@
-use sqlx::{postgres::{PgPoolOptions, PgRow}, Pool, Postgres, Row};
+use std::collections::HashMap;
 
-use tracing::{error, info};
-use crate::types::account::{Account, AccountId};
+use chrono::{DateTime, Utc};
+use sqlx::{
+  migrate::Migrator,
+  pool::PoolConnection,
+  postgres::{PgPoolOptions, PgRow},
+  Connection, PgConnection, Pool, Postgres, Row,
+};
 
-use crate::types::question::{Question, QuestionId, QuestionPayload};
+use tracing::{error, info, instrument};
+use crate::metrics;
+use crate::rate_limit::{self, Decision, Limit};
+use crate::duplicates::DuplicateConfig;
+use crate::reputation::ReputationConfig;
+use crate::types::account::{
+  Account, AccountId, AccountStatus, Profile, ProfileUpdate, PublicProfile, Role, TokenPurpose, TotpState,
+};
+use crate::types::admin::{AccountQuery, AccountSummary, Activity, AdminAccount};
+use crate::types::answer::{AddAnswerOutcome, Answer, AnswerDetail, AnswerId, AnswerPatch};
+use crate::types::api_key::{ApiKey, KeyGrant, Scope};
+use crate::types::audit::{self, AuditEntry, AuditQuery, NewAuditEntry};
+use crate::types::comment::Comment;
+use crate::types::export::AccountExport;
+use crate::types::flag::Flag;
+
+use crate::types::moderation::{CloseReason, ModerationEntry, ModerationQuery, QuestionState, StateOutcome};
+use crate::types::paging::PageQuery;
+use crate::types::question::{
+  AcceptOutcome, DuplicateCandidate, DuplicateOutcome, Question, QuestionDetail, QuestionFilter, QuestionId, QuestionPatch,
+  QuestionPayload, QuestionSort,
+};
+use crate::types::post::{PostTarget, UpdateOutcome, VISIBLE_ANSWER};
+use crate::types::reputation::{self, ReputationEvent};
+use crate::types::revision::{self, Revision};
+use crate::types::tag::{slugify, SynonymOutcome, Tag, TagQuery};
+use crate::types::vote::{Vote, VoteOutcome, VoteResult};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Synonyms column of the tag queries, which join `tag_synonyms s` and group by the tag
+const TAG_SYNONYMS: &str =
+  "coalesce(array_agg(s.slug ORDER BY s.slug) FILTER (WHERE s.slug IS NOT NULL), '{}') AS synonyms";
 
 #[derive(Debug, Clone)]
 pub struct Store {
@@ -20,24 +57,56 @@ impl Store {
     Store { pool }
   }
 
+  /// Checks a connection out of the pool, recording how long the caller had to wait for it
+  async fn conn(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let timer = metrics::DB_ACQUIRE_DURATION.start_timer();
+    let conn = self.pool.acquire().await;
+    timer.observe_duration();
+    conn
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn ping(&self) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(&mut *self.conn().await?).await.map(|_| ())
+  }
+
+  /// Versions of the embedded migrations which are not applied successfully yet
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error> {
+    let applied = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success")
+      .map(|row: PgRow| row.get::<i64, _>("version"))
+      .fetch_all(&mut *self.conn().await?)
+      .await?;
+
+    Ok(
+      MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect(),
+    )
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
   pub async fn get_q(
     &self,
     limit: Option<i32>,
     offset: i32,
+    sort: QuestionSort,
+    filter: Option<QuestionFilter>,
+    state: Option<QuestionState>,
   ) -> Result<Vec<Question>, sqlx::Error> {
-    let qs = sqlx::query("SELECT * FROM questions LIMIT $1 OFFSET $2")
+    let qs = sqlx::query(&format!(
+      "SELECT * FROM questions WHERE deleted_at IS NULL AND {} AND ($3::text IS NULL OR state = $3)
+            ORDER BY {} LIMIT $1 OFFSET $2",
+      filter.map_or("true", |f| f.condition()),
+      sort.order_by()
+    ))
       .bind(limit)
       .bind(offset)
-      .map(|row: PgRow| {
-        let id = row.get::<i32, _>("id");
-        Question {
-          id: QuestionId(id as u32),
-          title: row.get("title"),
-          content: row.get("content"),
-          tags: row.get("tags"),
-        }
-      })
-      .fetch_all(&self.pool)
+      .bind(state.map(|s| s.as_str()))
+      .map(question_from_row)
+      .fetch_all(&mut *self.conn().await?)
       .await;
 
     match qs {
@@ -49,67 +118,876 @@ impl Store {
     }
   }
 
-  pub async fn add_q(&self, q: QuestionPayload) -> Result<Question, sqlx::Error> {
-    sqlx::query(
-      "INSERT INTO questions (title, content, tags)
-            VALUES ($1, $2, $3)
-            RETURNING id, title, content, tags",
+  /// The question with its comments and answers, the accepted answer first and the rest by score
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn question_detail(&self, id: i32) -> Result<Option<QuestionDetail>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let Some(question) = sqlx::query("SELECT * FROM questions WHERE id = $1 AND deleted_at IS NULL")
+      .bind(id)
+      .map(question_from_row)
+      .fetch_optional(&mut *conn)
+      .await?
+    else {
+      return Ok(None);
+    };
+
+    let answers = sqlx::query(
+      "SELECT * FROM answers WHERE corresponding_question = $1 AND deleted_at IS NULL
+            ORDER BY id IS NOT DISTINCT FROM $2 DESC, score DESC, id",
     )
-      .bind(q.title)
-      .bind(q.content)
-      .bind(q.tags)
-      .map(|row: PgRow| {
-        let id = row.get::<i32, _>("id");
-        Question {
-          id: QuestionId(id as u32),
-          title: row.get("title"),
-          content: row.get("content"),
-          tags: row.get("tags"),
-        }
+      .bind(id)
+      .bind(question.accepted_answer_id)
+      .map(answer_from_row)
+      .fetch_all(&mut *conn)
+      .await?;
+
+    let (comments, answer_comments): (Vec<_>, Vec<_>) = sqlx::query(
+      "SELECT c.* FROM comments c LEFT JOIN answers a ON a.id = c.answer_id
+            WHERE c.question_id = $1 OR a.corresponding_question = $1
+            ORDER BY c.id",
+    )
+      .bind(id)
+      .map(comment_from_row)
+      .fetch_all(&mut *conn)
+      .await?
+      .into_iter()
+      .partition(|c| c.question_id.is_some());
+
+    let mut by_answer: HashMap<i32, Vec<Comment>> = HashMap::new();
+    for comment in answer_comments {
+      by_answer.entry(comment.answer_id.unwrap_or_default()).or_default().push(comment);
+    }
+    let answers = answers
+      .into_iter()
+      .map(|answer| AnswerDetail {
+        comments: by_answer.remove(&answer.id.0).unwrap_or_default(),
+        answer,
       })
-      .fetch_one(&self.pool)
+      .collect();
+    Ok(Some(QuestionDetail { question, comments, answers }))
+  }
+
+  /// `None` when `target` does not exist
+  #[instrument(skip(self, content), fields(db.system = "postgresql"))]
+  pub async fn add_comment(&self, target: PostTarget, account_id: i32, content: String) -> Result<Option<Comment>, sqlx::Error> {
+    sqlx::query(&format!(
+      "INSERT INTO comments (account_id, {col}, content) SELECT $1, id, $3 FROM {table} WHERE id = $2 AND {visible} RETURNING *",
+      col = target.column(),
+      table = target.table(),
+      visible = target.visible()
+    ))
+      .bind(account_id)
+      .bind(target.id())
+      .bind(content)
+      .map(comment_from_row)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Oldest first, `None` when `target` does not exist
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn comments(&self, target: PostTarget, page: &PageQuery) -> Result<Option<Vec<Comment>>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = $1 AND {}", target.table(), target.visible()))
+      .bind(target.id())
+      .fetch_optional(&mut *conn)
+      .await?
+      .is_some();
+    if !exists {
+      return Ok(None);
+    }
+
+    sqlx::query(&format!("SELECT * FROM comments WHERE {} = $1 ORDER BY id LIMIT $2 OFFSET $3", target.column()))
+      .bind(target.id())
+      .bind(page.limit())
+      .bind(page.offset())
+      .map(comment_from_row)
+      .fetch_all(&mut *conn)
       .await
+      .map(Some)
   }
 
-  pub async fn del_q(&self, id: i32) -> Result<i32, sqlx::Error> {
-    sqlx::query("DELETE FROM questions WHERE id = $1 RETURNING id")
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn comment(&self, id: i32) -> Result<Option<Comment>, sqlx::Error> {
+    sqlx::query("SELECT * FROM comments WHERE id = $1")
       .bind(id)
-      .map(|row: PgRow| row.get::<i32, _>("id"))
-      .fetch_one(&self.pool)
+      .map(comment_from_row)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self, content), fields(db.system = "postgresql"))]
+  pub async fn update_comment(&self, id: i32, content: String) -> Result<Option<Comment>, sqlx::Error> {
+    sqlx::query("UPDATE comments SET content = $2, updated_at = now() WHERE id = $1 RETURNING *")
+      .bind(id)
+      .bind(content)
+      .map(comment_from_row)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn delete_comment(&self, id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM comments WHERE id = $1")
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|res| res.rows_affected() > 0)
+  }
+
+  /// Marks `answer_id` as the accepted answer of the question, replacing an earlier one,
+  /// or clears it when `None`. Only the author of the question may do so
+  #[instrument(skip(self, conf), fields(db.system = "postgresql"))]
+  pub async fn accept_answer(
+    &self,
+    id: i32,
+    account_id: i32,
+    answer_id: Option<i32>,
+    conf: &ReputationConfig,
+  ) -> Result<AcceptOutcome, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let author = sqlx::query("SELECT account_id FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+      .bind(id)
+      .map(|row: PgRow| row.get::<Option<i32>, _>("account_id"))
+      .fetch_optional(&mut *tx)
+      .await?;
+    match author {
+      None => return Ok(AcceptOutcome::NotFound),
+      Some(author) if author != Some(account_id) => return Ok(AcceptOutcome::NotAuthor),
+      Some(_) => {}
+    }
+
+    sqlx::query("DELETE FROM reputation_events WHERE question_id = $1 AND kind = $2")
+      .bind(id)
+      .bind(reputation::ACCEPTED)
+      .execute(&mut *tx)
+      .await?;
+
+    if let Some(answer_id) = answer_id {
+      let answerer = sqlx::query(
+        "SELECT account_id FROM answers WHERE id = $1 AND corresponding_question = $2 AND deleted_at IS NULL",
+      )
+        .bind(answer_id)
+        .bind(id)
+        .map(|row: PgRow| row.get::<Option<i32>, _>("account_id"))
+        .fetch_optional(&mut *tx)
+        .await?;
+      let Some(answerer) = answerer else {
+        return Ok(AcceptOutcome::ForeignAnswer);
+      };
+
+      // accepting your own answer earns nothing
+      if let Some(answerer) = answerer.filter(|&answerer| answerer != account_id) {
+        sqlx::query(
+          "INSERT INTO reputation_events (account_id, kind, points, question_id, answer_id)
+                VALUES ($1, $2, $3, $4, $5)",
+        )
+          .bind(answerer)
+          .bind(reputation::ACCEPTED)
+          .bind(conf.answer_accepted)
+          .bind(id)
+          .bind(answer_id)
+          .execute(&mut *tx)
+          .await?;
+      }
+    }
+
+    let question = sqlx::query("UPDATE questions SET accepted_answer_id = $2 WHERE id = $1 RETURNING *")
+      .bind(id)
+      .bind(answer_id)
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(AcceptOutcome::Accepted(question))
+  }
+
+  /// Replaces synonyms by their tag and drops the duplicates this leaves, keeping the order
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn canonical_tags(&self, slugs: Vec<String>) -> Result<Vec<String>, sqlx::Error> {
+    let synonyms: HashMap<String, String> = sqlx::query(
+      "SELECT s.slug AS synonym, t.slug FROM tag_synonyms s JOIN tags t ON t.id = s.tag_id WHERE s.slug = ANY($1)",
+    )
+      .bind(&slugs)
+      .map(|row: PgRow| (row.get("synonym"), row.get("slug")))
+      .fetch_all(&mut *self.conn().await?)
+      .await?
+      .into_iter()
+      .collect();
+
+    let mut tags: Vec<String> = vec![];
+    for slug in slugs {
+      let slug = synonyms.get(&slug).cloned().unwrap_or(slug);
+      if !tags.contains(&slug) {
+        tags.push(slug);
+      }
+    }
+    Ok(tags)
+  }
+
+  /// Tags whose slug or one of whose synonyms starts with `q.q`
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn tags(&self, q: TagQuery) -> Result<Vec<Tag>, sqlx::Error> {
+    // slugs hold no LIKE wildcards
+    let prefix = q.q.map(|q| slugify(&q)).filter(|q| !q.is_empty());
+    sqlx::query(&format!(
+      "SELECT t.*, {} FROM tags t LEFT JOIN tag_synonyms s ON s.tag_id = t.id
+            WHERE $1::text IS NULL OR t.slug LIKE $1 || '%'
+              OR EXISTS (SELECT 1 FROM tag_synonyms m WHERE m.tag_id = t.id AND m.slug LIKE $1 || '%')
+            GROUP BY t.id
+            ORDER BY {}
+            LIMIT $2 OFFSET $3",
+      TAG_SYNONYMS,
+      q.sort.order_by()
+    ))
+      .bind(prefix)
+      .bind(q.limit.unwrap_or(50).clamp(1, 500))
+      .bind(q.offset.unwrap_or(0).max(0))
+      .map(tag_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn tag(&self, slug: &str) -> Result<Option<Tag>, sqlx::Error> {
+    sqlx::query(&format!(
+      "SELECT t.*, {} FROM tags t LEFT JOIN tag_synonyms s ON s.tag_id = t.id WHERE t.slug = $1 GROUP BY t.id",
+      TAG_SYNONYMS
+    ))
+      .bind(slug)
+      .map(tag_from_row)
+      .fetch_optional(&mut *self.conn().await?)
       .await
   }
 
-  pub async fn upd_q(&self, id: i32, q: QuestionPayload) -> Result<i32, sqlx::Error> {
+  #[instrument(skip(self, description), fields(db.system = "postgresql"))]
+  pub async fn set_tag_description(&self, slug: &str, description: Option<String>) -> Result<Option<Tag>, sqlx::Error> {
+    let updated = sqlx::query("UPDATE tags SET description = $2 WHERE slug = $1")
+      .bind(slug)
+      .bind(description)
+      .execute(&mut *self.conn().await?)
+      .await?
+      .rows_affected();
+    if updated == 0 {
+      return Ok(None);
+    }
+    self.tag(slug).await
+  }
+
+  /// Makes `synonym` an alias of the tag. When `synonym` is a tag of its own it is merged:
+  /// its questions and synonyms move over and the tag goes away
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn add_tag_synonym(&self, slug: &str, synonym: &str) -> Result<SynonymOutcome, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let tag_id = sqlx::query("SELECT id FROM tags WHERE slug = $1 FOR UPDATE")
+      .bind(slug)
+      .map(|row: PgRow| row.get::<i32, _>("id"))
+      .fetch_optional(&mut *tx)
+      .await?;
+    let Some(tag_id) = tag_id else {
+      return Ok(SynonymOutcome::TagNotFound);
+    };
+
+    let merged_id = sqlx::query("SELECT id FROM tags WHERE slug = $1 FOR UPDATE")
+      .bind(synonym)
+      .map(|row: PgRow| row.get::<i32, _>("id"))
+      .fetch_optional(&mut *tx)
+      .await?;
+    if let Some(merged_id) = merged_id {
+      // questions tagged with both keep the tag once, where it came first
+      sqlx::query(
+        "UPDATE questions SET tags = (
+                SELECT array_agg(t ORDER BY ord) FROM (
+                  SELECT t, min(ord) AS ord
+                  FROM unnest(array_replace(questions.tags, $2, $1)) WITH ORDINALITY AS u(t, ord)
+                  GROUP BY t
+                ) d
+              )
+              WHERE $2 = ANY(tags)",
+      )
+        .bind(slug)
+        .bind(synonym)
+        .execute(&mut *tx)
+        .await?;
+      sqlx::query("UPDATE tag_synonyms SET tag_id = $1 WHERE tag_id = $2")
+        .bind(tag_id)
+        .bind(merged_id)
+        .execute(&mut *tx)
+        .await?;
+      sqlx::query("DELETE FROM tags WHERE id = $1")
+        .bind(merged_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
     sqlx::query(
-      "UPDATE questions SET title = $1, content = $2, tags = $3 WHERE id = $4 RETURNING id",
+      "INSERT INTO tag_synonyms (slug, tag_id) VALUES ($1, $2)
+            ON CONFLICT (slug) DO UPDATE SET tag_id = excluded.tag_id",
+    )
+      .bind(synonym)
+      .bind(tag_id)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    match self.tag(slug).await? {
+      Some(tag) => Ok(SynonymOutcome::Added(tag)),
+      None => Ok(SynonymOutcome::TagNotFound),
+    }
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn remove_tag_synonym(&self, slug: &str, synonym: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM tag_synonyms s USING tags t WHERE s.tag_id = t.id AND t.slug = $1 AND s.slug = $2")
+      .bind(slug)
+      .bind(synonym)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|res| res.rows_affected() > 0)
+  }
+
+  /// Replaces the tags of question `id` unless it is locked
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn set_tags(
+    &self,
+    id: i32,
+    tags: Vec<String>,
+    account_id: Option<i32>,
+  ) -> Result<UpdateOutcome<Question>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    match lock_state(&mut tx, id).await? {
+      None => return Ok(UpdateOutcome::NotFound),
+      Some(QuestionState::Locked) => return Ok(UpdateOutcome::Locked),
+      Some(_) => {}
+    }
+
+    let question = sqlx::query("UPDATE questions SET tags = $2 WHERE id = $1 RETURNING *")
+      .bind(id)
+      .bind(tags)
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_revision(&mut tx, &question, account_id, revision::RETAG, None).await?;
+    tx.commit().await?;
+    Ok(UpdateOutcome::Updated(question))
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn question(&self, id: i32) -> Result<Option<Question>, sqlx::Error> {
+    sqlx::query("SELECT * FROM questions WHERE id = $1 AND deleted_at IS NULL")
+      .bind(id)
+      .map(question_from_row)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self, q), fields(db.system = "postgresql"))]
+  pub async fn add_q(&self, q: QuestionPayload, account_id: Option<i32>) -> Result<Question, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let question = sqlx::query(
+      "INSERT INTO questions (title, content, tags, account_id)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *",
     )
       .bind(q.title)
       .bind(q.content)
       .bind(q.tags)
+      .bind(account_id)
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_revision(&mut tx, &question, account_id, revision::CREATE, None).await?;
+    tx.commit().await?;
+    Ok(question)
+  }
+
+  /// Other questions with a title similar to the one of `q`, ranked higher for every tag they share with it.
+  /// Questions closed as duplicates are left out, their canonical question shows up instead
+  #[instrument(skip(self, q, conf), fields(db.system = "postgresql"))]
+  pub async fn similar_questions(&self, q: &Question, conf: &DuplicateConfig) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    // `%` goes through the trigram index where a `similarity()` comparison scans every title
+    sqlx::query("SELECT set_config('pg_trgm.similarity_threshold', $1, true)")
+      .bind(conf.min_title_similarity.to_string())
+      .execute(&mut *tx)
+      .await?;
+    let candidates = sqlx::query(
+      "SELECT id, title, tags,
+              (similarity(title, $2)
+                + $3 * cardinality(ARRAY(SELECT unnest(tags) INTERSECT SELECT unnest($4::text[]))))::real AS score
+            FROM questions
+            WHERE title % $2 AND id <> $1 AND deleted_at IS NULL AND duplicate_of IS NULL
+            ORDER BY score DESC, id
+            LIMIT $5",
+    )
+      .bind(q.id.0 as i32)
+      .bind(&q.title)
+      .bind(conf.tag_weight)
+      .bind(q.tags.clone().unwrap_or_default())
+      .bind(conf.max_candidates)
+      .map(|row: PgRow| DuplicateCandidate {
+        id: row.get("id"),
+        title: row.get("title"),
+        tags: row.get("tags"),
+        score: row.get("score"),
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(candidates)
+  }
+
+  /// Closes question `id` as a duplicate of `canonical`, or of the question `canonical` duplicates.
+  /// Questions closed as duplicates of `id` move along so that links never chain
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn mark_duplicate(&self, id: i32, canonical: i32, actor_id: Option<i32>) -> Result<DuplicateOutcome, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    // both rows are locked in id order, so that two admins closing them against each other cannot deadlock
+    let rows = sqlx::query(
+      "SELECT id, state, coalesce(duplicate_of, id) AS canonical FROM questions
+            WHERE id = ANY($1) AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+    )
+      .bind(vec![id, canonical])
+      .map(|row: PgRow| {
+        let state: QuestionState = row.get::<String, _>("state").parse().unwrap_or_default();
+        (row.get::<i32, _>("id"), state, row.get::<i32, _>("canonical"))
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+    let Some(&(_, from, _)) = rows.iter().find(|(row, ..)| *row == id) else {
+      return Ok(DuplicateOutcome::NotFound);
+    };
+    let canonical = match rows.iter().find(|(row, ..)| *row == canonical) {
+      None => return Ok(DuplicateOutcome::CanonicalNotFound),
+      Some(&(_, _, canonical)) if canonical == id => return Ok(DuplicateOutcome::SameQuestion),
+      Some(&(_, _, canonical)) => canonical,
+    };
+
+    let question = sqlx::query(
+      "UPDATE questions SET duplicate_of = $2, state = $3, close_reason = $4 WHERE id = $1 RETURNING *",
+    )
+      .bind(id)
+      .bind(canonical)
+      .bind(QuestionState::Closed.as_str())
+      .bind(CloseReason::Duplicate.as_str())
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_moderation(&mut tx, &question, from, actor_id).await?;
+    sqlx::query("UPDATE questions SET duplicate_of = $2 WHERE duplicate_of = $1")
       .bind(id)
-      .map(|row: PgRow| row.get("id"))
-      .fetch_one(&self.pool)
+      .bind(canonical)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(DuplicateOutcome::Marked(question))
+  }
+
+  /// Reopens question `id`, `None` when it is not closed as a duplicate
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn unmark_duplicate(&self, id: i32, actor_id: Option<i32>) -> Result<Option<Question>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let question = sqlx::query(
+      "UPDATE questions SET duplicate_of = NULL, state = $2, close_reason = NULL
+            WHERE id = $1 AND deleted_at IS NULL AND duplicate_of IS NOT NULL RETURNING *",
+    )
+      .bind(id)
+      .bind(QuestionState::Open.as_str())
+      .map(question_from_row)
+      .fetch_optional(&mut *tx)
+      .await?;
+    if let Some(q) = &question {
+      add_moderation(&mut tx, q, QuestionState::Closed, actor_id).await?;
+    }
+    tx.commit().await?;
+    Ok(question)
+  }
+
+  /// Moves question `id` to `state`, `reason` is only kept for closed questions.
+  /// Leaving the duplicate close reason drops the link to the canonical question
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn set_state(
+    &self,
+    id: i32,
+    state: QuestionState,
+    reason: Option<CloseReason>,
+    actor_id: Option<i32>,
+  ) -> Result<StateOutcome<Question>, sqlx::Error> {
+    let reason = reason.filter(|_| state == QuestionState::Closed);
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let current = sqlx::query("SELECT * FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+      .bind(id)
+      .map(question_from_row)
+      .fetch_optional(&mut *tx)
+      .await?;
+    let current = match current {
+      None => return Ok(StateOutcome::NotFound),
+      Some(q) if q.state == state && q.close_reason == reason => return Ok(StateOutcome::Unchanged),
+      Some(q) => q,
+    };
+
+    let question = sqlx::query(
+      "UPDATE questions SET state = $2, close_reason = $3,
+              duplicate_of = CASE WHEN $3 = 'duplicate' THEN duplicate_of END
+            WHERE id = $1 RETURNING *",
+    )
+      .bind(id)
+      .bind(state.as_str())
+      .bind(reason.map(|r| r.as_str()))
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_moderation(&mut tx, &question, current.state, actor_id).await?;
+    tx.commit().await?;
+    Ok(StateOutcome::Changed(question))
+  }
+
+  /// Newest first
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn moderation_log(&self, q: ModerationQuery) -> Result<Vec<ModerationEntry>, sqlx::Error> {
+    sqlx::query(
+      "SELECT * FROM moderation_log WHERE $1::integer IS NULL OR question_id = $1
+            ORDER BY id DESC LIMIT $2 OFFSET $3",
+    )
+      .bind(q.question_id)
+      .bind(q.limit.unwrap_or(50).clamp(1, 500))
+      .bind(q.offset.unwrap_or(0).max(0))
+      .map(|row: PgRow| ModerationEntry {
+        id: row.get("id"),
+        question_id: row.get("question_id"),
+        actor_id: row.get("actor_id"),
+        from_state: row.get("from_state"),
+        to_state: row.get("to_state"),
+        reason: row.get("reason"),
+        created_at: row.get("created_at"),
+      })
+      .fetch_all(&mut *self.conn().await?)
       .await
   }
 
-  pub(crate) async fn add_a(&self, qid: i32, content: String) -> Result<bool, sqlx::Error> {
-    match sqlx::query(
-      "INSERT INTO answers(content, corresponding_question) VALUES ($1, $2) RETURNING id",
+  /// Oldest first
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn revisions(&self, question_id: i32) -> Result<Vec<Revision>, sqlx::Error> {
+    sqlx::query(
+      "SELECT r.* FROM question_revisions r JOIN questions q ON q.id = r.question_id
+            WHERE r.question_id = $1 AND q.deleted_at IS NULL
+            ORDER BY r.revision",
+    )
+      .bind(question_id)
+      .map(revision_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Restores title, content and `tags` of `to`, which becomes a new revision itself.
+  /// Locked questions are only rolled back with `over_lock`
+  #[instrument(skip(self, to, tags), fields(db.system = "postgresql"))]
+  pub async fn rollback_q(
+    &self,
+    to: &Revision,
+    tags: Option<Vec<String>>,
+    account_id: Option<i32>,
+    over_lock: bool,
+  ) -> Result<UpdateOutcome<Question>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    match lock_state(&mut tx, to.question_id).await? {
+      None => return Ok(UpdateOutcome::NotFound),
+      Some(QuestionState::Locked) if !over_lock => return Ok(UpdateOutcome::Locked),
+      Some(_) => {}
+    }
+
+    let question = sqlx::query("UPDATE questions SET title = $2, content = $3, tags = $4 WHERE id = $1 RETURNING *")
+      .bind(to.question_id)
+      .bind(&to.title)
+      .bind(&to.content)
+      .bind(tags)
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_revision(&mut tx, &question, account_id, revision::ROLLBACK, Some(to.revision)).await?;
+    tx.commit().await?;
+    Ok(UpdateOutcome::Updated(question))
+  }
+
+  /// Soft-deletes `target` until `purge_deleted` gets to it, `false` when it is not there or deleted already.
+  /// The reputation earned on it is void from now on
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn delete_post(&self, target: PostTarget, account_id: Option<i32>) -> Result<bool, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let deleted = sqlx::query(&format!(
+      "UPDATE {} SET deleted_at = now(), deleted_by = $2 WHERE id = $1 AND {}",
+      target.table(),
+      target.visible()
+    ))
+      .bind(target.id())
+      .bind(account_id)
+      .execute(&mut *tx)
+      .await?
+      .rows_affected()
+      > 0;
+    if deleted {
+      void_reputation(&mut tx, Some(target)).await?;
+    }
+    tx.commit().await?;
+    Ok(deleted)
+  }
+
+  /// Undoes `delete_post`, `false` when `target` is not deleted
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn restore_post(&self, target: PostTarget) -> Result<bool, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let restored = sqlx::query(&format!(
+      "UPDATE {} SET deleted_at = NULL, deleted_by = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+      target.table()
+    ))
+      .bind(target.id())
+      .execute(&mut *tx)
+      .await?
+      .rows_affected()
+      > 0;
+    if restored {
+      void_reputation(&mut tx, Some(target)).await?;
+    }
+    tx.commit().await?;
+    Ok(restored)
+  }
+
+  /// Hard-deletes the posts deleted more than `retention_days` ago, with the answers of such questions.
+  /// Their reputation went void on deletion, so purging them leaves reputation as it is.
+  /// Returns how many questions and answers went
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn purge_deleted(&self, retention_days: i32) -> Result<(u64, u64), sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let answers = sqlx::query(
+      "DELETE FROM answers
+            WHERE deleted_at < now() - make_interval(days => $1)
+              OR corresponding_question IN (
+                SELECT id FROM questions WHERE deleted_at < now() - make_interval(days => $1)
+              )",
+    )
+      .bind(retention_days)
+      .execute(&mut *tx)
+      .await?
+      .rows_affected();
+    let questions = sqlx::query("DELETE FROM questions WHERE deleted_at < now() - make_interval(days => $1)")
+      .bind(retention_days)
+      .execute(&mut *tx)
+      .await?
+      .rows_affected();
+    tx.commit().await?;
+    Ok((questions, answers))
+  }
+
+  /// Sets the fields `patch` has, unless the question is locked or moved past version `expected` meanwhile.
+  /// The result is kept as a revision
+  #[instrument(skip(self, patch), fields(db.system = "postgresql"))]
+  pub async fn upd_q(
+    &self,
+    id: i32,
+    patch: QuestionPatch,
+    expected: Option<i32>,
+    account_id: Option<i32>,
+  ) -> Result<UpdateOutcome<Question>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let current = match sqlx::query("SELECT * FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+      .bind(id)
+      .map(question_from_row)
+      .fetch_optional(&mut *tx)
+      .await?
+    {
+      Some(q) => q,
+      None => return Ok(UpdateOutcome::NotFound),
+    };
+    if current.state == QuestionState::Locked {
+      return Ok(UpdateOutcome::Locked);
+    }
+    if expected.is_some_and(|v| v != current.version) {
+      return Ok(UpdateOutcome::Stale { current: current.version });
+    }
+
+    let question = sqlx::query(
+      "UPDATE questions SET title = $1, content = $2, tags = $3 WHERE id = $4 RETURNING *",
     )
+      .bind(patch.title.unwrap_or(current.title))
+      .bind(patch.content.unwrap_or(current.content))
+      .bind(patch.tags.unwrap_or(current.tags))
+      .bind(id)
+      .map(question_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    add_revision(&mut tx, &question, account_id, revision::EDIT, None).await?;
+    tx.commit().await?;
+    Ok(UpdateOutcome::Updated(question))
+  }
+
+  /// Answers question `qid` unless its state forbids it, `answer_protected` lets the account answer protected questions.
+  /// The question row stays locked until the answer is in, so that a state change cannot slip in between
+  #[instrument(skip(self, content), fields(db.system = "postgresql"))]
+  pub(crate) async fn add_a(
+    &self,
+    qid: i32,
+    content: String,
+    account_id: Option<i32>,
+    answer_protected: bool,
+  ) -> Result<AddAnswerOutcome, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    match lock_state(&mut tx, qid).await? {
+      None => return Ok(AddAnswerOutcome::QuestionNotFound),
+      Some(QuestionState::Open) => {}
+      Some(QuestionState::Protected) if answer_protected => {}
+      Some(state) => return Ok(AddAnswerOutcome::Refused(state)),
+    }
+
+    let id = sqlx::query("INSERT INTO answers(content, corresponding_question, account_id) VALUES ($1, $2, $3) RETURNING id")
       .bind(content)
       .bind(qid)
+      .bind(account_id)
       .map(|row: PgRow| row.get::<i32, _>("id"))
-      .fetch_one(&self.pool)
+      .fetch_one(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    info!("New answer [{id}] created");
+    Ok(AddAnswerOutcome::Added)
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn answer(&self, id: i32) -> Result<Option<Answer>, sqlx::Error> {
+    sqlx::query(&format!("SELECT * FROM answers WHERE id = $1 AND {}", VISIBLE_ANSWER))
+      .bind(id)
+      .map(answer_from_row)
+      .fetch_optional(&mut *self.conn().await?)
       .await
+  }
+
+  /// Like `upd_q`, answers keep no revisions
+  #[instrument(skip(self, patch), fields(db.system = "postgresql"))]
+  pub async fn upd_a(&self, id: i32, patch: AnswerPatch, expected: Option<i32>) -> Result<UpdateOutcome<Answer>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    let current = match sqlx::query(&format!("SELECT * FROM answers WHERE id = $1 AND {} FOR UPDATE", VISIBLE_ANSWER))
+      .bind(id)
+      .map(answer_from_row)
+      .fetch_optional(&mut *tx)
+      .await?
     {
-      Ok(id) => {
-        info!("New answer [{id}] created");
-        Ok(true)
+      Some(a) => a,
+      None => return Ok(UpdateOutcome::NotFound),
+    };
+    if lock_state(&mut tx, current.qid.0 as i32).await? == Some(QuestionState::Locked) {
+      return Ok(UpdateOutcome::Locked);
+    }
+    if expected.is_some_and(|v| v != current.version) {
+      return Ok(UpdateOutcome::Stale { current: current.version });
+    }
+
+    let answer = sqlx::query("UPDATE answers SET content = $2 WHERE id = $1 RETURNING *")
+      .bind(id)
+      .bind(patch.content.unwrap_or(current.content))
+      .map(answer_from_row)
+      .fetch_one(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    Ok(UpdateOutcome::Updated(answer))
+  }
+
+  /// Casts, changes or withdraws (`value` is `None`) the vote of `account_id` on `target`,
+  /// authors may not vote on their own posts
+  #[instrument(skip(self, conf), fields(db.system = "postgresql"))]
+  pub async fn vote(
+    &self,
+    target: PostTarget,
+    account_id: i32,
+    value: Option<i16>,
+    conf: &ReputationConfig,
+  ) -> Result<VoteOutcome, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let author = sqlx::query(&format!("SELECT account_id FROM {} WHERE id = $1 AND {}", target.table(), target.visible()))
+      .bind(target.id())
+      .map(|row: PgRow| row.get::<Option<i32>, _>("account_id"))
+      .fetch_optional(&mut *tx)
+      .await?;
+    let author = match author {
+      None => return Ok(VoteOutcome::NotFound),
+      Some(Some(author)) if author == account_id => return Ok(VoteOutcome::OwnPost),
+      Some(author) => author,
+    };
+
+    match value {
+      Some(value) => {
+        // the conflict target repeats the predicate of the partial unique index
+        let vote_id = sqlx::query(&format!(
+          "INSERT INTO votes (account_id, {col}, value) VALUES ($1, $2, $3)
+                ON CONFLICT ({col}, account_id) WHERE {col} IS NOT NULL
+                DO UPDATE SET value = excluded.value, updated_at = now() WHERE votes.value <> excluded.value
+                RETURNING id",
+          col = target.column()
+        ))
+          .bind(account_id)
+          .bind(target.id())
+          .bind(value)
+          .map(|row: PgRow| row.get::<i32, _>("id"))
+          .fetch_optional(&mut *tx)
+          .await?;
+
+        // no row when the vote did not change, withdrawn votes take their event along
+        if let (Some(vote_id), Some(author)) = (vote_id, author) {
+          let (kind, points) = conf.vote_event(target, value);
+          sqlx::query(&format!(
+            "INSERT INTO reputation_events (account_id, kind, points, vote_id, {col}) VALUES ($1, $2, $3, $4, $5)
+                  ON CONFLICT (vote_id) WHERE vote_id IS NOT NULL
+                  DO UPDATE SET kind = excluded.kind, points = excluded.points, created_at = now()",
+            col = target.column()
+          ))
+            .bind(author)
+            .bind(kind)
+            .bind(points)
+            .bind(vote_id)
+            .bind(target.id())
+            .execute(&mut *tx)
+            .await?;
+        }
+      }
+      None => {
+        sqlx::query(&format!("DELETE FROM votes WHERE account_id = $1 AND {} = $2", target.column()))
+          .bind(account_id)
+          .bind(target.id())
+          .execute(&mut *tx)
+          .await?;
       }
-      Err(e) => Err(e),
     }
+
+    // kept up to date by the votes_score trigger
+    let score = sqlx::query(&format!("SELECT score FROM {} WHERE id = $1", target.table()))
+      .bind(target.id())
+      .map(|row: PgRow| row.get::<i32, _>("score"))
+      .fetch_one(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(VoteOutcome::Voted(VoteResult { score, vote: value }))
   }
 
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
   pub async fn add_account(&self, a: Account) -> Result<i32, sqlx::Error> {
     match sqlx::query(
       "INSERT INTO account(email, password) VALUES ($1, $2) RETURNING id",
@@ -117,7 +995,7 @@ impl Store {
       .bind(a.email)
       .bind(a.password)
       .map(|row: PgRow| row.get::<i32, _>("id"))
-      .fetch_one(&self.pool)
+      .fetch_one(&mut *self.conn().await?)
       .await
     {
       Ok(id) => {
@@ -128,15 +1006,1222 @@ impl Store {
     }
   }
 
-  pub async fn find_account(&self, email: String) -> Result<Account, sqlx::Error> {
-    sqlx::query("SELECT * FROM account WHERE email = $1")
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn find_account(&self, email: String) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query("SELECT * FROM account WHERE lower(email) = lower($1)")
       .bind(email)
       .map(|row: PgRow| Account {
         id: Some(AccountId(row.get("id"))),
         email: row.get("email"),
         password: row.get("password"),
       })
-      .fetch_one(&self.pool)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self, hashed), fields(db.system = "postgresql"))]
+  pub async fn update_password(&self, id: i32, hashed: String) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET password = $1 WHERE id = $2")
+      .bind(hashed)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Sets a new password and drops the sessions issued before `sessions_valid_after`.
+  /// The time comes from the caller, which issues the session to keep after it.
+  #[instrument(skip(self, hashed), fields(db.system = "postgresql"))]
+  pub async fn change_password(
+    &self,
+    id: i32,
+    hashed: String,
+    sessions_valid_after: DateTime<Utc>,
+  ) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET password = $1, sessions_valid_after = $2 WHERE id = $3")
+      .bind(hashed)
+      .bind(sessions_valid_after)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Drops the login sessions of `id` issued before `sessions_valid_after`, API keys are kept
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn revoke_sessions(&self, id: i32, sessions_valid_after: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET sessions_valid_after = $1 WHERE id = $2")
+      .bind(sessions_valid_after)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn find_account_by_id(&self, id: i32) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query("SELECT id, email, password FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| Account {
+        id: Some(AccountId(row.get("id"))),
+        email: row.get("email"),
+        password: row.get("password"),
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn profile(&self, id: i32) -> Result<Option<Profile>, sqlx::Error> {
+    sqlx::query("SELECT * FROM account WHERE id = $1")
+      .bind(id)
+      .map(profile_from_row)
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn public_profile(&self, id: i32) -> Result<Option<PublicProfile>, sqlx::Error> {
+    sqlx::query("SELECT id, display_name, bio, avatar_url, reputation, created_at FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| PublicProfile {
+        id: row.get("id"),
+        display_name: row.get("display_name"),
+        bio: row.get("bio"),
+        avatar_url: row.get("avatar_url"),
+        reputation: row.get("reputation"),
+        created_at: row.get("created_at"),
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Applies the fields set in `p`, empty strings clear the field
+  #[instrument(skip(self, p), fields(db.system = "postgresql"))]
+  pub async fn update_profile(&self, id: i32, p: ProfileUpdate) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "UPDATE account SET
+              display_name = CASE WHEN $1::text IS NULL THEN display_name ELSE nullif($1, '') END,
+              bio = CASE WHEN $2::text IS NULL THEN bio ELSE nullif($2, '') END,
+              avatar_url = CASE WHEN $3::text IS NULL THEN avatar_url ELSE nullif($3, '') END
+            WHERE id = $4",
+    )
+      .bind(p.display_name)
+      .bind(p.bio)
+      .bind(p.avatar_url)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn set_pending_email(&self, id: i32, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET pending_email = $1 WHERE id = $2")
+      .bind(email)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Swaps in the pending email, which the confirmation link just verified. Returns the old and new emails.
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn confirm_email_change(&self, id: i32) -> Result<Option<(String, String)>, sqlx::Error> {
+    sqlx::query(
+      "UPDATE account a SET email = a.pending_email, pending_email = NULL, email_verified_at = now()
+            FROM (SELECT id, email AS old_email FROM account WHERE id = $1 FOR UPDATE) o
+            WHERE a.id = o.id AND a.pending_email IS NOT NULL
+            RETURNING o.old_email, a.email",
+    )
+      .bind(id)
+      .map(|row: PgRow| (row.get("old_email"), row.get("email")))
+      .fetch_optional(&mut *self.conn().await?)
       .await
   }
+
+  /// Erases the account: its questions, answers and comments go to the deleted user placeholder,
+  /// the audit log forgets its email and IPs and the row itself is deleted.
+  /// Returns the email the account had.
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn erase_account(&self, id: i32) -> Result<Option<String>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let email = sqlx::query("SELECT email FROM account WHERE id = $1 AND NOT placeholder FOR UPDATE")
+      .bind(id)
+      .map(|row: PgRow| row.get::<String, _>("email"))
+      .fetch_optional(&mut *tx)
+      .await?;
+    let Some(email) = email else {
+      return Ok(None);
+    };
+
+    for table in ["questions", "answers", "comments", "question_revisions"] {
+      sqlx::query(&format!(
+        "UPDATE {} SET account_id = (SELECT id FROM account WHERE placeholder) WHERE account_id = $1",
+        table
+      ))
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query("UPDATE audit_log SET email = NULL, ip = NULL WHERE account_id = $1 OR lower(email) = lower($2)")
+      .bind(id)
+      .bind(&email)
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query("DELETE FROM account WHERE id = $1")
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query("DELETE FROM login_throttle WHERE email = lower($1)")
+      .bind(&email)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(Some(email))
+  }
+
+  /// Everything tied to the account, read from a single snapshot
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn export_account(&self, id: i32) -> Result<Option<AccountExport>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+      .execute(&mut *tx)
+      .await?;
+
+    let account = sqlx::query("SELECT * FROM account WHERE id = $1")
+      .bind(id)
+      .map(profile_from_row)
+      .fetch_optional(&mut *tx)
+      .await?;
+    let Some(account) = account else {
+      return Ok(None);
+    };
+
+    let questions = sqlx::query("SELECT * FROM questions WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(question_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let answers = sqlx::query("SELECT * FROM answers WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(answer_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let votes = sqlx::query("SELECT * FROM votes WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(|row: PgRow| Vote {
+        question_id: row.get("question_id"),
+        answer_id: row.get("answer_id"),
+        value: row.get("value"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+      })
+      .fetch_all(&mut *tx)
+      .await?;
+    let reputation = sqlx::query("SELECT * FROM reputation_events WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(reputation_event_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let revisions = sqlx::query("SELECT * FROM question_revisions WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(revision_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let comments = sqlx::query("SELECT * FROM comments WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(comment_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let flags = sqlx::query("SELECT * FROM flags WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(flag_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    let api_keys = sqlx::query("SELECT * FROM api_key WHERE account_id = $1 ORDER BY id")
+      .bind(id)
+      .map(api_key_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+    // failed logins only know the email they were tried with
+    let audit_log = sqlx::query(
+      "SELECT * FROM audit_log WHERE account_id = $1 OR actor_id = $1 OR lower(email) = lower($2)
+            ORDER BY created_at, id",
+    )
+      .bind(id)
+      .bind(&account.email)
+      .map(audit_from_row)
+      .fetch_all(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(Some(AccountExport {
+      exported_at: Utc::now(),
+      account,
+      questions,
+      answers,
+      revisions,
+      comments,
+      votes,
+      reputation,
+      flags,
+      api_keys,
+      audit_log,
+    }))
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn questions_by_account(&self, account_id: i32, page: &PageQuery) -> Result<Vec<Question>, sqlx::Error> {
+    sqlx::query("SELECT * FROM questions WHERE account_id = $1 AND deleted_at IS NULL ORDER BY id DESC LIMIT $2 OFFSET $3")
+      .bind(account_id)
+      .bind(page.limit())
+      .bind(page.offset())
+      .map(question_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn answers_by_account(&self, account_id: i32, page: &PageQuery) -> Result<Vec<Answer>, sqlx::Error> {
+    sqlx::query(&format!(
+      "SELECT * FROM answers WHERE account_id = $1 AND {} ORDER BY id DESC LIMIT $2 OFFSET $3",
+      VISIBLE_ANSWER
+    ))
+      .bind(account_id)
+      .bind(page.limit())
+      .bind(page.offset())
+      .map(answer_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn reputation_events(&self, account_id: i32, page: &PageQuery) -> Result<Vec<ReputationEvent>, sqlx::Error> {
+    sqlx::query("SELECT * FROM reputation_events WHERE account_id = $1 AND NOT void ORDER BY id DESC LIMIT $2 OFFSET $3")
+      .bind(account_id)
+      .bind(page.limit())
+      .bind(page.offset())
+      .map(reputation_event_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Role and reputation, what the privilege checks look at
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn account_standing(&self, id: i32) -> Result<Option<(Role, i32)>, sqlx::Error> {
+    sqlx::query("SELECT role, reputation FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| {
+        (
+          row.get::<String, _>("role").parse().unwrap_or(Role::User),
+          row.get::<i32, _>("reputation"),
+        )
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Rebuilds the reputation ledger from the votes and accepted answers with the current points.
+  /// Returns how many accounts had a reputation differing from their ledger
+  #[instrument(skip(self, conf), fields(db.system = "postgresql"))]
+  pub async fn recompute_reputation(&self, conf: &ReputationConfig) -> Result<u64, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    // votes and accepts wait until the ledger is rebuilt, reads go on
+    sqlx::query("LOCK TABLE reputation_events IN EXCLUSIVE MODE").execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM reputation_events").execute(&mut *tx).await?;
+
+    for target in [PostTarget::Question(0), PostTarget::Answer(0)] {
+      let (up, up_points) = conf.vote_event(target, 1);
+      let (down, down_points) = conf.vote_event(target, -1);
+      sqlx::query(&format!(
+        "INSERT INTO reputation_events (account_id, kind, points, vote_id, {col}, created_at)
+              SELECT p.account_id,
+                CASE WHEN v.value > 0 THEN $1 ELSE $2 END,
+                CASE WHEN v.value > 0 THEN $3 ELSE $4 END,
+                v.id, p.id, v.updated_at
+              FROM votes v JOIN {table} p ON p.id = v.{col}
+              WHERE p.account_id IS NOT NULL AND p.account_id <> v.account_id",
+        col = target.column(),
+        table = target.table()
+      ))
+        .bind(up)
+        .bind(down)
+        .bind(up_points)
+        .bind(down_points)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+      "INSERT INTO reputation_events (account_id, kind, points, question_id, answer_id)
+            SELECT a.account_id, $1, $2, q.id, a.id
+            FROM questions q JOIN answers a ON a.id = q.accepted_answer_id
+            WHERE a.account_id IS NOT NULL AND a.account_id IS DISTINCT FROM q.account_id",
+    )
+      .bind(reputation::ACCEPTED)
+      .bind(conf.answer_accepted)
+      .execute(&mut *tx)
+      .await?;
+    void_reputation(&mut tx, None).await?;
+
+    let drifted = sqlx::query(
+      "UPDATE account SET reputation = ledger.points
+            FROM (
+              SELECT a.id, coalesce(sum(e.points) FILTER (WHERE NOT e.void), 0)::integer AS points
+              FROM account a LEFT JOIN reputation_events e ON e.account_id = a.id
+              GROUP BY a.id
+            ) ledger
+            WHERE account.id = ledger.id AND account.reputation <> ledger.points",
+    )
+      .execute(&mut *tx)
+      .await?
+      .rows_affected();
+
+    tx.commit().await?;
+    Ok(drifted)
+  }
+
+  /// Reports `target`, flagging it again keeps the first reason. `false` when the target does not exist
+  #[instrument(skip(self, reason), fields(db.system = "postgresql"))]
+  pub async fn flag(&self, target: PostTarget, account_id: i32, reason: String) -> Result<bool, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let exists = sqlx::query(&format!("SELECT 1 FROM {} WHERE id = $1 AND {}", target.table(), target.visible()))
+      .bind(target.id())
+      .fetch_optional(&mut *conn)
+      .await?
+      .is_some();
+    if !exists {
+      return Ok(false);
+    }
+
+    sqlx::query(&format!(
+      "INSERT INTO flags (account_id, {col}, reason) VALUES ($1, $2, $3)
+            ON CONFLICT ({col}, account_id) WHERE {col} IS NOT NULL DO NOTHING",
+      col = target.column()
+    ))
+      .bind(account_id)
+      .bind(target.id())
+      .bind(reason)
+      .execute(&mut *conn)
+      .await?;
+    Ok(true)
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn flags(&self, page: &PageQuery) -> Result<Vec<Flag>, sqlx::Error> {
+    sqlx::query("SELECT * FROM flags ORDER BY id DESC LIMIT $1 OFFSET $2")
+      .bind(page.limit())
+      .bind(page.offset())
+      .map(flag_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn email_verified(&self, id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query("SELECT email_verified_at IS NOT NULL AS verified FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| row.get::<bool, _>("verified"))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+      .map(|verified| verified.unwrap_or(false))
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn verify_email(&self, id: i32) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET email_verified_at = now() WHERE id = $1 AND email_verified_at IS NULL")
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Sets a new password, the reset link proved the email so it gets verified too.
+  /// Sessions opened before are dropped, they may belong to whoever made the reset necessary.
+  /// Returns the email of the account.
+  #[instrument(skip(self, hashed), fields(db.system = "postgresql"))]
+  pub async fn reset_password(&self, id: i32, hashed: String) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query(
+      "UPDATE account SET password = $1, email_verified_at = coalesce(email_verified_at, now()),
+              password_reset_required = false, sessions_valid_after = now()
+            WHERE id = $2 RETURNING email",
+    )
+      .bind(hashed)
+      .bind(id)
+      .map(|row: PgRow| row.get::<String, _>("email"))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self, token_hash), fields(db.system = "postgresql", purpose = purpose.as_str()))]
+  pub async fn add_account_token(
+    &self,
+    account_id: i32,
+    purpose: TokenPurpose,
+    token_hash: &str,
+    ttl_secs: i64,
+  ) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO account_token (account_id, purpose, token_hash, expires_at)
+            VALUES ($1, $2, $3, now() + make_interval(secs => $4))",
+    )
+      .bind(account_id)
+      .bind(purpose.as_str())
+      .bind(token_hash)
+      .bind(ttl_secs as f64)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Email of the account of the token, without using it up, when it exists, is unused and has not expired
+  #[instrument(skip(self, token_hash), fields(db.system = "postgresql", purpose = purpose.as_str()))]
+  pub async fn account_token_email(&self, purpose: TokenPurpose, token_hash: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query(
+      "SELECT a.email FROM account_token t JOIN account a ON a.id = t.account_id
+            WHERE t.token_hash = $1 AND t.purpose = $2 AND t.used_at IS NULL AND t.expires_at > now()",
+    )
+      .bind(token_hash)
+      .bind(purpose.as_str())
+      .map(|row: PgRow| row.get::<String, _>("email"))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Marks the token used and returns its account, when it exists, is unused and has not expired
+  #[instrument(skip(self, token_hash), fields(db.system = "postgresql", purpose = purpose.as_str()))]
+  pub async fn use_account_token(&self, purpose: TokenPurpose, token_hash: &str) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query(
+      "UPDATE account_token SET used_at = now()
+            WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > now()
+            RETURNING account_id",
+    )
+      .bind(token_hash)
+      .bind(purpose.as_str())
+      .map(|row: PgRow| row.get::<i32, _>("account_id"))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Voids the outstanding tokens of an account, e.g. older reset links once the password changed
+  #[instrument(skip(self), fields(db.system = "postgresql", purpose = purpose.as_str()))]
+  pub async fn revoke_account_tokens(&self, account_id: i32, purpose: TokenPurpose) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account_token SET used_at = now() WHERE account_id = $1 AND purpose = $2 AND used_at IS NULL")
+      .bind(account_id)
+      .bind(purpose.as_str())
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn totp_state(&self, id: i32) -> Result<Option<TotpState>, sqlx::Error> {
+    sqlx::query("SELECT email, totp_secret, totp_pending_secret, totp_last_step FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| TotpState {
+        email: row.get("email"),
+        secret: row.get("totp_secret"),
+        pending_secret: row.get("totp_pending_secret"),
+        last_step: row.get("totp_last_step"),
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self, secret), fields(db.system = "postgresql"))]
+  pub async fn set_pending_totp(&self, id: i32, secret: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE account SET totp_pending_secret = $1 WHERE id = $2")
+      .bind(secret)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Promotes the pending secret, confirmed by a code of time step `step`, and replaces the recovery codes
+  #[instrument(skip(self, code_hashes), fields(db.system = "postgresql"))]
+  pub async fn enable_totp(&self, id: i32, step: i64, code_hashes: &[String]) -> Result<(), sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    sqlx::query(
+      "UPDATE account SET totp_secret = totp_pending_secret, totp_pending_secret = NULL,
+              totp_enabled_at = now(), totp_last_step = $1
+            WHERE id = $2",
+    )
+      .bind(step)
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query("DELETE FROM recovery_code WHERE account_id = $1")
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+
+    sqlx::query("INSERT INTO recovery_code (account_id, code_hash) SELECT $1, unnest($2::text[])")
+      .bind(id)
+      .bind(code_hashes)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await
+  }
+
+  /// Records `step` as the last accepted code, false when a code of this step or a later one was used already
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn use_totp_step(&self, id: i32, step: i64) -> Result<bool, sqlx::Error> {
+    sqlx::query(
+      "UPDATE account SET totp_last_step = $1
+            WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+    )
+      .bind(step)
+      .bind(id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|r| r.rows_affected() == 1)
+  }
+
+  #[instrument(skip(self, code_hash), fields(db.system = "postgresql"))]
+  pub async fn use_recovery_code(&self, id: i32, code_hash: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query(
+      "UPDATE recovery_code SET used_at = now() WHERE account_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+      .bind(id)
+      .bind(code_hash)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|r| r.rows_affected() > 0)
+  }
+
+  /// Turns two-factor authentication off, false when the account does not exist
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn reset_totp(&self, id: i32) -> Result<bool, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let found = sqlx::query(
+      "UPDATE account SET totp_secret = NULL, totp_pending_secret = NULL,
+              totp_enabled_at = NULL, totp_last_step = NULL
+            WHERE id = $1",
+    )
+      .bind(id)
+      .execute(&mut *tx)
+      .await?
+      .rows_affected()
+      > 0;
+
+    sqlx::query("DELETE FROM recovery_code WHERE account_id = $1")
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(found)
+  }
+
+  #[instrument(skip(self, key_hash), fields(db.system = "postgresql"))]
+  pub async fn add_api_key(
+    &self,
+    account_id: i32,
+    name: &str,
+    prefix: &str,
+    key_hash: &str,
+    scopes: &[Scope],
+  ) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO api_key (account_id, name, prefix, key_hash, scopes)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *",
+    )
+      .bind(account_id)
+      .bind(name)
+      .bind(prefix)
+      .bind(key_hash)
+      .bind(scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>())
+      .map(api_key_from_row)
+      .fetch_one(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn find_api_keys(&self, account_id: i32) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query("SELECT * FROM api_key WHERE account_id = $1 ORDER BY id")
+      .bind(account_id)
+      .map(api_key_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Revokes a key of `account_id`, false when it has no such active key
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn revoke_api_key(&self, account_id: i32, id: i32) -> Result<bool, sqlx::Error> {
+    sqlx::query("UPDATE api_key SET revoked_at = now() WHERE id = $1 AND account_id = $2 AND revoked_at IS NULL")
+      .bind(id)
+      .bind(account_id)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|r| r.rows_affected() == 1)
+  }
+
+  /// Looks an active key up by hash and records its use, returns its account and grant
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn use_api_key(&self, key_hash: &str) -> Result<Option<(i32, KeyGrant)>, sqlx::Error> {
+    sqlx::query(
+      "UPDATE api_key SET last_used_at = now()
+            WHERE key_hash = $1 AND revoked_at IS NULL
+            RETURNING id, account_id, scopes",
+    )
+      .bind(key_hash)
+      .map(|row: PgRow| {
+        (
+          row.get::<i32, _>("account_id"),
+          KeyGrant {
+            id: row.get("id"),
+            scopes: parse_scopes(&row),
+          },
+        )
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn account_status(&self, id: i32) -> Result<Option<AccountStatus>, sqlx::Error> {
+    sqlx::query(
+      "SELECT suspended_at IS NOT NULL AS suspended, password_reset_required, sessions_valid_after
+            FROM account WHERE id = $1",
+    )
+      .bind(id)
+      .map(|row: PgRow| AccountStatus {
+        suspended: row.get("suspended"),
+        password_reset_required: row.get("password_reset_required"),
+        sessions_valid_after: row.get("sessions_valid_after"),
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn search_accounts(&self, q: AccountQuery) -> Result<Vec<AccountSummary>, sqlx::Error> {
+    // the email is matched literally, not as a pattern
+    let email = q.email.map(|e| e.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    sqlx::query(
+      "SELECT * FROM account
+            WHERE ($1::varchar IS NULL OR email ILIKE '%' || $1 || '%')
+              AND ($2::varchar IS NULL OR role = $2)
+              AND ($3::boolean IS NULL OR (suspended_at IS NOT NULL) = $3)
+            ORDER BY id
+            LIMIT $4 OFFSET $5",
+    )
+      .bind(email)
+      .bind(q.role.map(|r| r.as_str()))
+      .bind(q.suspended)
+      .bind(q.limit.unwrap_or(50).clamp(1, 500))
+      .bind(q.offset.unwrap_or(0).max(0))
+      .map(account_summary_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn admin_account(&self, id: i32) -> Result<Option<AdminAccount>, sqlx::Error> {
+    sqlx::query(
+      "SELECT a.*,
+              (SELECT count(*) FROM questions WHERE account_id = a.id) AS question_count,
+              (SELECT count(*) FROM answers WHERE account_id = a.id) AS answer_count,
+              (SELECT count(*) FROM api_key WHERE account_id = a.id AND revoked_at IS NULL) AS api_key_count,
+              (SELECT count(*) FROM audit_log WHERE action = $2 AND account_id = a.id AND success) AS login_count,
+              (SELECT count(*) FROM audit_log WHERE action = $2 AND lower(email) = lower(a.email) AND NOT success)
+                AS failed_login_count,
+              (SELECT max(created_at) FROM audit_log WHERE action = $2 AND account_id = a.id AND success)
+                AS last_login_at
+            FROM account a WHERE a.id = $1",
+    )
+      .bind(id)
+      .bind(audit::LOGIN)
+      .map(|row: PgRow| AdminAccount {
+        totp_enabled: row.get::<Option<String>, _>("totp_secret").is_some(),
+        suspended_reason: row.get("suspended_reason"),
+        password_reset_required: row.get("password_reset_required"),
+        activity: Activity {
+          questions: row.get("question_count"),
+          answers: row.get("answer_count"),
+          active_api_keys: row.get("api_key_count"),
+          logins: row.get("login_count"),
+          failed_logins: row.get("failed_login_count"),
+          last_login_at: row.get("last_login_at"),
+        },
+        summary: account_summary_from_row(row),
+      })
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Suspends the account with `reason`, or lifts the suspension when `suspend` is false
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn set_suspended(&self, id: i32, suspend: bool, reason: Option<String>) -> Result<bool, sqlx::Error> {
+    sqlx::query(
+      "UPDATE account SET suspended_at = CASE WHEN $2 THEN coalesce(suspended_at, now()) END,
+              suspended_reason = CASE WHEN $2 THEN $3 END
+            WHERE id = $1",
+    )
+      .bind(id)
+      .bind(suspend)
+      .bind(reason)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|r| r.rows_affected() == 1)
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn set_role(&self, id: i32, role: Role) -> Result<bool, sqlx::Error> {
+    sqlx::query("UPDATE account SET role = $2 WHERE id = $1")
+      .bind(id)
+      .bind(role.as_str())
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|r| r.rows_affected() == 1)
+  }
+
+  /// Refuses logins until the password is reset and drops every session and API key.
+  /// Returns the email to send the reset link to.
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn force_password_reset(&self, id: i32) -> Result<Option<String>, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    let email = sqlx::query(
+      "UPDATE account SET password_reset_required = true, sessions_valid_after = now()
+            WHERE id = $1 RETURNING email",
+    )
+      .bind(id)
+      .map(|row: PgRow| row.get::<String, _>("email"))
+      .fetch_optional(&mut *tx)
+      .await?;
+
+    sqlx::query("UPDATE api_key SET revoked_at = now() WHERE account_id = $1 AND revoked_at IS NULL")
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(email)
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn account_role(&self, id: i32) -> Result<Option<Role>, sqlx::Error> {
+    sqlx::query("SELECT role FROM account WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| row.get::<String, _>("role").parse::<Role>().unwrap_or(Role::User))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// End of the lockout of `email`, if any
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn login_locked_until(&self, email: &str) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query("SELECT locked_until FROM login_throttle WHERE email = $1 AND locked_until > now()")
+      .bind(email)
+      .map(|row: PgRow| row.get::<DateTime<Utc>, _>("locked_until"))
+      .fetch_optional(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Extends the failure streak of `email`. A streak starts over once `window_secs` passed since its last failure
+  /// and the end of its lockout, so that lockouts longer than the window still escalate.
+  /// Returns the length of the streak.
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn add_login_failure(&self, email: &str, window_secs: i64) -> Result<i32, sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO login_throttle (email, failures) VALUES ($1, 1)
+            ON CONFLICT (email) DO UPDATE SET
+              failures = CASE
+                WHEN greatest(login_throttle.last_failure_at, login_throttle.locked_until)
+                  < now() - make_interval(secs => $2) THEN 1
+                ELSE login_throttle.failures + 1
+              END,
+              last_failure_at = now()
+            RETURNING failures",
+    )
+      .bind(email)
+      .bind(window_secs as f64)
+      .map(|row: PgRow| row.get::<i32, _>("failures"))
+      .fetch_one(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn lock_login(&self, email: &str, secs: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE login_throttle SET locked_until = now() + make_interval(secs => $2) WHERE email = $1")
+      .bind(email)
+      .bind(secs as f64)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn reset_login_failures(&self, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM login_throttle WHERE email = $1")
+      .bind(email)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  /// Number of failed logins coming from `ip` in the last `window_secs`.
+  /// Attempts refused by a lockout are left out, or retrying would keep the ip locked for good.
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn ip_login_failures(&self, ip: &str, window_secs: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query(
+      "SELECT count(*) AS failures FROM audit_log
+            WHERE action = $1 AND NOT success AND ip = $2 AND created_at > now() - make_interval(secs => $3)
+              AND coalesce(detail, '') <> ALL($4)",
+    )
+      .bind(audit::LOGIN)
+      .bind(ip)
+      .bind(window_secs as f64)
+      .bind([audit::LOCKED, audit::IP_LOCKED])
+      .map(|row: PgRow| row.get::<i64, _>("failures"))
+      .fetch_one(&mut *self.conn().await?)
+      .await
+  }
+
+  #[instrument(skip_all, fields(db.system = "postgresql", action = e.action))]
+  pub async fn add_audit(&self, e: NewAuditEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO audit_log (action, account_id, actor_id, email, ip, success, detail)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+      .bind(e.action)
+      .bind(e.account_id)
+      .bind(e.actor_id)
+      .bind(e.email)
+      .bind(e.ip)
+      .bind(e.success)
+      .bind(e.detail)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|_| ())
+  }
+
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn find_audit(&self, q: AuditQuery) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query(
+      "SELECT * FROM audit_log
+            WHERE ($1::varchar IS NULL OR action = $1)
+              AND ($2::integer IS NULL OR account_id = $2)
+              AND ($3::integer IS NULL OR actor_id = $3)
+              AND ($4::varchar IS NULL OR email = $4)
+              AND ($5::varchar IS NULL OR ip = $5)
+              AND ($6::boolean IS NULL OR success = $6)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7 OFFSET $8",
+    )
+      .bind(q.action)
+      .bind(q.account_id)
+      .bind(q.actor_id)
+      .bind(q.email)
+      .bind(q.ip)
+      .bind(q.success)
+      .bind(q.limit.unwrap_or(50).clamp(1, 500))
+      .bind(q.offset.unwrap_or(0).max(0))
+      .map(audit_from_row)
+      .fetch_all(&mut *self.conn().await?)
+      .await
+  }
+
+  /// Takes a token out of a bucket shared by every instance, the row lock
+  /// serializes concurrent requests of the same key.
+  #[instrument(skip(self, limit), fields(db.system = "postgresql"))]
+  pub async fn take_rate_limit_token(&self, key: &str, limit: &Limit) -> Result<Decision, sqlx::Error> {
+    let mut conn = self.conn().await?;
+    let mut tx = conn.begin().await?;
+
+    sqlx::query(
+      "INSERT INTO rate_limit_buckets (key, tokens) VALUES ($1, $2) ON CONFLICT (key) DO NOTHING",
+    )
+      .bind(key)
+      .bind(limit.capacity as f64)
+      .execute(&mut *tx)
+      .await?;
+
+    let (tokens, elapsed) = sqlx::query(
+      "SELECT tokens, EXTRACT(EPOCH FROM now() - updated_at)::float8 AS elapsed
+            FROM rate_limit_buckets WHERE key = $1 FOR UPDATE",
+    )
+      .bind(key)
+      .map(|row: PgRow| (row.get::<f64, _>("tokens"), row.get::<f64, _>("elapsed")))
+      .fetch_one(&mut *tx)
+      .await?;
+
+    let (tokens, decision) = rate_limit::take(tokens, elapsed, limit);
+
+    sqlx::query("UPDATE rate_limit_buckets SET tokens = $1, updated_at = now() WHERE key = $2")
+      .bind(tokens)
+      .bind(key)
+      .execute(&mut *tx)
+      .await?;
+
+    tx.commit().await?;
+    Ok(decision)
+  }
+
+  /// Deletes the buckets untouched for `idle_secs`, returns how many
+  #[instrument(skip(self), fields(db.system = "postgresql"))]
+  pub async fn delete_idle_rate_limit_buckets(&self, idle_secs: f64) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM rate_limit_buckets WHERE updated_at < now() - make_interval(secs => $1)")
+      .bind(idle_secs)
+      .execute(&mut *self.conn().await?)
+      .await
+      .map(|res| res.rows_affected())
+  }
+}
+
+fn profile_from_row(row: PgRow) -> Profile {
+  Profile {
+    id: row.get("id"),
+    email: row.get("email"),
+    pending_email: row.get("pending_email"),
+    email_verified: row.get::<Option<DateTime<Utc>>, _>("email_verified_at").is_some(),
+    display_name: row.get("display_name"),
+    bio: row.get("bio"),
+    avatar_url: row.get("avatar_url"),
+    role: row.get::<String, _>("role").parse().unwrap_or(Role::User),
+    totp_enabled: row.get::<Option<String>, _>("totp_secret").is_some(),
+    reputation: row.get("reputation"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn account_summary_from_row(row: PgRow) -> AccountSummary {
+  AccountSummary {
+    id: row.get("id"),
+    email: row.get("email"),
+    display_name: row.get("display_name"),
+    role: row.get::<String, _>("role").parse().unwrap_or(Role::User),
+    email_verified: row.get::<Option<DateTime<Utc>>, _>("email_verified_at").is_some(),
+    suspended_at: row.get("suspended_at"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn question_from_row(row: PgRow) -> Question {
+  Question {
+    id: QuestionId(row.get::<i32, _>("id") as u32),
+    title: row.get("title"),
+    content: row.get("content"),
+    tags: row.get("tags"),
+    account_id: row.get("account_id"),
+    score: row.get("score"),
+    accepted_answer_id: row.get("accepted_answer_id"),
+    version: row.get("version"),
+    duplicate_of: row.get("duplicate_of"),
+    state: row.get::<String, _>("state").parse().unwrap_or_default(),
+    close_reason: row.get::<Option<String>, _>("close_reason").and_then(|r| r.parse().ok()),
+  }
+}
+
+fn answer_from_row(row: PgRow) -> Answer {
+  Answer {
+    id: AnswerId(row.get("id")),
+    qid: QuestionId(row.get::<Option<i32>, _>("corresponding_question").unwrap_or_default() as u32),
+    content: row.get("content"),
+    account_id: row.get("account_id"),
+    score: row.get("score"),
+    created_at: row.get("created_at"),
+    version: row.get("version"),
+  }
+}
+
+/// Voids the reputation events of deleted posts and revives those of restored ones, for the question of `target`
+/// and its answers or, without target, everywhere. Answers of a deleted question count as deleted
+async fn void_reputation(conn: &mut PgConnection, target: Option<PostTarget>) -> Result<(), sqlx::Error> {
+  let question_id = match target {
+    Some(PostTarget::Answer(id)) => sqlx::query("SELECT corresponding_question FROM answers WHERE id = $1")
+      .bind(id)
+      .map(|row: PgRow| row.get::<Option<i32>, _>("corresponding_question"))
+      .fetch_optional(&mut *conn)
+      .await?
+      .flatten(),
+    Some(PostTarget::Question(id)) => Some(id),
+    None => None,
+  };
+  if target.is_some() && question_id.is_none() {
+    return Ok(());
+  }
+
+  sqlx::query(
+    "UPDATE reputation_events SET void = post.void
+          FROM (
+            SELECT e.id, (q.deleted_at IS NOT NULL OR a.deleted_at IS NOT NULL) AS void
+            FROM reputation_events e
+            LEFT JOIN answers a ON a.id = e.answer_id
+            JOIN questions q ON q.id = coalesce(e.question_id, a.corresponding_question)
+            WHERE $1::integer IS NULL OR q.id = $1
+          ) post
+          WHERE reputation_events.id = post.id AND reputation_events.void <> post.void",
+  )
+    .bind(question_id)
+    .execute(conn)
+    .await
+    .map(|_| ())
+}
+
+/// Current state of question `id`, locking its row until the end of the transaction
+async fn lock_state(conn: &mut PgConnection, id: i32) -> Result<Option<QuestionState>, sqlx::Error> {
+  sqlx::query("SELECT state FROM questions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE")
+    .bind(id)
+    .map(|row: PgRow| row.get::<String, _>("state").parse().unwrap_or_default())
+    .fetch_optional(conn)
+    .await
+}
+
+/// Logs that `actor_id` moved `q` from state `from` to the one it is in now
+async fn add_moderation(
+  conn: &mut PgConnection,
+  q: &Question,
+  from: QuestionState,
+  actor_id: Option<i32>,
+) -> Result<(), sqlx::Error> {
+  sqlx::query(
+    "INSERT INTO moderation_log (question_id, actor_id, from_state, to_state, reason) VALUES ($1, $2, $3, $4, $5)",
+  )
+    .bind(q.id.0 as i32)
+    .bind(actor_id)
+    .bind(from.as_str())
+    .bind(q.state.as_str())
+    .bind(q.close_reason.map(|r| r.as_str()))
+    .execute(conn)
+    .await
+    .map(|_| ())
+}
+
+/// Snapshot of `q` after a change made by `account_id`, numbered after the latest revision.
+/// The change itself has to be made in the same transaction so that it locks the question row
+async fn add_revision(
+  conn: &mut PgConnection,
+  q: &Question,
+  account_id: Option<i32>,
+  action: &str,
+  rolled_back_to: Option<i32>,
+) -> Result<(), sqlx::Error> {
+  sqlx::query(
+    "INSERT INTO question_revisions (question_id, revision, account_id, action, rolled_back_to, title, content, tags)
+          SELECT $1, coalesce(max(revision), 0) + 1, $2, $3, $4, $5, $6, $7
+          FROM question_revisions WHERE question_id = $1",
+  )
+    .bind(q.id.0 as i32)
+    .bind(account_id)
+    .bind(action)
+    .bind(rolled_back_to)
+    .bind(&q.title)
+    .bind(&q.content)
+    .bind(&q.tags)
+    .execute(conn)
+    .await
+    .map(|_| ())
+}
+
+fn revision_from_row(row: PgRow) -> Revision {
+  Revision {
+    question_id: row.get("question_id"),
+    revision: row.get("revision"),
+    account_id: row.get("account_id"),
+    action: row.get("action"),
+    rolled_back_to: row.get("rolled_back_to"),
+    title: row.get("title"),
+    content: row.get("content"),
+    tags: row.get("tags"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn tag_from_row(row: PgRow) -> Tag {
+  Tag {
+    slug: row.get("slug"),
+    description: row.get("description"),
+    usage_count: row.get("usage_count"),
+    synonyms: row.get("synonyms"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn comment_from_row(row: PgRow) -> Comment {
+  Comment {
+    id: row.get("id"),
+    account_id: row.get("account_id"),
+    question_id: row.get("question_id"),
+    answer_id: row.get("answer_id"),
+    content: row.get("content"),
+    created_at: row.get("created_at"),
+    updated_at: row.get("updated_at"),
+  }
+}
+
+fn reputation_event_from_row(row: PgRow) -> ReputationEvent {
+  ReputationEvent {
+    id: row.get("id"),
+    kind: row.get("kind"),
+    points: row.get("points"),
+    question_id: row.get("question_id"),
+    answer_id: row.get("answer_id"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn flag_from_row(row: PgRow) -> Flag {
+  Flag {
+    id: row.get("id"),
+    account_id: row.get("account_id"),
+    question_id: row.get("question_id"),
+    answer_id: row.get("answer_id"),
+    reason: row.get("reason"),
+    created_at: row.get("created_at"),
+  }
+}
+
+fn audit_from_row(row: PgRow) -> AuditEntry {
+  AuditEntry {
+    id: row.get("id"),
+    action: row.get("action"),
+    account_id: row.get("account_id"),
+    actor_id: row.get("actor_id"),
+    email: row.get("email"),
+    ip: row.get("ip"),
+    success: row.get("success"),
+    detail: row.get("detail"),
+    created_at: row.get("created_at"),
+  }
+}
+
+// Scopes dropped from the code are ignored rather than failing every request of the key
+fn parse_scopes(row: &PgRow) -> Vec<Scope> {
+  row
+    .get::<Vec<String>, _>("scopes")
+    .iter()
+    .filter_map(|s| s.parse().ok())
+    .collect()
+}
+
+fn api_key_from_row(row: PgRow) -> ApiKey {
+  ApiKey {
+    id: row.get("id"),
+    name: row.get("name"),
+    prefix: row.get("prefix"),
+    scopes: parse_scopes(&row),
+    created_at: row.get("created_at"),
+    last_used_at: row.get("last_used_at"),
+    revoked_at: row.get("revoked_at"),
+  }
 }
@@ -0,0 +1,109 @@
+use opentelemetry::{
+  global,
+  trace::{TraceContextExt, TraceError, TracerProvider as _},
+  Context, KeyValue,
+};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+  propagation::TraceContextPropagator,
+  runtime,
+  trace::{self, Sampler, Tracer, TracerProvider},
+  Resource,
+};
+use serde::Deserialize;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use warp::{
+  http::{HeaderMap, HeaderValue},
+  reply::Response,
+  Reply,
+};
+
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+  /// OTLP/HTTP collector base url, e.g. `http://localhost:4318`. Export is disabled when empty.
+  pub otlp_endpoint: Option<String>,
+  pub service_name: String,
+  /// Ratio of root traces to sample, remote parents keep their own decision
+  pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+  fn default() -> Self {
+    TelemetryConfig {
+      otlp_endpoint: None,
+      service_name: env!("CARGO_PKG_NAME").to_string(),
+      sample_ratio: 1.0,
+    }
+  }
+}
+
+/// Builds the tracer backing the `tracing-opentelemetry` layer.
+/// Spans always get W3C trace ids so they can be propagated, they are only
+/// shipped anywhere when an OTLP endpoint is configured.
+pub fn init_tracer(conf: &TelemetryConfig) -> Result<Tracer, TraceError> {
+  global::set_text_map_propagator(TraceContextPropagator::new());
+
+  let config = trace::config()
+    .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+      conf.sample_ratio,
+    ))))
+    .with_resource(Resource::new(vec![KeyValue::new(
+      "service.name",
+      conf.service_name.clone(),
+    )]));
+
+  let mut builder = TracerProvider::builder().with_config(config);
+  if let Some(endpoint) = conf.otlp_endpoint.as_ref().filter(|e| !e.is_empty()) {
+    let exporter = opentelemetry_otlp::new_exporter()
+      .http()
+      .with_endpoint(endpoint)
+      .build_span_exporter()?;
+    builder = builder.with_batch_exporter(exporter, runtime::Tokio);
+  }
+
+  let provider = builder.build();
+  let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+  global::set_tracer_provider(provider);
+  Ok(tracer)
+}
+
+/// Flushes spans which are still buffered in the batch exporter.
+pub fn shutdown() {
+  global::shutdown_tracer_provider();
+}
+
+/// Reads the remote parent out of incoming `traceparent`/`tracestate` headers.
+pub fn extract_context(headers: &HeaderMap) -> Option<Context> {
+  let cx = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+  if cx.span().span_context().is_valid() {
+    Some(cx)
+  } else {
+    None
+  }
+}
+
+/// Writes the context of the current span as `traceparent`/`tracestate` headers.
+pub fn inject_context(headers: &mut HeaderMap) {
+  let cx = Span::current().context();
+  global::get_text_map_propagator(|p| p.inject_context(&cx, &mut HeaderInjector(headers)));
+}
+
+/// Echoes the trace of the current request back to the client.
+pub fn with_trace_id(reply: impl Reply) -> Response {
+  let mut res = reply.into_response();
+  let cx = Span::current().context();
+  let span_cx = cx.span().span_context().clone();
+
+  if span_cx.is_valid() {
+    if let Ok(v) = HeaderValue::from_str(&span_cx.trace_id().to_string()) {
+      res.headers_mut().insert(TRACE_ID_HEADER, v);
+    }
+    inject_context(res.headers_mut());
+  }
+  res
+}
@@ -0,0 +1,121 @@
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TotpConfig {
+  /// Name shown by authenticator apps next to the account
+  pub issuer: String,
+  /// Steps of 30s accepted before and after the current one, to absorb clock drift
+  pub skew_steps: u8,
+  /// Lifetime of the token handed out between the password and the code
+  pub mfa_token_ttl_secs: i64,
+  pub recovery_codes: usize,
+}
+
+impl Default for TotpConfig {
+  fn default() -> Self {
+    TotpConfig {
+      issuer: env!("CARGO_PKG_NAME").to_string(),
+      skew_steps: 1,
+      mfa_token_ttl_secs: 300,
+      recovery_codes: 10,
+    }
+  }
+}
+
+/// Random 160 bits secret, base32 encoded as authenticator apps expect it
+pub fn new_secret() -> String {
+  let bytes: [u8; 20] = thread_rng().gen();
+  Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+fn totp(conf: &TotpConfig, secret: &str, email: &str) -> Option<TOTP> {
+  let bytes = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+  TOTP::new(
+    Algorithm::SHA1,
+    DIGITS,
+    conf.skew_steps,
+    STEP_SECS,
+    bytes,
+    Some(conf.issuer.replace(':', "")),
+    email.replace(':', ""),
+  )
+  .ok()
+}
+
+/// Enrollment link for authenticator apps, usually shown as a QR code
+pub fn otpauth_uri(conf: &TotpConfig, secret: &str, email: &str) -> String {
+  totp(conf, secret, email).expect("Invalid TOTP secret").get_url()
+}
+
+/// Time step `code` was generated for, when it is valid for `secret` at `now`.
+/// Callers keep the step to refuse the same code a second time.
+pub fn verify(conf: &TotpConfig, secret: &str, code: &str, now: u64) -> Option<u64> {
+  let totp = totp(conf, secret, "")?;
+  let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+  let current = now / STEP_SECS;
+  let skew = conf.skew_steps as u64;
+
+  (current.saturating_sub(skew)..=current + skew)
+    .rev()
+    .find(|step| totp.generate(step * STEP_SECS) == code)
+}
+
+/// Single-use codes to log in without the authenticator, formatted like `k3xq-7fma-2pcz-w9de`.
+/// They are stored with a plain hash like other tokens, so they carry 80 random bits to stay out of brute force reach.
+pub fn new_recovery_codes(count: usize) -> Vec<String> {
+  let mut rng = thread_rng();
+  (0..count)
+    .map(|_| {
+      let bytes: [u8; 10] = rng.gen();
+      let s = Secret::Raw(bytes.to_vec()).to_encoded().to_string().to_lowercase();
+      format!("{}-{}-{}-{}", &s[..4], &s[4..8], &s[8..12], &s[12..])
+    })
+    .collect()
+}
+
+/// Recovery codes are compared without their dash, spaces or case
+pub fn normalize_recovery_code(code: &str) -> String {
+  code
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric())
+    .collect::<String>()
+    .to_lowercase()
+}
+
+#[cfg(test)]
+mod totp_tests {
+  use super::{new_recovery_codes, new_secret, normalize_recovery_code, otpauth_uri, totp, verify, TotpConfig};
+
+  #[test]
+  fn test_verify() {
+    let conf = TotpConfig::default();
+    let secret = new_secret();
+    let now = 1_700_000_000;
+    let code = |time: u64| totp(&conf, &secret, "").unwrap().generate(time);
+
+    assert_eq!(verify(&conf, &secret, &code(now), now), Some(now / 30));
+    assert_eq!(verify(&conf, &secret, &code(now - 30), now), Some(now / 30 - 1));
+    assert_eq!(verify(&conf, &secret, &code(now + 30), now), Some(now / 30 + 1));
+    assert_eq!(verify(&conf, &secret, &code(now - 90), now), None);
+    assert_eq!(verify(&conf, &secret, "12345", now), None);
+    assert_eq!(verify(&conf, "not base32!", &code(now), now), None);
+
+    let uri = otpauth_uri(&conf, &secret, "foo@example.com");
+    assert!(uri.starts_with("otpauth://totp/helloworld:foo%40example.com?secret="));
+  }
+
+  #[test]
+  fn test_recovery_codes() {
+    let codes = new_recovery_codes(10);
+    assert_eq!(codes.len(), 10);
+    assert!(codes.iter().all(|c| c.len() == 19 && c.split('-').all(|group| group.len() == 4)));
+    assert!(codes.iter().all(|c| normalize_recovery_code(c).len() == 16));
+    assert_eq!(normalize_recovery_code(" K3XQ-7fma-2PCZ-w9de\n"), "k3xq7fma2pczw9de");
+  }
+}
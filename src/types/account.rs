@@ -1,7 +1,12 @@
-use chrono::{DateTime, Utc};
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+use crate::types::api_key::{KeyGrant, Scope};
+use crate::utils::mask_email;
+
+#[derive(Deserialize, Serialize)]
 pub struct Account {
     pub id: Option<AccountId>,
     pub email: String,
@@ -11,9 +16,219 @@ pub struct Account {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountId(pub i32);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+  User,
+  Admin,
+}
+
+impl Role {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Role::User => "user",
+      Role::Admin => "admin",
+    }
+  }
+}
+
+impl FromStr for Role {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "user" => Ok(Role::User),
+      "admin" => Ok(Role::Admin),
+      _ => Err(format!("Unknown role {}", s)),
+    }
+  }
+}
+
+/// What a mailed `account_token` may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+  VerifyEmail,
+  ResetPassword,
+  ChangeEmail,
+}
+
+impl TokenPurpose {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      TokenPurpose::VerifyEmail => "verify_email",
+      TokenPurpose::ResetPassword => "reset_password",
+      TokenPurpose::ChangeEmail => "change_email",
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmail {
+  pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+  pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordReset {
+  pub token: String,
+  pub password: String,
+}
+
+/// The account as its owner sees it on `GET /me`
+#[derive(Debug, Serialize)]
+pub struct Profile {
+  pub id: i32,
+  pub email: String,
+  pub pending_email: Option<String>,
+  pub email_verified: bool,
+  pub display_name: Option<String>,
+  pub bio: Option<String>,
+  pub avatar_url: Option<String>,
+  pub role: Role,
+  pub totp_enabled: bool,
+  pub reputation: i32,
+  pub created_at: NaiveDateTime,
+}
+
+/// What anybody may see about an account
+#[derive(Debug, Serialize)]
+pub struct PublicProfile {
+  pub id: i32,
+  pub display_name: Option<String>,
+  pub bio: Option<String>,
+  pub avatar_url: Option<String>,
+  pub reputation: i32,
+  pub created_at: NaiveDateTime,
+}
+
+/// Body of `PATCH /me`, missing fields are left alone and empty ones are cleared
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileUpdate {
+  pub display_name: Option<String>,
+  pub bio: Option<String>,
+  pub avatar_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmail {
+  pub new_email: String,
+  pub current_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePassword {
+  pub current_password: String,
+  pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccount {
+  pub current_password: String,
+}
+
+/// What `auth` checks on every request besides the credential itself
+#[derive(Debug)]
+pub struct AccountStatus {
+  pub suspended: bool,
+  pub password_reset_required: bool,
+  pub sessions_valid_after: Option<DateTime<Utc>>,
+}
+
+/// Two-factor state of an account, secrets are never sent out after enrollment
+pub struct TotpState {
+  pub email: String,
+  pub secret: Option<String>,
+  pub pending_secret: Option<String>,
+  pub last_step: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+  pub secret: String,
+  pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpCode {
+  pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+  pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponse {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub token_type: Option<&'static str>,
+  pub expires_at: DateTime<Utc>,
+  /// Only in cookie mode, to send back in `X-CSRF-Token`
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub csrf_token: Option<String>,
+}
+
+/// Answer of a login whose password is right but which still needs the second factor
+#[derive(Serialize)]
+pub struct MfaChallenge {
+  pub mfa_required: bool,
+  pub mfa_token: String,
+}
+
+/// Second step of a login, with either a code of the authenticator or a recovery code
+#[derive(Deserialize)]
+pub struct MfaLogin {
+  pub mfa_token: String,
+  pub code: Option<String>,
+  pub recovery_code: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Session {
   pub id: Option<i32>,
   pub exp: DateTime<Utc>,
   pub nbf: DateTime<Utc>,
+  /// Token that cookie-authenticated requests have to send along, see `routes::auth::check_csrf`
+  #[serde(default)]
+  pub csrf: Option<String>,
+  /// Admin impersonating the account, see `routes::admin::impersonate`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub act: Option<i32>,
+  /// Set when the request came with an API key rather than a login token
+  #[serde(skip)]
+  pub api_key: Option<KeyGrant>,
+}
+
+impl Session {
+  /// Login sessions may do anything, API keys only what their scopes grant
+  pub fn allows(&self, scope: Scope) -> bool {
+    self.api_key.as_ref().is_none_or(|k| k.scopes.contains(&scope))
+  }
+}
+
+// Accounts and sessions end up in logs, keep credentials and claims out of them
+impl fmt::Debug for Account {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Account")
+      .field("id", &self.id)
+      .field("email", &mask_email(&self.email))
+      .field("password", &"***")
+      .finish()
+  }
+}
+
+impl fmt::Debug for Session {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Session")
+      .field("id", &self.id)
+      .field("exp", &self.exp)
+      .field("act", &self.act)
+      .field("api_key", &self.api_key.as_ref().map(|k| k.id))
+      .finish_non_exhaustive()
+  }
 }
@@ -0,0 +1,63 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::account::Role;
+
+/// Filters of `GET /admin/accounts`, every field is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct AccountQuery {
+  /// Part of the email, case insensitive
+  pub email: Option<String>,
+  pub role: Option<Role>,
+  pub suspended: Option<bool>,
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+  pub id: i32,
+  pub email: String,
+  pub display_name: Option<String>,
+  pub role: Role,
+  pub email_verified: bool,
+  pub suspended_at: Option<DateTime<Utc>>,
+  pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Activity {
+  pub questions: i64,
+  pub answers: i64,
+  pub active_api_keys: i64,
+  pub logins: i64,
+  pub failed_logins: i64,
+  pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// The account as admins see it on `GET /admin/accounts/{id}`
+#[derive(Debug, Serialize)]
+pub struct AdminAccount {
+  #[serde(flatten)]
+  pub summary: AccountSummary,
+  pub totp_enabled: bool,
+  pub suspended_reason: Option<String>,
+  pub password_reset_required: bool,
+  pub activity: Activity,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Suspension {
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleChange {
+  pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Impersonation {
+  /// Why support needs to act as the user, kept in the audit log
+  pub reason: String,
+}
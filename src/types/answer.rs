@@ -1,19 +1,41 @@
-use std::{io::Error, str::FromStr};
-use super::question::QuestionId;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
-pub struct AnswerId(String);
+use super::{comment::Comment, moderation::QuestionState, question::QuestionId};
 
-impl FromStr for AnswerId {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(AnswerId(s.to_string()))
-    }
-}
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize)]
+pub struct AnswerId(pub i32);
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Answer {
     pub id: AnswerId,
     pub qid: QuestionId,
     pub content: String,
+    pub account_id: Option<i32>,
+    pub score: i32,
+    pub created_at: NaiveDateTime,
+    /// Bumped by every edit, sent as `ETag`
+    pub version: i32,
+}
+
+/// Body of `PATCH /a/{id}`
+#[derive(Debug, Deserialize)]
+pub struct AnswerPatch {
+    pub content: Option<String>,
+}
+
+/// Answer in the question detail
+#[derive(Debug, Serialize)]
+pub struct AnswerDetail {
+    #[serde(flatten)]
+    pub answer: Answer,
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Debug)]
+pub enum AddAnswerOutcome {
+    Added,
+    QuestionNotFound,
+    /// The question takes no answers in this state, or none from this account when it is protected
+    Refused(QuestionState),
 }
@@ -0,0 +1,90 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Every key starts with this, it tells keys and session tokens apart
+pub const KEY_PREFIX: &str = "qak_";
+
+/// What an API key may do, sessions from `login` may do everything
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+  #[serde(rename = "questions:write")]
+  QuestionsWrite,
+  #[serde(rename = "answers:write")]
+  AnswersWrite,
+  #[serde(rename = "votes:write")]
+  VotesWrite,
+  #[serde(rename = "flags:write")]
+  FlagsWrite,
+  #[serde(rename = "comments:write")]
+  CommentsWrite,
+  #[serde(rename = "admin")]
+  Admin,
+}
+
+impl Scope {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Scope::QuestionsWrite => "questions:write",
+      Scope::AnswersWrite => "answers:write",
+      Scope::VotesWrite => "votes:write",
+      Scope::FlagsWrite => "flags:write",
+      Scope::CommentsWrite => "comments:write",
+      Scope::Admin => "admin",
+    }
+  }
+}
+
+impl FromStr for Scope {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "questions:write" => Ok(Scope::QuestionsWrite),
+      "answers:write" => Ok(Scope::AnswersWrite),
+      "votes:write" => Ok(Scope::VotesWrite),
+      "flags:write" => Ok(Scope::FlagsWrite),
+      "comments:write" => Ok(Scope::CommentsWrite),
+      "admin" => Ok(Scope::Admin),
+      _ => Err(format!("Unknown scope {}", s)),
+    }
+  }
+}
+
+impl fmt::Display for Scope {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Key a request was authenticated with
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyGrant {
+  pub id: i32,
+  pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKey {
+  pub id: i32,
+  pub name: String,
+  pub prefix: String,
+  pub scopes: Vec<Scope>,
+  pub created_at: DateTime<Utc>,
+  pub last_used_at: Option<DateTime<Utc>>,
+  pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewApiKey {
+  pub name: String,
+  pub scopes: Vec<String>,
+}
+
+/// Answer of the key creation, the only time the key itself is shown
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+  #[serde(flatten)]
+  pub info: ApiKey,
+  pub key: String,
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const LOGIN: &str = "login";
+pub const VERIFY_EMAIL: &str = "verify_email";
+pub const PASSWORD_RESET: &str = "password_reset";
+pub const MFA: &str = "mfa";
+pub const API_KEY: &str = "api_key";
+pub const ACCOUNT: &str = "account";
+pub const DATA_EXPORT: &str = "data_export";
+pub const ERASURE: &str = "erasure";
+pub const ADMIN: &str = "admin";
+pub const IMPERSONATE: &str = "impersonate";
+/// Writes made with an impersonation token, `actor_id` is the admin behind them
+pub const IMPERSONATED_REQUEST: &str = "impersonated_request";
+
+/// Details of logins refused before the credential is checked, these are no failures of their own
+pub const LOCKED: &str = "locked";
+pub const IP_LOCKED: &str = "ip locked";
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+  pub id: i32,
+  pub action: String,
+  pub account_id: Option<i32>,
+  pub actor_id: Option<i32>,
+  pub email: Option<String>,
+  pub ip: Option<String>,
+  pub success: bool,
+  pub detail: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct NewAuditEntry {
+  pub action: &'static str,
+  pub account_id: Option<i32>,
+  /// Admin acting on `account_id`
+  pub actor_id: Option<i32>,
+  pub email: Option<String>,
+  pub ip: Option<String>,
+  pub success: bool,
+  pub detail: Option<String>,
+}
+
+/// Filters of `GET /admin/audit`, every field is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+  pub action: Option<String>,
+  pub account_id: Option<i32>,
+  pub actor_id: Option<i32>,
+  pub email: Option<String>,
+  pub ip: Option<String>,
+  pub success: Option<bool>,
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct Comment {
+  pub id: i32,
+  pub account_id: Option<i32>,
+  pub question_id: Option<i32>,
+  pub answer_id: Option<i32>,
+  pub content: String,
+  pub created_at: DateTime<Utc>,
+  /// Set once the author edits the comment
+  pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Body of `POST /q/{id}/comments`, `POST /a/{id}/comments` and `PUT /comments/{id}`
+#[derive(Debug, Deserialize)]
+pub struct CommentPayload {
+  pub content: String,
+}
@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::types::{
+  account::Profile, answer::Answer, api_key::ApiKey, audit::AuditEntry, comment::Comment, flag::Flag,
+  question::Question, reputation::ReputationEvent, revision::Revision, vote::Vote,
+};
+
+/// Everything stored about an account, as handed out on a data subject access request
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+  pub exported_at: DateTime<Utc>,
+  pub account: Profile,
+  pub questions: Vec<Question>,
+  pub answers: Vec<Answer>,
+  /// Edits the account made, also of questions of others
+  pub revisions: Vec<Revision>,
+  pub comments: Vec<Comment>,
+  pub votes: Vec<Vote>,
+  pub reputation: Vec<ReputationEvent>,
+  pub flags: Vec<Flag>,
+  pub api_keys: Vec<ApiKey>,
+  pub audit_log: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+  #[default]
+  Zip,
+  Json,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportQuery {
+  #[serde(default)]
+  pub format: ExportFormat,
+}
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /q/{id}/flag` and `POST /a/{id}/flag`
+#[derive(Debug, Deserialize)]
+pub struct NewFlag {
+  pub reason: String,
+}
+
+/// A post reported to the admins
+#[derive(Debug, Serialize)]
+pub struct Flag {
+  pub id: i32,
+  pub account_id: i32,
+  pub question_id: Option<i32>,
+  pub answer_id: Option<i32>,
+  pub reason: String,
+  pub created_at: DateTime<Utc>,
+}
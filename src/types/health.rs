@@ -0,0 +1,70 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+  Up,
+  Degraded,
+  Down,
+}
+
+/// Result of probing a single dependency from `/readyz`.
+#[derive(Debug, Serialize)]
+pub struct DependencyCheck {
+  pub status: Status,
+  /// Critical dependencies take the whole service out of rotation when down
+  pub critical: bool,
+  pub latency_ms: u128,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+  pub status: Status,
+  pub checks: BTreeMap<&'static str, DependencyCheck>,
+}
+
+impl Readiness {
+  pub fn new(checks: BTreeMap<&'static str, DependencyCheck>) -> Self {
+    let status = checks
+      .values()
+      .filter(|c| c.status != Status::Up)
+      .fold(Status::Up, |acc, c| match (acc, c.critical) {
+        (Status::Down, _) | (_, true) => Status::Down,
+        _ => Status::Degraded,
+      });
+    Readiness { status, checks }
+  }
+}
+
+#[cfg(test)]
+mod health_tests {
+  use std::collections::BTreeMap;
+
+  use super::{DependencyCheck, Readiness, Status};
+
+  fn check(status: Status, critical: bool) -> DependencyCheck {
+    DependencyCheck { status, critical, latency_ms: 1, detail: None }
+  }
+
+  #[test]
+  fn test_readiness_status() {
+    let r = Readiness::new(BTreeMap::from([("db", check(Status::Up, true))]));
+    assert_eq!(r.status, Status::Up);
+
+    let r = Readiness::new(BTreeMap::from([
+      ("db", check(Status::Up, true)),
+      ("profanity", check(Status::Down, false)),
+    ]));
+    assert_eq!(r.status, Status::Degraded);
+
+    let r = Readiness::new(BTreeMap::from([
+      ("db", check(Status::Down, true)),
+      ("profanity", check(Status::Down, false)),
+    ]));
+    assert_eq!(r.status, Status::Down);
+  }
+}
@@ -1,4 +1,17 @@
+pub mod admin;
 pub mod answer;
+pub mod api_key;
+pub mod audit;
+pub mod comment;
+pub mod export;
+pub mod flag;
+pub mod health;
+pub mod moderation;
 pub mod paging;
+pub mod post;
 pub mod question;
+pub mod reputation;
+pub mod revision;
+pub mod tag;
+pub mod vote;
 pub mod account;
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Where a question is in its lifecycle, set by admins
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionState {
+  #[default]
+  Open,
+  /// No new answers, the question may still be edited and reopened
+  Closed,
+  /// No new answers and no edits
+  Locked,
+  /// Only accounts with the `AnswerProtected` privilege may answer
+  Protected,
+}
+
+impl QuestionState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      QuestionState::Open => "open",
+      QuestionState::Closed => "closed",
+      QuestionState::Locked => "locked",
+      QuestionState::Protected => "protected",
+    }
+  }
+}
+
+impl FromStr for QuestionState {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "open" => Ok(QuestionState::Open),
+      "closed" => Ok(QuestionState::Closed),
+      "locked" => Ok(QuestionState::Locked),
+      "protected" => Ok(QuestionState::Protected),
+      _ => Err(format!("Unknown question state {}", s)),
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+  /// Set through `PUT /admin/q/{id}/duplicate`, which also names the canonical question
+  Duplicate,
+  OffTopic,
+  Unclear,
+  TooBroad,
+  OpinionBased,
+}
+
+impl CloseReason {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CloseReason::Duplicate => "duplicate",
+      CloseReason::OffTopic => "off_topic",
+      CloseReason::Unclear => "unclear",
+      CloseReason::TooBroad => "too_broad",
+      CloseReason::OpinionBased => "opinion_based",
+    }
+  }
+}
+
+impl FromStr for CloseReason {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "duplicate" => Ok(CloseReason::Duplicate),
+      "off_topic" => Ok(CloseReason::OffTopic),
+      "unclear" => Ok(CloseReason::Unclear),
+      "too_broad" => Ok(CloseReason::TooBroad),
+      "opinion_based" => Ok(CloseReason::OpinionBased),
+      _ => Err(format!("Unknown close reason {}", s)),
+    }
+  }
+}
+
+/// Body of `PUT /admin/q/{id}/state`, closing needs a `reason` and nothing else takes one
+#[derive(Debug, Deserialize)]
+pub struct StateChange {
+  pub state: QuestionState,
+  pub reason: Option<CloseReason>,
+}
+
+impl StateChange {
+  /// Why `reason` does not go with `state`, if it does not
+  pub fn reason_error(&self) -> Option<&'static str> {
+    match (self.state, self.reason) {
+      (QuestionState::Closed, None) => Some("is needed to close a question"),
+      (QuestionState::Closed, Some(CloseReason::Duplicate)) => Some("duplicate needs the question, see /admin/q/{id}/duplicate"),
+      (QuestionState::Closed, Some(_)) | (_, None) => None,
+      (_, Some(_)) => Some("is only taken when closing a question"),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum StateOutcome<T> {
+  Changed(T),
+  NotFound,
+  /// The question is in that state already
+  Unchanged,
+}
+
+/// A state change of a question, oldest entries come last
+#[derive(Debug, Serialize)]
+pub struct ModerationEntry {
+  pub id: i32,
+  pub question_id: i32,
+  pub actor_id: Option<i32>,
+  pub from_state: String,
+  pub to_state: String,
+  pub reason: Option<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Filters of `GET /admin/moderation`
+#[derive(Debug, Default, Deserialize)]
+pub struct ModerationQuery {
+  pub question_id: Option<i32>,
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+#[cfg(test)]
+mod moderation_tests {
+  use super::{CloseReason, QuestionState, StateChange};
+
+  #[test]
+  fn test_round_trip() {
+    for state in [QuestionState::Open, QuestionState::Closed, QuestionState::Locked, QuestionState::Protected] {
+      assert_eq!(state.as_str().parse::<QuestionState>(), Ok(state));
+    }
+    for reason in [
+      CloseReason::Duplicate,
+      CloseReason::OffTopic,
+      CloseReason::Unclear,
+      CloseReason::TooBroad,
+      CloseReason::OpinionBased,
+    ] {
+      assert_eq!(reason.as_str().parse::<CloseReason>(), Ok(reason));
+    }
+    assert!("archived".parse::<QuestionState>().is_err());
+  }
+
+  #[test]
+  fn test_reason_error() {
+    let error = |state, reason| StateChange { state, reason }.reason_error();
+    assert_eq!(error(QuestionState::Closed, Some(CloseReason::OffTopic)), None);
+    assert_eq!(error(QuestionState::Closed, None), Some("is needed to close a question"));
+    assert!(error(QuestionState::Closed, Some(CloseReason::Duplicate)).is_some());
+    for state in [QuestionState::Open, QuestionState::Locked, QuestionState::Protected] {
+      assert_eq!(error(state, None), None);
+      assert_eq!(error(state, Some(CloseReason::Unclear)), Some("is only taken when closing a question"));
+    }
+  }
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 
 use error_handler::AppError;
+use serde::Deserialize;
 
 /// Paging struct used for pagination.
 /// ## Example:
-/// `?start=1&end=10`
+/// `?limit=10&offset=20`
 #[derive(Debug, Default)]
 pub struct Pagination {
     pub limit: Option<i32>,
@@ -22,7 +23,7 @@ pub fn extract_paging(params: HashMap<String, String>) -> Result<Pagination, App
                     .map_err(AppError::ParseError)?,
             ),
             offset: params
-                .get("end")
+                .get("offset")
                 .unwrap()
                 .parse::<i32>()
                 .map_err(AppError::ParseError)?,
@@ -31,3 +32,50 @@ pub fn extract_paging(params: HashMap<String, String>) -> Result<Pagination, App
     }
     Err(AppError::MissingParams)
 }
+
+/// `?limit=&offset=` of the listings added after `extract_paging`, both optional
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(50).clamp(1, 500)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+}
+
+#[cfg(test)]
+mod paging_tests {
+    use std::collections::HashMap;
+
+    use super::{extract_paging, PageQuery};
+
+    #[test]
+    fn test_extract_paging() {
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        let p = extract_paging(params(&[("limit", "10"), ("offset", "20")])).unwrap();
+        assert_eq!((p.limit, p.offset), (Some(10), 20));
+        assert!(extract_paging(params(&[("limit", "10")])).is_err());
+        assert!(extract_paging(params(&[("limit", "10"), ("offset", "x")])).is_err());
+    }
+
+    #[test]
+    fn test_page_query() {
+        let page = |limit, offset| PageQuery { limit, offset };
+
+        assert_eq!((page(None, None).limit(), page(None, None).offset()), (50, 0));
+        assert_eq!((page(Some(10), Some(20)).limit(), page(Some(10), Some(20)).offset()), (10, 20));
+        assert_eq!(page(Some(0), None).limit(), 1);
+        assert_eq!(page(Some(10_000), None).limit(), 500);
+        assert_eq!(page(None, Some(-5)).offset(), 0);
+    }
+}
@@ -0,0 +1,108 @@
+use error_handler::AppError;
+
+use crate::validation::field_errors;
+
+/// See `PostTarget::visible`
+pub const VISIBLE_ANSWER: &str = "deleted_at IS NULL
+  AND NOT EXISTS (SELECT 1 FROM questions dq WHERE dq.id = corresponding_question AND dq.deleted_at IS NOT NULL)";
+
+/// A question or answer, what votes and flags are attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostTarget {
+  Question(i32),
+  Answer(i32),
+}
+
+impl PostTarget {
+  pub fn id(&self) -> i32 {
+    match self {
+      PostTarget::Question(id) | PostTarget::Answer(id) => *id,
+    }
+  }
+
+  pub fn table(&self) -> &'static str {
+    match self {
+      PostTarget::Question(_) => "questions",
+      PostTarget::Answer(_) => "answers",
+    }
+  }
+
+  pub fn not_found(&self) -> AppError {
+    match self {
+      PostTarget::Question(_) => AppError::QuestionNotFound,
+      PostTarget::Answer(_) => AppError::AnswerNotFound,
+    }
+  }
+
+  /// Condition on the row of the target table that leaves out soft-deleted posts,
+  /// answers are gone as well once their question is deleted
+  pub fn visible(&self) -> &'static str {
+    match self {
+      PostTarget::Question(_) => "deleted_at IS NULL",
+      PostTarget::Answer(_) => VISIBLE_ANSWER,
+    }
+  }
+
+  /// Column pointing at the target in the tables attached to posts
+  pub fn column(&self) -> &'static str {
+    match self {
+      PostTarget::Question(_) => "question_id",
+      PostTarget::Answer(_) => "answer_id",
+    }
+  }
+}
+
+/// Outcome of a write that may be conditional on the version of the post
+#[derive(Debug)]
+pub enum UpdateOutcome<T> {
+  Updated(T),
+  NotFound,
+  Stale { current: i32 },
+  /// The question, or the question of the answer, is locked
+  Locked,
+}
+
+/// `ETag` of a post at `version`
+pub fn etag(version: i32) -> String {
+  format!("\"{}\"", version)
+}
+
+/// Version an `If-Match` header names, `None` without header or for `*`.
+/// Weak tags count the same as versions are only ever compared as a whole.
+pub fn if_match(header: Option<&str>) -> Result<Option<i32>, AppError> {
+  let value = match header.map(str::trim) {
+    None | Some("*") => return Ok(None),
+    Some(value) => value,
+  };
+  value
+    .trim_start_matches("W/")
+    .strip_prefix('"')
+    .and_then(|v| v.strip_suffix('"'))
+    .and_then(|v| v.parse().ok())
+    .map(Some)
+    .ok_or_else(|| AppError::Validation(field_errors("If-Match", ["must be a single ETag or *"])))
+}
+
+#[cfg(test)]
+mod post_tests {
+  use super::{etag, if_match, PostTarget, VISIBLE_ANSWER};
+
+  #[test]
+  fn test_if_match() {
+    assert_eq!(if_match(None).unwrap(), None);
+    assert_eq!(if_match(Some("*")).unwrap(), None);
+    assert_eq!(if_match(Some(&etag(3))).unwrap(), Some(3));
+    assert_eq!(if_match(Some(" W/\"12\" ")).unwrap(), Some(12));
+    assert!(if_match(Some("3")).is_err());
+    assert!(if_match(Some("\"1\", \"2\"")).is_err());
+  }
+
+  #[test]
+  fn test_visible() {
+    assert_eq!(PostTarget::Question(1).visible(), "deleted_at IS NULL");
+    assert_eq!(PostTarget::Answer(1).visible(), VISIBLE_ANSWER);
+    // answers of a deleted question are hidden with it
+    assert!(VISIBLE_ANSWER.starts_with("deleted_at IS NULL"));
+    assert!(VISIBLE_ANSWER.contains("dq.id = corresponding_question AND dq.deleted_at IS NOT NULL"));
+  }
+}
@@ -1,6 +1,12 @@
 use std::{str::FromStr, io::Error};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{
+    answer::AnswerDetail,
+    comment::Comment,
+    moderation::{CloseReason, QuestionState},
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Question {
@@ -8,6 +14,88 @@ pub struct Question {
     pub title: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub account_id: Option<i32>,
+    /// Sum of the votes, see `types::vote`
+    #[serde(default)]
+    pub score: i32,
+    /// Answer the author marked as solving the question
+    #[serde(default)]
+    pub accepted_answer_id: Option<i32>,
+    /// Bumped by every edit, sent as `ETag`
+    #[serde(default)]
+    pub version: i32,
+    /// Question this one was closed as a duplicate of
+    #[serde(default)]
+    pub duplicate_of: Option<i32>,
+    #[serde(default)]
+    pub state: QuestionState,
+    /// Set while the question is closed
+    #[serde(default)]
+    pub close_reason: Option<CloseReason>,
+}
+
+/// Response of `POST /q`
+#[derive(Debug, Serialize)]
+pub struct NewQuestion {
+    #[serde(flatten)]
+    pub question: Question,
+    pub possible_duplicates: Vec<DuplicateCandidate>,
+}
+
+/// Earlier question that looks like the same question, see `duplicates::DuplicateConfig`
+#[derive(Debug, Serialize)]
+pub struct DuplicateCandidate {
+    pub id: i32,
+    pub title: String,
+    pub tags: Option<Vec<String>>,
+    /// Title similarity plus the bonus for shared tags
+    pub score: f32,
+}
+
+/// Body of `PUT /admin/q/{id}/duplicate`
+#[derive(Debug, Deserialize)]
+pub struct MarkDuplicate {
+    pub duplicate_of: i32,
+}
+
+#[derive(Debug)]
+pub enum DuplicateOutcome {
+    Marked(Question),
+    NotFound,
+    CanonicalNotFound,
+    /// The question would end up a duplicate of itself
+    SameQuestion,
+}
+
+/// `GET /q/{id}`, the accepted answer comes first
+#[derive(Debug, Serialize)]
+pub struct QuestionDetail {
+    #[serde(flatten)]
+    pub question: Question,
+    pub comments: Vec<Comment>,
+    pub answers: Vec<AnswerDetail>,
+}
+
+/// Body of `PUT /q/{id}/tags`
+#[derive(Debug, Deserialize)]
+pub struct Retag {
+    pub tags: Vec<String>,
+}
+
+/// Body of `PUT /q/{id}/accept`
+#[derive(Debug, Deserialize)]
+pub struct AcceptAnswer {
+    pub answer_id: i32,
+}
+
+#[derive(Debug)]
+pub enum AcceptOutcome {
+    Accepted(Question),
+    NotFound,
+    NotAuthor,
+    /// The answer does not exist or belongs to another question
+    ForeignAnswer,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,9 +105,95 @@ pub struct QuestionPayload {
     pub tags: Option<Vec<String>>,
 }
 
+/// Body of `PATCH /q/{id}`, a JSON merge patch: absent fields are kept and `"tags": null` drops the tags
+#[derive(Debug, Default, Deserialize)]
+pub struct QuestionPatch {
+    pub title: Option<String>,
+    pub content: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub tags: Option<Option<Vec<String>>>,
+}
+
+impl From<QuestionPayload> for QuestionPatch {
+    fn from(q: QuestionPayload) -> Self {
+        QuestionPatch {
+            title: Some(q.title),
+            content: Some(q.content),
+            tags: Some(q.tags),
+        }
+    }
+}
+
+/// Tells an explicit `null` apart from an absent field
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
 #[derive(Debug, Serialize, Clone, Eq, Hash, PartialEq, Deserialize)]
 pub struct QuestionId(pub u32);
 
+/// `?sort=` of the question list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuestionSort {
+    #[default]
+    Oldest,
+    Newest,
+    Score,
+}
+
+impl QuestionSort {
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            QuestionSort::Oldest => "id",
+            QuestionSort::Newest => "id DESC",
+            QuestionSort::Score => "score DESC, id DESC",
+        }
+    }
+}
+
+/// `?filter=` of the question list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuestionFilter {
+    Answered,
+    Unanswered,
+}
+
+impl QuestionFilter {
+    pub fn condition(&self) -> &'static str {
+        match self {
+            QuestionFilter::Answered => "accepted_answer_id IS NOT NULL",
+            QuestionFilter::Unanswered => "accepted_answer_id IS NULL",
+        }
+    }
+}
+
+impl FromStr for QuestionFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "answered" => Ok(QuestionFilter::Answered),
+            "unanswered" => Ok(QuestionFilter::Unanswered),
+            _ => Err(format!("Unknown filter {}", s)),
+        }
+    }
+}
+
+impl FromStr for QuestionSort {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(QuestionSort::Oldest),
+            "newest" => Ok(QuestionSort::Newest),
+            "score" => Ok(QuestionSort::Score),
+            _ => Err(format!("Unknown sort {}", s)),
+        }
+    }
+}
+
 impl FromStr for QuestionId {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub const UPVOTED: &str = "upvoted";
+pub const DOWNVOTED: &str = "downvoted";
+pub const ACCEPTED: &str = "accepted";
+
+/// Entry of the reputation ledger of an account
+#[derive(Debug, Serialize)]
+pub struct ReputationEvent {
+  pub id: i32,
+  pub kind: String,
+  pub points: i32,
+  pub question_id: Option<i32>,
+  pub answer_id: Option<i32>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// Actions which need some reputation, admins may always take them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+  /// Retag questions of other accounts
+  EditTags,
+  Flag,
+  /// Answer questions in the `Protected` state
+  AnswerProtected,
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+pub const CREATE: &str = "create";
+pub const EDIT: &str = "edit";
+pub const RETAG: &str = "retag";
+pub const ROLLBACK: &str = "rollback";
+
+/// A question as it was after a change, revisions are numbered from 1 per question
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+  pub question_id: i32,
+  pub revision: i32,
+  /// Who made the change
+  pub account_id: Option<i32>,
+  pub action: String,
+  pub rolled_back_to: Option<i32>,
+  pub title: String,
+  pub content: String,
+  pub tags: Option<Vec<String>>,
+  pub created_at: DateTime<Utc>,
+}
+
+/// `?from=&to=` of `GET /q/{id}/revisions/diff`, by default the latest revision against the one before
+#[derive(Debug, Default, Deserialize)]
+pub struct DiffQuery {
+  pub from: Option<i32>,
+  pub to: Option<i32>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct RevisionDiff {
+  pub from: i32,
+  pub to: i32,
+  /// Unified diffs, empty when unchanged
+  pub title: String,
+  pub content: String,
+  pub tags_added: Vec<String>,
+  pub tags_removed: Vec<String>,
+}
+
+impl RevisionDiff {
+  pub fn between(from: &Revision, to: &Revision) -> Self {
+    let unified = |old: &str, new: &str| {
+      TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&format!("revision {}", from.revision), &format!("revision {}", to.revision))
+        .to_string()
+    };
+    let old_tags = from.tags.clone().unwrap_or_default();
+    let new_tags = to.tags.clone().unwrap_or_default();
+
+    RevisionDiff {
+      from: from.revision,
+      to: to.revision,
+      title: unified(&from.title, &to.title),
+      content: unified(&from.content, &to.content),
+      tags_added: new_tags.iter().filter(|t| !old_tags.contains(t)).cloned().collect(),
+      tags_removed: old_tags.iter().filter(|t| !new_tags.contains(t)).cloned().collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod revision_tests {
+  use chrono::Utc;
+
+  use super::{Revision, RevisionDiff};
+
+  fn revision(revision: i32, content: &str, tags: &[&str]) -> Revision {
+    Revision {
+      question_id: 1,
+      revision,
+      account_id: Some(1),
+      action: "edit".to_string(),
+      rolled_back_to: None,
+      title: "How to use warp".to_string(),
+      content: content.to_string(),
+      tags: Some(tags.iter().map(|t| t.to_string()).collect()),
+      created_at: Utc::now(),
+    }
+  }
+
+  #[test]
+  fn test_diff() {
+    let diff = RevisionDiff::between(
+      &revision(1, "first line\nsecond line\n", &["rust", "warp"]),
+      &revision(3, "first line\nchanged line\n", &["rust", "tokio"]),
+    );
+
+    assert_eq!(diff.from, 1);
+    assert_eq!(diff.to, 3);
+    assert_eq!(diff.title, "");
+    assert_eq!(
+      diff.content,
+      "--- revision 1\n+++ revision 3\n@@ -1,2 +1,2 @@\n first line\n-second line\n+changed line\n"
+    );
+    assert_eq!(diff.tags_added, vec!["tokio"]);
+    assert_eq!(diff.tags_removed, vec!["warp"]);
+  }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct Tag {
+  pub slug: String,
+  pub description: Option<String>,
+  /// Number of questions tagged with it
+  pub usage_count: i32,
+  pub synonyms: Vec<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagSort {
+  #[default]
+  Popular,
+  Name,
+}
+
+impl TagSort {
+  pub fn order_by(&self) -> &'static str {
+    match self {
+      TagSort::Popular => "t.usage_count DESC, t.slug",
+      TagSort::Name => "t.slug",
+    }
+  }
+}
+
+/// `GET /tags`, every field is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct TagQuery {
+  /// Start of the slug or of one of its synonyms
+  pub q: Option<String>,
+  #[serde(default)]
+  pub sort: TagSort,
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+/// Body of `PUT /tags/{slug}`
+#[derive(Debug, Deserialize)]
+pub struct TagUpdate {
+  pub description: Option<String>,
+}
+
+/// Body of `POST /admin/tags/{slug}/synonyms`, slugified before use
+#[derive(Debug, Deserialize)]
+pub struct NewSynonym {
+  pub synonym: String,
+}
+
+#[derive(Debug)]
+pub enum SynonymOutcome {
+  Added(Tag),
+  TagNotFound,
+}
+
+/// Lowercase ASCII letters, digits, `+`, `.` and single dashes, so that "Rust Lang" and
+/// "rust_lang" become "rust-lang". `#` is spelled out to keep the slug usable in paths.
+pub fn slugify(raw: &str) -> String {
+  let mut slug = String::new();
+  for c in raw.trim().to_lowercase().chars() {
+    match c {
+      'a'..='z' | '0'..='9' | '+' | '.' => slug.push(c),
+      '#' => slug.push_str("sharp"),
+      c if (c.is_whitespace() || c == '_' || c == '-') && !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+      _ => {}
+    }
+  }
+  slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tag_tests {
+  use super::slugify;
+
+  #[test]
+  fn test_slugify() {
+    assert_eq!(slugify(" Rust "), "rust");
+    assert_eq!(slugify("Rust Lang"), "rust-lang");
+    assert_eq!(slugify("rust_lang"), "rust-lang");
+    assert_eq!(slugify("C#"), "csharp");
+    assert_eq!(slugify("c++"), "c++");
+    assert_eq!(slugify("--a--b--"), "a-b");
+    assert_eq!(slugify("Café !"), "caf");
+    assert_eq!(slugify("!!!"), "");
+  }
+}
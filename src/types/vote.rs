@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Body of `PUT /q/{id}/vote` and `PUT /a/{id}/vote`, 1 for up and -1 for down
+#[derive(Debug, Deserialize)]
+pub struct NewVote {
+  pub value: i16,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct VoteResult {
+  pub score: i32,
+  /// Vote of the caller after the change, `None` once withdrawn
+  pub vote: Option<i16>,
+}
+
+/// A vote as listed in the data export of its account
+#[derive(Debug, Serialize)]
+pub struct Vote {
+  pub question_id: Option<i32>,
+  pub answer_id: Option<i32>,
+  pub value: i16,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VoteOutcome {
+  Voted(VoteResult),
+  NotFound,
+  OwnPost,
+}
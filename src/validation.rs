@@ -0,0 +1,237 @@
+use std::{collections::HashSet, fs, io, sync::Arc};
+
+use error_handler::FieldError;
+use serde::Deserialize;
+
+use crate::types::account::ProfileUpdate;
+
+/// Trims and case-folds an email so that lookups do not depend on how it was typed
+pub fn normalize_email(raw: &str) -> String {
+  raw.trim().to_lowercase()
+}
+
+/// Pragmatic syntax check of a normalized email, `local@domain.tld`
+pub fn validate_email(email: &str) -> Result<(), &'static str> {
+  if email.is_empty() {
+    return Err("is required");
+  }
+  if email.len() > 254 {
+    return Err("is too long");
+  }
+
+  let (local, domain) = email.rsplit_once('@').ok_or("must contain @")?;
+  if local.is_empty() || local.len() > 64 {
+    return Err("has an invalid local part");
+  }
+  if local.contains(|c: char| c.is_whitespace() || c.is_control() || c == '@')
+    || local.starts_with('.')
+    || local.ends_with('.')
+    || local.contains("..")
+  {
+    return Err("has an invalid local part");
+  }
+
+  let labels: Vec<&str> = domain.split('.').collect();
+  if labels.len() < 2 {
+    return Err("must have a domain like example.com");
+  }
+  let valid_label = |l: &&str| {
+    !l.is_empty()
+      && l.len() <= 63
+      && !l.starts_with('-')
+      && !l.ends_with('-')
+      && l.chars().all(|c| c.is_alphanumeric() || c == '-')
+  };
+  if !labels.iter().all(valid_label) || labels.last().is_none_or(|tld| tld.len() < 2) {
+    return Err("has an invalid domain");
+  }
+  Ok(())
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PasswordPolicy {
+  pub min_length: usize,
+  pub max_length: usize,
+  /// File with one known breached password per line, compared case-insensitively
+  pub breached_list: Option<String>,
+  #[serde(skip)]
+  breached: Arc<HashSet<String>>,
+}
+
+impl Default for PasswordPolicy {
+  fn default() -> Self {
+    PasswordPolicy {
+      min_length: 10,
+      max_length: 128,
+      breached_list: None,
+      breached: Arc::default(),
+    }
+  }
+}
+
+impl PasswordPolicy {
+  /// Reads the breached password list into memory
+  pub fn load(mut self) -> io::Result<Self> {
+    if let Some(path) = &self.breached_list {
+      let list = fs::read_to_string(path)?
+        .lines()
+        .map(|l| l.trim().to_lowercase())
+        .filter(|l| !l.is_empty())
+        .collect();
+      self.breached = Arc::new(list);
+    }
+    Ok(self)
+  }
+
+  /// Every rule `password` breaks, empty when it is acceptable
+  pub fn check(&self, password: &str, email: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let len = password.chars().count();
+
+    if len < self.min_length {
+      errors.push(format!("must be at least {} characters", self.min_length));
+    }
+    if len > self.max_length {
+      errors.push(format!("must be at most {} characters", self.max_length));
+    }
+
+    let lower = password.to_lowercase();
+    if !email.is_empty() && (lower == email || email.split('@').next() == Some(lower.as_str())) {
+      errors.push("must not be the email".to_string());
+    }
+    if self.breached.contains(&lower) {
+      errors.push("appears in a list of breached passwords".to_string());
+    }
+    errors
+  }
+}
+
+/// Field errors of a profile update, empty values clear a field and are always fine
+pub fn validate_profile(p: &ProfileUpdate) -> Vec<FieldError> {
+  let mut errors = vec![];
+  let too_long = |value: &Option<String>, max: usize| value.as_ref().is_some_and(|v| v.chars().count() > max);
+
+  if too_long(&p.display_name, 64) {
+    errors.extend(field_errors("display_name", ["must be at most 64 characters"]));
+  }
+  if p.display_name.as_ref().is_some_and(|n| n.chars().any(char::is_control)) {
+    errors.extend(field_errors("display_name", ["must not contain control characters"]));
+  }
+  if too_long(&p.bio, 2000) {
+    errors.extend(field_errors("bio", ["must be at most 2000 characters"]));
+  }
+  if let Some(url) = p.avatar_url.as_deref().filter(|u| !u.is_empty()) {
+    let valid = (url.starts_with("https://") || url.starts_with("http://"))
+      && url.len() <= 512
+      && !url.contains(|c: char| c.is_whitespace() || c.is_control() || c == '"' || c == '<' || c == '>');
+    if !valid {
+      errors.extend(field_errors("avatar_url", ["must be an http(s) url of at most 512 characters"]));
+    }
+  }
+  errors
+}
+
+/// Field errors of the text of a question or answer, fields a patch leaves out are `None` and always fine
+pub fn validate_post(title: Option<&str>, content: Option<&str>) -> Vec<FieldError> {
+  let mut errors = vec![];
+  if title.is_some_and(|t| t.trim().is_empty()) {
+    errors.extend(field_errors("title", ["must not be empty"]));
+  }
+  if content.is_some_and(|c| c.trim().is_empty()) {
+    errors.extend(field_errors("content", ["must not be empty"]));
+  }
+  errors
+}
+
+pub fn validate_tags(tags: &[String]) -> Vec<FieldError> {
+  let mut errors = vec![];
+  if tags.len() > 5 {
+    errors.push("must be at most 5 tags");
+  }
+  if tags.iter().any(|t| t.trim().is_empty() || t.chars().count() > 35) {
+    errors.push("must be between 1 and 35 characters each");
+  }
+  field_errors("tags", errors)
+}
+
+pub fn field_errors(field: &str, messages: impl IntoIterator<Item = impl Into<String>>) -> Vec<FieldError> {
+  messages
+    .into_iter()
+    .map(|m| FieldError {
+      field: field.to_string(),
+      message: m.into(),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod validation_tests {
+  use std::{collections::HashSet, sync::Arc};
+
+  use super::{normalize_email, validate_email, validate_post, validate_profile, validate_tags, PasswordPolicy};
+  use crate::types::account::ProfileUpdate;
+
+  #[test]
+  fn test_validate_post() {
+    assert!(validate_post(Some("Title"), Some("Content")).is_empty());
+    assert!(validate_post(None, None).is_empty());
+    let fields = |errors: Vec<super::FieldError>| errors.into_iter().map(|e| e.field).collect::<Vec<_>>();
+    assert_eq!(fields(validate_post(Some(" "), Some(""))), ["title", "content"]);
+    assert_eq!(fields(validate_post(None, Some("\n"))), ["content"]);
+  }
+
+  #[test]
+  fn test_email() {
+    assert_eq!(normalize_email("  Foo.Bar@Example.COM "), "foo.bar@example.com");
+    assert!(validate_email("foo.bar@example.com").is_ok());
+    assert!(validate_email("foo+tag@sub.example.io").is_ok());
+    assert!(validate_email("").is_err());
+    assert!(validate_email("foo@bar").is_err());
+    assert!(validate_email("foo").is_err());
+    assert!(validate_email("@example.com").is_err());
+    assert!(validate_email("fo o@example.com").is_err());
+    assert!(validate_email("foo@-example.com").is_err());
+    assert!(validate_email("foo..bar@example.com").is_err());
+  }
+
+  #[test]
+  fn test_password_policy() {
+    let policy = PasswordPolicy {
+      breached: Arc::new(HashSet::from(["password123".to_string()])),
+      ..Default::default()
+    };
+
+    assert!(policy.check("correct horse battery", "foo@example.com").is_empty());
+    assert_eq!(policy.check("short", "foo@example.com").len(), 1);
+    assert_eq!(policy.check("PassWord123", "foo@example.com").len(), 1);
+    assert_eq!(policy.check("foobarbaz1@example.com", "foobarbaz1@example.com").len(), 1);
+    assert_eq!(policy.check(&"x".repeat(200), "foo@example.com").len(), 1);
+  }
+
+  #[test]
+  fn test_profile() {
+    let update = |name: &str, url: &str| ProfileUpdate {
+      display_name: Some(name.to_string()),
+      bio: None,
+      avatar_url: Some(url.to_string()),
+    };
+
+    assert!(validate_profile(&update("Foo", "https://example.com/a.png")).is_empty());
+    assert!(validate_profile(&update("", "")).is_empty());
+    assert!(validate_profile(&ProfileUpdate::default()).is_empty());
+    assert_eq!(validate_profile(&update(&"x".repeat(65), "javascript:alert(1)")).len(), 2);
+    assert_eq!(validate_profile(&update("a\u{7}b", "https://example.com/a b")).len(), 2);
+  }
+
+  #[test]
+  fn test_tags() {
+    let tags = |s: &[&str]| s.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+    assert!(validate_tags(&[]).is_empty());
+    assert!(validate_tags(&tags(&["rust", "warp"])).is_empty());
+    assert_eq!(validate_tags(&tags(&["rust", " "])).len(), 1);
+    assert_eq!(validate_tags(&tags(&[&"x".repeat(36)])).len(), 1);
+    assert_eq!(validate_tags(&tags(&["a", "b", "c", "d", "e", ""])).len(), 2);
+  }
+}